@@ -0,0 +1,108 @@
+use crate::scenario::Setup;
+use crate::simulation::dispatch::Dispatch;
+use crate::simulation::hashgrid::{GpuOptions, HashGrid, MAX_INDICES};
+use eyre::{bail, Result};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use wgpu::{CommandEncoderDescriptor, Device, Maintain, Queue};
+
+/// time steps run before timing, so that pipeline creation and first-use costs of the driver
+/// are not measured
+const WARMUP_STEPS: u64 = 200;
+
+/// time steps recorded into one command encoder. Every update records two of them.
+const STEPS_PER_SUBMIT: u64 = 100;
+
+/// runs `steps` time steps of the dynamics of the setup's atoms, ignoring its stages and
+/// outputs, once with every [`Dispatch`] and otherwise the setup's gpu options. Prints the
+/// throughput of each and the speedup of per-atom over per-cell dispatch. Other changes to
+/// the simulation passes can be compared by running the same scenario before and after.
+///
+/// Per-cell dispatch skips the atoms of overflowing cells, so both only do the same work as
+/// long as no cell overflows. The cells are checked after every submission, outside of the
+/// timing, and the benchmark fails if one overflows.
+pub async fn run(device: &Arc<Device>, queue: &Queue, setup: &Setup, steps: u64) -> Result<()> {
+    let per_cell = measure(device, queue, setup, steps, Dispatch::Cells).await?;
+    let per_atom = measure(device, queue, setup, steps, Dispatch::Atoms).await?;
+    println!(
+        "per-atom dispatch is {:.2}x as fast as per-cell dispatch",
+        per_atom / per_cell
+    );
+    Ok(())
+}
+
+/// runs `steps` time steps with `dispatch`, prints the throughput and returns the steps per
+/// second
+async fn measure(
+    device: &Arc<Device>,
+    queue: &Queue,
+    setup: &Setup,
+    steps: u64,
+    dispatch: Dispatch,
+) -> Result<f64> {
+    let mut hash_grid = HashGrid::from_slice(
+        device,
+        &setup.atoms,
+        &setup.properties,
+        &setup.topology,
+        &setup.parameters,
+        setup.seed,
+        GpuOptions {
+            dispatch,
+            ..setup.gpu
+        },
+    );
+    hash_grid.set_mode(queue, false, true);
+
+    advance(device, queue, &mut hash_grid, WARMUP_STEPS).await?;
+    let first_step = hash_grid.step();
+    let elapsed = advance(device, queue, &mut hash_grid, steps)
+        .await?
+        .as_secs_f64();
+
+    let steps = hash_grid.step() - first_step;
+    let atoms = hash_grid.atom_count();
+    println!(
+        "{atoms} atoms ({:?}), {steps} steps in {elapsed:.3} s: {:.1} steps/s, \
+         {:.2} million atom steps/s",
+        hash_grid.options(),
+        steps as f64 / elapsed,
+        atoms as f64 * steps as f64 / elapsed / 1e6,
+    );
+    Ok(steps as f64 / elapsed)
+}
+
+/// advances the hash grid by at least `steps` time steps, checking the cells after every
+/// submission, and returns how long the gpu took for the steps
+async fn advance(
+    device: &Arc<Device>,
+    queue: &Queue,
+    hash_grid: &mut HashGrid,
+    steps: u64,
+) -> Result<Duration> {
+    let end = hash_grid.step() + steps;
+    let mut elapsed = Duration::ZERO;
+    while hash_grid.step() < end {
+        let start = Instant::now();
+        let mut command_encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Benchmark"),
+        });
+        let batch_end = end.min(hash_grid.step() + STEPS_PER_SUBMIT);
+        while hash_grid.step() < batch_end {
+            hash_grid.update(&mut command_encoder);
+        }
+        queue.submit(Some(command_encoder.finish()));
+        device.poll(Maintain::Wait);
+        elapsed += start.elapsed();
+
+        let fullest_cell = hash_grid.fullest_cell(device, queue).await?;
+        if fullest_cell > MAX_INDICES as u32 {
+            bail!(
+                "a cell holds {fullest_cell} atoms at step {}, more than the {MAX_INDICES} \
+                 per-cell dispatch handles, so both dispatches would not do the same work",
+                hash_grid.step()
+            );
+        }
+    }
+    Ok(elapsed)
+}
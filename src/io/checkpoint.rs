@@ -0,0 +1,736 @@
+use crate::simulation::ewald::{Ewald, Reciprocal};
+use crate::simulation::external::{ExternalForces, ForceField, HarmonicTrap};
+use crate::simulation::hashgrid::MAX_NEIGHBOURS;
+use crate::simulation::properties::Properties;
+use crate::simulation::topology::{
+    Angle, AnglePotential, Bond, BondPotential, ConstraintSolver, Topology, MAX_ANGLES, MAX_BONDS,
+};
+use crate::simulation::{Atom, Boundary, LennardJones, SimulationParameters, Species, Thermostat};
+use eyre::{bail, eyre, Result};
+use nalgebra::Vector2;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+const MAGIC: &[u8; 8] = b"JONESCHK";
+
+/// bumped whenever the layout below or the layout of [`Atom`] changes
+pub const VERSION: u32 = 14;
+
+/// the complete state of a [`crate::simulation::hashgrid::HashGrid`], enough to resume a run
+/// where it stopped.
+///
+/// The binary layout is little endian: the magic `JONESCHK`, the version, the simulation
+/// parameters, the step counter, the rng seed, the thermostat and minimization flags, the next
+/// free atom id, the bonds, the angles, the raw contents of the property buffers, both
+/// ping-pong atom buffers and the rounding errors of the positions, each preceded by its
+/// length, and finally the neighbour lists if there are any. The cells are rebuilt from the
+/// atom positions every step, so they are not part of the state.
+pub struct Checkpoint {
+    pub parameters: SimulationParameters,
+    pub step: u64,
+    /// the seed of the run. The gpu rng is counter based, so this together with `step` is its
+    /// entire state.
+    pub seed: u64,
+    pub minimize: bool,
+    /// whether the thermostat of the parameters was enabled
+    pub thermostat: bool,
+    /// the id the next inserted atom gets
+    pub next_id: u32,
+    /// the properties of the atom in each slot of the atom buffers
+    pub properties: Properties,
+    pub topology: Topology,
+    pub atoms_curr: Vec<Atom>,
+    pub atoms_last: Vec<Atom>,
+    /// the rounding error of the last position update of every atom, all zero unless the
+    /// positions are compensated, see [`crate::simulation::precision::Precision`]
+    pub position_compensation: Vec<Vector2<f32>>,
+    /// only present if the parameters have a neighbour skin. Without it, the lists are built
+    /// in the first step.
+    pub neighbour_lists: Option<NeighbourListState>,
+}
+
+/// the verlet neighbour lists of a [`Checkpoint`], so that a resumed run rebuilds them in the
+/// same steps as an uninterrupted one and sums the forces in the same order
+pub struct NeighbourListState {
+    /// the lists of the atom slots, `MAX_NEIGHBOURS + 1` entries each, see `Neighbours` in
+    /// `interact.wgsl`
+    pub neighbours: Vec<u32>,
+    /// the positions of the atom slots when the lists were built
+    pub reference_positions: Vec<Vector2<f32>>,
+    /// whether the lists are rebuilt in the next step
+    pub rebuild: bool,
+}
+
+impl Checkpoint {
+    pub fn write(&self, writer: &mut impl Write) -> Result<()> {
+        writer.write_all(MAGIC)?;
+        write_u32(writer, VERSION)?;
+
+        let parameters = &self.parameters;
+        write_f32(writer, parameters.grid_side_length)?;
+        write_f32(writer, parameters.cell_side_length)?;
+        write_f32(writer, parameters.time_step)?;
+        for boundary in parameters.boundaries {
+            write_u32(writer, boundary.gpu_id())?;
+        }
+
+        write_u32(writer, parameters.species.len() as u32)?;
+        for species in &parameters.species {
+            write_bytes(writer, species.name.as_bytes())?;
+            write_f32(writer, species.mass)?;
+            write_f32(writer, species.charge)?;
+        }
+        write_u32(writer, parameters.pair_potentials.len() as u32)?;
+        for lj in &parameters.pair_potentials {
+            write_f32(writer, lj.epsilon)?;
+            write_f32(writer, lj.sigma)?;
+            write_f32(writer, lj.cutoff)?;
+        }
+        write_u32(writer, parameters.bond_types.len() as u32)?;
+        for bond_type in &parameters.bond_types {
+            write_u32(writer, bond_type.gpu_id())?;
+            write_f32(writer, bond_type.k())?;
+            write_f32(writer, bond_type.length())?;
+        }
+        write_u32(writer, parameters.angle_types.len() as u32)?;
+        for angle_type in &parameters.angle_types {
+            write_u32(writer, angle_type.gpu_id())?;
+            write_f32(writer, angle_type.k())?;
+            write_f32(writer, angle_type.angle())?;
+        }
+        match parameters.thermostat {
+            None => write_u32(writer, 0)?,
+            Some(Thermostat::Langevin {
+                temperature,
+                damping,
+            }) => {
+                write_u32(writer, 1)?;
+                write_f32(writer, temperature)?;
+                write_f32(writer, damping)?;
+            }
+        }
+        // a skin of 0 means there are no neighbour lists
+        write_f32(writer, parameters.neighbour_skin.unwrap_or(0.0))?;
+        write_f32(writer, parameters.constraint_solver.tolerance)?;
+        write_u32(writer, parameters.constraint_solver.max_iterations)?;
+        match parameters.electrostatics {
+            None => write_u32(writer, 0)?,
+            Some(Ewald {
+                alpha,
+                cutoff,
+                reciprocal,
+            }) => {
+                write_u32(writer, 1)?;
+                write_f32(writer, alpha)?;
+                write_f32(writer, cutoff)?;
+                match reciprocal {
+                    Reciprocal::Direct { k_max } => {
+                        write_u32(writer, 0)?;
+                        write_u32(writer, k_max)?;
+                    }
+                    Reciprocal::Mesh { size, order } => {
+                        write_u32(writer, 1)?;
+                        write_u32(writer, size)?;
+                        write_u32(writer, order)?;
+                    }
+                }
+            }
+        }
+        let external_forces = &parameters.external_forces;
+        for value in external_forces
+            .gravity
+            .iter()
+            .chain(external_forces.electric_field.iter())
+        {
+            write_f32(writer, *value)?;
+        }
+        write_u32(writer, external_forces.traps.len() as u32)?;
+        for trap in &external_forces.traps {
+            write_f32(writer, trap.center.x)?;
+            write_f32(writer, trap.center.y)?;
+            write_f32(writer, trap.stiffness)?;
+            // all species are stored as an invalid species id
+            write_u32(writer, trap.species.unwrap_or(u32::MAX))?;
+        }
+        match &external_forces.force_field {
+            None => write_u32(writer, 0)?,
+            Some(field) => {
+                write_u32(writer, 1)?;
+                write_u32(writer, field.width)?;
+                write_u32(writer, field.height)?;
+                write_bytes(writer, bytemuck::cast_slice(&field.samples))?;
+            }
+        }
+
+        writer.write_all(&self.step.to_le_bytes())?;
+        writer.write_all(&self.seed.to_le_bytes())?;
+        write_u32(writer, self.minimize as u32)?;
+        write_u32(writer, self.thermostat as u32)?;
+        write_u32(writer, self.next_id)?;
+
+        write_u32(writer, self.topology.bonds.len() as u32)?;
+        for bond in &self.topology.bonds {
+            write_u32(writer, bond.a)?;
+            write_u32(writer, bond.b)?;
+            write_u32(writer, bond.kind)?;
+        }
+        write_u32(writer, self.topology.angles.len() as u32)?;
+        for angle in &self.topology.angles {
+            write_u32(writer, angle.a)?;
+            write_u32(writer, angle.b)?;
+            write_u32(writer, angle.c)?;
+            write_u32(writer, angle.kind)?;
+        }
+
+        for column in self.properties.columns() {
+            write_bytes(writer, column)?;
+        }
+        write_bytes(writer, bytemuck::cast_slice(&self.atoms_curr))?;
+        write_bytes(writer, bytemuck::cast_slice(&self.atoms_last))?;
+        write_bytes(writer, bytemuck::cast_slice(&self.position_compensation))?;
+
+        match &self.neighbour_lists {
+            None => write_u32(writer, 0)?,
+            Some(lists) => {
+                write_u32(writer, 1)?;
+                write_bytes(writer, bytemuck::cast_slice(&lists.neighbours))?;
+                write_bytes(writer, bytemuck::cast_slice(&lists.reference_positions))?;
+                write_u32(writer, lists.rebuild as u32)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read(reader: &mut impl Read) -> Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            bail!("not a checkpoint file");
+        }
+        let version = read_u32(reader)?;
+        if version != VERSION {
+            bail!("unsupported checkpoint version {version}, expected {VERSION}");
+        }
+
+        let grid_side_length = read_f32(reader)?;
+        let cell_side_length = read_f32(reader)?;
+        let time_step = read_f32(reader)?;
+        let boundaries = [read_boundary(reader)?, read_boundary(reader)?];
+
+        let species = (0..read_u32(reader)?)
+            .map(|_| {
+                let name = String::from_utf8(read_bytes(reader)?)?;
+                let mass = read_f32(reader)?;
+                let charge = read_f32(reader)?;
+                Ok(Species { name, mass, charge })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let pair_potentials = (0..read_u32(reader)?)
+            .map(|_| {
+                Ok(LennardJones {
+                    epsilon: read_f32(reader)?,
+                    sigma: read_f32(reader)?,
+                    cutoff: read_f32(reader)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if pair_potentials.len() != species.len().pow(2) {
+            bail!("checkpoint has an invalid pair potential table");
+        }
+        let bond_types = (0..read_u32(reader)?)
+            .map(|_| {
+                let id = read_u32(reader)?;
+                let k = read_f32(reader)?;
+                let length = read_f32(reader)?;
+                match id {
+                    0 => Ok(BondPotential::Harmonic { k, length }),
+                    1 => Ok(BondPotential::Fene {
+                        k,
+                        max_length: length,
+                    }),
+                    2 => Ok(BondPotential::Constraint { length }),
+                    other => bail!("unknown bond potential {other} in checkpoint"),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let angle_types = (0..read_u32(reader)?)
+            .map(|_| {
+                let id = read_u32(reader)?;
+                let k = read_f32(reader)?;
+                let angle = read_f32(reader)?;
+                match id {
+                    0 => Ok(AnglePotential::Harmonic { k, angle }),
+                    1 => Ok(AnglePotential::Cosine { k, angle }),
+                    other => bail!("unknown angle potential {other} in checkpoint"),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let thermostat = match read_u32(reader)? {
+            0 => None,
+            1 => Some(Thermostat::Langevin {
+                temperature: read_f32(reader)?,
+                damping: read_f32(reader)?,
+            }),
+            other => bail!("unknown thermostat {other} in checkpoint"),
+        };
+        let neighbour_skin = Some(read_f32(reader)?).filter(|&skin| skin > 0.0);
+        let constraint_solver = ConstraintSolver {
+            tolerance: read_f32(reader)?,
+            max_iterations: read_u32(reader)?,
+        };
+        let electrostatics = match read_u32(reader)? {
+            0 => None,
+            1 => Some(Ewald {
+                alpha: read_f32(reader)?,
+                cutoff: read_f32(reader)?,
+                reciprocal: match read_u32(reader)? {
+                    0 => Reciprocal::Direct {
+                        k_max: read_u32(reader)?,
+                    },
+                    1 => Reciprocal::Mesh {
+                        size: read_u32(reader)?,
+                        order: read_u32(reader)?,
+                    },
+                    other => bail!("unknown reciprocal space sum {other} in checkpoint"),
+                },
+            }),
+            other => bail!("unknown electrostatics {other} in checkpoint"),
+        };
+        let gravity = Vector2::new(read_f32(reader)?, read_f32(reader)?);
+        let electric_field = Vector2::new(read_f32(reader)?, read_f32(reader)?);
+        let traps = (0..read_u32(reader)?)
+            .map(|_| {
+                Ok(HarmonicTrap {
+                    center: Vector2::new(read_f32(reader)?, read_f32(reader)?),
+                    stiffness: read_f32(reader)?,
+                    species: Some(read_u32(reader)?).filter(|&species| species != u32::MAX),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let force_field = match read_u32(reader)? {
+            0 => None,
+            1 => {
+                let width = read_u32(reader)?;
+                let height = read_u32(reader)?;
+                let samples = read_pod_vec::<Vector2<f32>>(reader)?;
+                if samples.len() != width as usize * height as usize {
+                    bail!("the force field in the checkpoint has the wrong amount of samples");
+                }
+                Some(ForceField {
+                    width,
+                    height,
+                    samples,
+                })
+            }
+            other => bail!("unknown force field {other} in checkpoint"),
+        };
+
+        let step = read_u64(reader)?;
+        let seed = read_u64(reader)?;
+        let minimize = read_u32(reader)? != 0;
+        let thermostat_enabled = read_u32(reader)? != 0;
+        let next_id = read_u32(reader)?;
+
+        let bonds = (0..read_u32(reader)?)
+            .map(|_| {
+                Ok(Bond {
+                    a: read_u32(reader)?,
+                    b: read_u32(reader)?,
+                    kind: read_u32(reader)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let angles = (0..read_u32(reader)?)
+            .map(|_| {
+                Ok(Angle {
+                    a: read_u32(reader)?,
+                    b: read_u32(reader)?,
+                    c: read_u32(reader)?,
+                    kind: read_u32(reader)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let columns = (0..Properties::COUNT)
+            .map(|_| read_bytes(reader))
+            .collect::<Result<Vec<_>>>()?;
+        let atoms_curr = read_pod_vec::<Atom>(reader)?;
+        let atoms_last = read_pod_vec::<Atom>(reader)?;
+        let position_compensation = read_pod_vec(reader)?;
+        let count = atoms_curr.len();
+        if atoms_last.len() != count
+            || position_compensation.len() != count
+            || columns
+                .iter()
+                .any(|column| column.len() != count * Properties::SIZE)
+        {
+            bail!("the atom buffers of the checkpoint differ in length");
+        }
+        let properties =
+            Properties::from_columns(count, std::array::from_fn(|index| &*columns[index]));
+
+        let neighbour_lists = match read_u32(reader)? {
+            0 => None,
+            1 => Some(NeighbourListState {
+                neighbours: read_pod_vec(reader)?,
+                reference_positions: read_pod_vec(reader)?,
+                rebuild: read_u32(reader)? != 0,
+            }),
+            other => bail!("unknown neighbour lists {other} in checkpoint"),
+        };
+        if let Some(lists) = &neighbour_lists {
+            if neighbour_skin.is_none()
+                || lists.neighbours.len() != count * (MAX_NEIGHBOURS + 1)
+                || lists.reference_positions.len() != count
+            {
+                bail!("the neighbour lists of the checkpoint do not match its atoms");
+            }
+        }
+
+        let mut counts = properties
+            .ids
+            .iter()
+            .map(|&id| (id, (0, 0)))
+            .collect::<HashMap<_, _>>();
+        for bond in &bonds {
+            if bond.kind as usize >= bond_types.len() || bond.a == bond.b {
+                bail!("checkpoint has an invalid bond");
+            }
+            for id in [bond.a, bond.b] {
+                let (count, _) = counts
+                    .get_mut(&id)
+                    .ok_or_else(|| eyre!("checkpoint has a bond of the missing atom {id}"))?;
+                *count += 1;
+                if *count > MAX_BONDS {
+                    bail!("atom {id} of the checkpoint has more than {MAX_BONDS} bonds");
+                }
+            }
+        }
+        for angle in &angles {
+            if angle.kind as usize >= angle_types.len()
+                || angle.a == angle.b
+                || angle.b == angle.c
+                || angle.a == angle.c
+            {
+                bail!("checkpoint has an invalid angle");
+            }
+            for id in [angle.a, angle.b, angle.c] {
+                let (_, count) = counts
+                    .get_mut(&id)
+                    .ok_or_else(|| eyre!("checkpoint has an angle of the missing atom {id}"))?;
+                *count += 1;
+                if *count > MAX_ANGLES {
+                    bail!("atom {id} of the checkpoint has more than {MAX_ANGLES} angles");
+                }
+            }
+        }
+
+        Ok(Self {
+            parameters: SimulationParameters {
+                grid_side_length,
+                cell_side_length,
+                time_step,
+                boundaries,
+                species,
+                pair_potentials,
+                bond_types,
+                angle_types,
+                constraint_solver,
+                thermostat,
+                neighbour_skin,
+                electrostatics,
+                external_forces: ExternalForces {
+                    gravity,
+                    electric_field,
+                    traps,
+                    force_field,
+                },
+            },
+            step,
+            seed,
+            minimize,
+            thermostat: thermostat_enabled,
+            next_id,
+            properties,
+            topology: Topology { bonds, angles },
+            atoms_curr,
+            atoms_last,
+            position_compensation,
+            neighbour_lists,
+        })
+    }
+}
+
+fn write_u32(writer: &mut impl Write, value: u32) -> Result<()> {
+    Ok(writer.write_all(&value.to_le_bytes())?)
+}
+
+fn write_f32(writer: &mut impl Write, value: f32) -> Result<()> {
+    Ok(writer.write_all(&value.to_le_bytes())?)
+}
+
+fn write_bytes(writer: &mut impl Write, bytes: &[u8]) -> Result<()> {
+    writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    Ok(writer.write_all(bytes)?)
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f32(reader: &mut impl Read) -> Result<f32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+fn read_bytes(reader: &mut impl Read) -> Result<Vec<u8>> {
+    let mut bytes = vec![0; read_u64(reader)? as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_pod_vec<T: bytemuck::Pod>(reader: &mut impl Read) -> Result<Vec<T>> {
+    let bytes = read_bytes(reader)?;
+    if bytes.len() % std::mem::size_of::<T>() != 0 {
+        bail!("checkpoint buffer has an invalid length");
+    }
+    // the bytes are not necessarily aligned for `T`, so they are copied instead of cast
+    let mut values = vec![T::zeroed(); bytes.len() / std::mem::size_of::<T>()];
+    bytemuck::cast_slice_mut(&mut values).copy_from_slice(&bytes);
+    Ok(values)
+}
+
+fn read_boundary(reader: &mut impl Read) -> Result<Boundary> {
+    let id = read_u32(reader)?;
+    [Boundary::Open, Boundary::Periodic, Boundary::Reflective]
+        .into_iter()
+        .find(|boundary| boundary.gpu_id() == id)
+        .ok_or_else(|| eyre!("unknown boundary {id} in checkpoint"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario::{Overrides, Setup};
+    use std::path::Path;
+
+    /// charged atoms with a bonded and angled chain, electrostatics, a thermostat, external
+    /// forces and neighbour lists, so that every optional part of the layout is written
+    const SCENARIO: &str = r#"
+        seed = 3
+        [box]
+        side_length = 20.0
+        cell_size = 4.0
+        [[species]]
+        name = "A"
+        mass = 1.0
+        charge = 1.0
+        [[species]]
+        name = "B"
+        mass = 2.0
+        charge = -1.0
+        [[generators]]
+        kind = "random_gas"
+        species = "A"
+        count = 20
+        min_separation = 1.0
+        [[generators]]
+        kind = "random_gas"
+        species = "B"
+        count = 20
+        min_separation = 1.0
+        [[potentials]]
+        kind = "lennard_jones"
+        species = ["A", "A"]
+        epsilon = 1.0
+        sigma = 1.0
+        [[potentials]]
+        kind = "lennard_jones"
+        species = ["B", "B"]
+        epsilon = 0.5
+        sigma = 1.2
+        [[bond_types]]
+        kind = "harmonic"
+        name = "spring"
+        k = 100.0
+        length = 1.0
+        [[bonds]]
+        type = "spring"
+        ids = [0, 1, 2]
+        [[angle_types]]
+        kind = "cosine"
+        name = "stiff"
+        k = 5.0
+        angle = 180.0
+        [[angles]]
+        type = "stiff"
+        ids = [0, 1, 2]
+        [electrostatics]
+        kind = "ewald"
+        cutoff = 3.0
+        [thermostat]
+        kind = "langevin"
+        temperature = 0.5
+        damping = 1.0
+        [neighbour_list]
+        skin = 0.3
+        [[external_forces]]
+        kind = "gravity"
+        acceleration = [0.0, -0.1]
+        [[external_forces]]
+        kind = "harmonic_trap"
+        center = [10.0, 10.0]
+        stiffness = 5.0
+        species = "A"
+        [[stages]]
+        kind = "equilibrate"
+        steps = 10
+    "#;
+
+    /// a checkpoint of the scenario in the middle of a run, with distinct rounding errors and
+    /// neighbour lists for every atom
+    fn checkpoint() -> Checkpoint {
+        let setup = Setup::parse(SCENARIO, Path::new("."), Overrides::default()).unwrap();
+        let count = setup.atoms.len();
+        let atoms_last = setup
+            .atoms
+            .iter()
+            .map(|atom| {
+                let position = atom.position() - Vector2::new(0.01, 0.0);
+                Atom::new(position, atom.velocity(), atom.force()).with_species(atom.species())
+            })
+            .collect();
+        Checkpoint {
+            parameters: setup.parameters,
+            step: 1234,
+            seed: setup.seed,
+            minimize: false,
+            thermostat: true,
+            next_id: count as u32,
+            properties: setup.properties,
+            topology: setup.topology,
+            atoms_curr: setup.atoms,
+            atoms_last,
+            position_compensation: (0..count)
+                .map(|slot| Vector2::new(slot as f32 * 1e-7, -(slot as f32) * 1e-8))
+                .collect(),
+            neighbour_lists: Some(NeighbourListState {
+                neighbours: (0..count * (MAX_NEIGHBOURS + 1))
+                    .map(|entry| (entry % 97) as u32)
+                    .collect(),
+                reference_positions: (0..count)
+                    .map(|slot| Vector2::new(slot as f32 * 0.5, 1.0))
+                    .collect(),
+                rebuild: false,
+            }),
+        }
+    }
+
+    fn bytes(checkpoint: &Checkpoint) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        checkpoint.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trips() {
+        let written = checkpoint();
+        let read = Checkpoint::read(&mut bytes(&written).as_slice()).unwrap();
+
+        // the parameters only derive debug, which prints floats exactly
+        assert_eq!(
+            format!("{:?}", read.parameters),
+            format!("{:?}", written.parameters)
+        );
+        assert!(read.parameters.electrostatics.is_some());
+        assert_eq!(read.parameters.external_forces.traps.len(), 1);
+        assert_eq!(
+            (
+                read.step,
+                read.seed,
+                read.minimize,
+                read.thermostat,
+                read.next_id
+            ),
+            (1234, written.seed, false, true, written.next_id)
+        );
+        assert_eq!(read.properties, written.properties);
+        assert_eq!(read.topology, written.topology);
+        assert_eq!(read.topology.angles.len(), 1);
+        for (read, written) in [
+            (&read.atoms_curr, &written.atoms_curr),
+            (&read.atoms_last, &written.atoms_last),
+        ] {
+            assert_eq!(
+                bytemuck::cast_slice::<_, u8>(read),
+                bytemuck::cast_slice::<_, u8>(written)
+            );
+        }
+        assert_eq!(read.position_compensation, written.position_compensation);
+
+        let (read, written) = (
+            read.neighbour_lists.unwrap(),
+            written.neighbour_lists.unwrap(),
+        );
+        assert_eq!(read.neighbours, written.neighbours);
+        assert_eq!(read.reference_positions, written.reference_positions);
+        assert_eq!(read.rebuild, written.rebuild);
+    }
+
+    #[test]
+    fn round_trips_without_neighbour_lists() {
+        let mut written = checkpoint();
+        written.parameters.neighbour_skin = None;
+        written.neighbour_lists = None;
+        let read = Checkpoint::read(&mut bytes(&written).as_slice()).unwrap();
+        assert!(read.parameters.neighbour_skin.is_none());
+        assert!(read.neighbour_lists.is_none());
+        assert_eq!(read.position_compensation, written.position_compensation);
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let error = |bytes: &[u8]| match Checkpoint::read(&mut &bytes[..]) {
+            Ok(_) => panic!("expected an error"),
+            Err(error) => error.to_string(),
+        };
+        let valid = bytes(&checkpoint());
+
+        assert_eq!(error(b"NOTACHECKPOINT"), "not a checkpoint file");
+        let mut old = valid.clone();
+        old[8..12].copy_from_slice(&(VERSION - 1).to_le_bytes());
+        assert!(error(&old).contains("unsupported checkpoint version"));
+        // every prefix misses some part of the state
+        for length in [12, valid.len() / 2, valid.len() - 1] {
+            assert!(Checkpoint::read(&mut &valid[..length]).is_err(), "{length}");
+        }
+
+        let mut mismatched = checkpoint();
+        mismatched.position_compensation.pop();
+        assert_eq!(
+            error(&bytes(&mismatched)),
+            "the atom buffers of the checkpoint differ in length"
+        );
+        let mut mismatched = checkpoint();
+        mismatched
+            .neighbour_lists
+            .as_mut()
+            .unwrap()
+            .neighbours
+            .pop();
+        assert_eq!(
+            error(&bytes(&mismatched)),
+            "the neighbour lists of the checkpoint do not match its atoms"
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use crate::simulation::external::ForceField;
+use eyre::{bail, eyre, Result, WrapErr};
+use nalgebra::Vector2;
+use std::path::Path;
+
+pub fn read(path: &Path) -> Result<ForceField> {
+    let source = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("failed to read force field {}", path.display()))?;
+    parse(&source).wrap_err_with(|| format!("invalid force field {}", path.display()))
+}
+
+/// parses a force field with one row of samples per line, from the bottom of the box to the
+/// top. Every line holds the x and y components of the samples of its row from left to right,
+/// separated by whitespace. Empty lines and everything after a `#` are ignored.
+pub fn parse(source: &str) -> Result<ForceField> {
+    let mut width = None;
+    let mut height = 0;
+    let mut samples = Vec::new();
+    for (index, raw_line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let content = raw_line.split('#').next().unwrap_or_default().trim();
+        if content.is_empty() {
+            continue;
+        }
+
+        let values = content
+            .split_whitespace()
+            .map(|field| {
+                field
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|value| value.is_finite())
+                    .ok_or_else(|| eyre!("line {line_number}: invalid number `{field}`"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if values.len() % 2 != 0 {
+            bail!("line {line_number}: expected pairs of x and y components");
+        }
+        let row_width = values.len() / 2;
+        match width {
+            None => width = Some(row_width),
+            Some(width) if width != row_width => {
+                bail!("line {line_number}: expected {width} samples like the first row, got {row_width}")
+            }
+            Some(_) => {}
+        }
+        samples.extend(
+            values
+                .chunks_exact(2)
+                .map(|sample| Vector2::new(sample[0], sample[1])),
+        );
+        height += 1;
+    }
+
+    let width = width.ok_or_else(|| eyre!("the force field has no samples"))?;
+    Ok(ForceField {
+        width: width as u32,
+        height,
+        samples,
+    })
+}
@@ -0,0 +1,425 @@
+use crate::simulation::properties::Properties;
+use crate::simulation::{Atom, Boundary, SimulationParameters};
+use eyre::{bail, eyre, Result, WrapErr};
+use nalgebra::Vector2;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+/// the contents of a lammps data file that are relevant for a two dimensional simulation
+pub struct DataFile {
+    /// the lower corner of the box, i.e. `xlo` and `ylo`
+    pub lo: Vector2<f32>,
+    /// the upper corner of the box, i.e. `xhi` and `yhi`
+    pub hi: Vector2<f32>,
+    /// the mass of every atom type
+    pub masses: Vec<f32>,
+    /// the atoms, sorted by their id
+    pub atoms: Vec<DataAtom>,
+}
+
+pub struct DataAtom {
+    pub id: u64,
+    /// the zero based atom type, lammps counts from one
+    pub atom_type: u32,
+    pub position: Vector2<f32>,
+    pub velocity: Vector2<f32>,
+}
+
+/// the columns of the atom type and the x coordinate in the `Atoms` section for the supported
+/// atom styles
+fn atom_columns(style: &str) -> Option<(usize, usize)> {
+    match style {
+        "atomic" => Some((1, 2)),
+        "charge" => Some((1, 3)),
+        "bond" | "angle" | "molecular" => Some((2, 3)),
+        "full" => Some((2, 4)),
+        _ => None,
+    }
+}
+
+fn parse<T: std::str::FromStr>(line: usize, field: Option<&str>) -> Result<T> {
+    let field = field.ok_or_else(|| eyre!("line {line}: missing field"))?;
+    field
+        .parse()
+        .map_err(|_| eyre!("line {line}: invalid number `{field}`"))
+}
+
+impl DataFile {
+    pub fn read(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read lammps data file {}", path.display()))?;
+        Self::parse(&source)
+            .wrap_err_with(|| format!("invalid lammps data file {}", path.display()))
+    }
+
+    /// parses the header (atom count, atom types, box) and the `Masses`, `Atoms` and
+    /// `Velocities` sections. Other sections are skipped, z coordinates are ignored.
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut atom_count = None;
+        let mut type_count = None;
+        let mut lo = Vector2::zeros();
+        let mut hi = Vector2::zeros();
+        let mut masses = Vec::new();
+        let mut atoms = Vec::new();
+        let mut velocities = HashMap::new();
+
+        // the section the current line belongs to and the style comment of its header. Lines
+        // before the first section belong to the header.
+        let mut section: Option<(String, String)> = None;
+
+        // the first line is always a comment
+        for (index, raw_line) in source.lines().enumerate().skip(1) {
+            let line_number = index + 1;
+            let (content, comment) = match raw_line.split_once('#') {
+                Some((content, comment)) => (content.trim(), Some(comment.trim())),
+                None => (raw_line.trim(), None),
+            };
+            if content.is_empty() {
+                continue;
+            }
+            let mut fields = content.split_whitespace();
+
+            if content.chars().next().is_some_and(|c| c.is_alphabetic()) {
+                let name = content.to_owned();
+                let style = comment.unwrap_or_default().to_owned();
+                section = Some((name, style));
+                continue;
+            }
+
+            match &section {
+                None => {
+                    let keyword = content
+                        .split_whitespace()
+                        .skip_while(|field| field.parse::<f64>().is_ok())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    match keyword.as_str() {
+                        "atoms" => atom_count = Some(parse::<usize>(line_number, fields.next())?),
+                        "atom types" => {
+                            type_count = Some(parse::<usize>(line_number, fields.next())?)
+                        }
+                        "xlo xhi" | "ylo yhi" => {
+                            let axis = if keyword == "xlo xhi" { 0 } else { 1 };
+                            lo[axis] = parse(line_number, fields.next())?;
+                            hi[axis] = parse(line_number, fields.next())?;
+                        }
+                        "xy xz yz" => {
+                            let tilt = parse::<f32>(line_number, fields.next())?;
+                            if tilt != 0.0 {
+                                bail!("line {line_number}: triclinic boxes are not supported");
+                            }
+                        }
+                        // zlo zhi, bonds, angles and the like are not needed
+                        _ => {}
+                    }
+                }
+                Some((name, style)) => match name.as_str() {
+                    "Masses" => {
+                        let atom_type = parse::<usize>(line_number, fields.next())?;
+                        let mass = parse::<f32>(line_number, fields.next())?;
+                        if atom_type == 0 {
+                            bail!("line {line_number}: atom types start at 1");
+                        }
+                        if masses.len() < atom_type {
+                            masses.resize(atom_type, 0.0);
+                        }
+                        masses[atom_type - 1] = mass;
+                    }
+                    "Atoms" => {
+                        let style = if style.is_empty() { "atomic" } else { style };
+                        let (type_column, column) = atom_columns(style).ok_or_else(|| {
+                            eyre!("line {line_number}: unsupported atom style `{style}`")
+                        })?;
+                        let fields = content.split_whitespace().collect::<Vec<_>>();
+                        let id = parse::<u64>(line_number, fields.first().copied())?;
+                        let atom_type =
+                            parse::<u32>(line_number, fields.get(type_column).copied())?;
+                        if atom_type == 0 {
+                            bail!("line {line_number}: atom types start at 1");
+                        }
+                        atoms.push(DataAtom {
+                            id,
+                            atom_type: atom_type - 1,
+                            position: Vector2::new(
+                                parse(line_number, fields.get(column).copied())?,
+                                parse(line_number, fields.get(column + 1).copied())?,
+                            ),
+                            velocity: Vector2::zeros(),
+                        });
+                    }
+                    "Velocities" => {
+                        let id = parse::<u64>(line_number, fields.next())?;
+                        let velocity = Vector2::new(
+                            parse(line_number, fields.next())?,
+                            parse(line_number, fields.next())?,
+                        );
+                        velocities.insert(id, velocity);
+                    }
+                    _ => {}
+                },
+            }
+        }
+
+        let atom_count = atom_count.ok_or_else(|| eyre!("missing `atoms` header line"))?;
+        if atoms.len() != atom_count {
+            bail!(
+                "the header announces {atom_count} atoms, but the `Atoms` section has {}",
+                atoms.len()
+            );
+        }
+        let type_count = type_count.ok_or_else(|| eyre!("missing `atom types` header line"))?;
+        if masses.len() != type_count || masses.iter().any(|&mass| mass <= 0.0) {
+            bail!("the `Masses` section must give a positive mass for all {type_count} atom types");
+        }
+        if let Some(atom) = atoms
+            .iter()
+            .find(|atom| atom.atom_type as usize >= type_count)
+        {
+            bail!(
+                "atom {} has type {}, but there are only {type_count} atom types",
+                atom.id,
+                atom.atom_type + 1
+            );
+        }
+
+        atoms.sort_by_key(|atom| atom.id);
+        for atom in &mut atoms {
+            if let Some(velocity) = velocities.get(&atom.id) {
+                atom.velocity = *velocity;
+            }
+        }
+
+        Ok(Self {
+            lo,
+            hi,
+            masses,
+            atoms,
+        })
+    }
+}
+
+/// appends one frame in the style of lammps' `dump custom` with the columns
+/// `id type q mass x y z vx vy vz fx fy fz`. Lammps ids start at 1, so they are the stable ids
+/// of the atoms plus one.
+pub fn write_dump_frame(
+    writer: &mut impl Write,
+    step: u64,
+    parameters: &SimulationParameters,
+    atoms: &[Atom],
+    properties: &Properties,
+) -> std::io::Result<()> {
+    let side_length = parameters.grid_side_length;
+    let bounds = |boundary: Boundary| match boundary {
+        Boundary::Periodic => "pp",
+        Boundary::Open => "ss",
+        Boundary::Reflective => "ff",
+    };
+
+    writeln!(writer, "ITEM: TIMESTEP\n{step}")?;
+    writeln!(writer, "ITEM: NUMBER OF ATOMS\n{}", atoms.len())?;
+    writeln!(
+        writer,
+        "ITEM: BOX BOUNDS {} {} pp",
+        bounds(parameters.boundaries[0]),
+        bounds(parameters.boundaries[1])
+    )?;
+    writeln!(writer, "0 {side_length}\n0 {side_length}\n-0.5 0.5")?;
+    writeln!(writer, "ITEM: ATOMS id type q mass x y z vx vy vz fx fy fz")?;
+
+    for (slot, atom) in atoms.iter().enumerate() {
+        let (position, velocity, force) = (atom.position(), atom.velocity(), atom.force());
+        writeln!(
+            writer,
+            "{} {} {} {} {} {} 0 {} {} 0 {} {} 0",
+            properties.ids[slot] + 1,
+            atom.species() + 1,
+            properties.charges[slot],
+            properties.masses[slot],
+            position.x,
+            position.y,
+            velocity.x,
+            velocity.y,
+            force.x,
+            force.y
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a data file with two atom types, a bond and the given `Atoms` section header and lines,
+    /// whose atoms are listed out of order
+    fn data_file(header: &str, atoms: &str) -> String {
+        format!(
+            "lammps data file\n\
+             \n\
+             3 atoms\n\
+             2 atom types\n\
+             1 bonds\n\
+             -1.0 9.0 xlo xhi\n\
+             0.0 10.0 ylo yhi\n\
+             -0.5 0.5 zlo zhi\n\
+             \n\
+             Masses\n\
+             \n\
+             1 1.0\n\
+             2 39.9 # argon\n\
+             \n\
+             {header}\n\
+             \n\
+             {atoms}\n\
+             \n\
+             Bonds\n\
+             \n\
+             1 1 1 2\n"
+        )
+    }
+
+    /// the `Atoms` line of the atom style, with the molecule id 7 and the charge 0.5 where the
+    /// style has them
+    fn atom_line(style: &str, id: u64, atom_type: u32, x: f32, y: f32) -> String {
+        match style {
+            "atomic" => format!("{id} {atom_type} {x} {y} 0.0"),
+            "charge" => format!("{id} {atom_type} 0.5 {x} {y} 0.0"),
+            "bond" | "angle" | "molecular" => format!("{id} 7 {atom_type} {x} {y} 0.0"),
+            "full" => format!("{id} 7 {atom_type} 0.5 {x} {y} 0.0 0 0 0"),
+            _ => unreachable!(),
+        }
+    }
+
+    fn atomic(atoms: &str) -> String {
+        data_file("Atoms # atomic", atoms)
+    }
+
+    fn error(source: &str) -> String {
+        match DataFile::parse(source) {
+            Ok(_) => panic!("expected an error"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn reads_the_header_and_the_masses() {
+        let file = DataFile::parse(&atomic("3 1 2 2 0\n1 1 0 0 0\n2 2 1 1 0")).unwrap();
+        assert_eq!(file.lo, Vector2::new(-1.0, 0.0));
+        assert_eq!(file.hi, Vector2::new(9.0, 10.0));
+        assert_eq!(file.masses, vec![1.0, 39.9]);
+        let ids = file.atoms.iter().map(|atom| atom.id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn maps_the_columns_of_every_atom_style() {
+        for style in ["atomic", "charge", "bond", "angle", "molecular", "full"] {
+            let atoms = [(1, 1, 0.0, 0.0), (2, 2, 1.5, 2.5), (3, 1, 3.0, 4.0)]
+                .map(|(id, atom_type, x, y)| atom_line(style, id, atom_type, x, y))
+                .join("\n");
+            let file = DataFile::parse(&data_file(&format!("Atoms # {style}"), &atoms))
+                .unwrap_or_else(|error| panic!("{style}: {error}"));
+            let atom = &file.atoms[1];
+            assert_eq!((atom.id, atom.atom_type), (2, 1), "{style}");
+            assert_eq!(atom.position, Vector2::new(1.5, 2.5), "{style}");
+        }
+    }
+
+    #[test]
+    fn atoms_without_a_style_comment_are_atomic() {
+        let file =
+            DataFile::parse(&data_file("Atoms", "1 1 0 0 0\n2 2 1.5 2.5 0\n3 1 3 4 0")).unwrap();
+        assert_eq!(file.atoms[1].atom_type, 1);
+        assert_eq!(file.atoms[1].position, Vector2::new(1.5, 2.5));
+    }
+
+    #[test]
+    fn merges_the_velocities_by_id() {
+        let source = atomic("1 1 0 0 0\n2 2 1 1 0\n3 1 2 2 0")
+            + "\nVelocities\n\n3 0.5 -0.5 0.0\n1 1.0 2.0 0.0\n";
+        let file = DataFile::parse(&source).unwrap();
+        let velocities = file
+            .atoms
+            .iter()
+            .map(|atom| atom.velocity)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            velocities,
+            vec![
+                Vector2::new(1.0, 2.0),
+                Vector2::zeros(),
+                Vector2::new(0.5, -0.5)
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_comments_and_other_sections() {
+        let source = atomic("1 1 0 0 0 # first\n2 2 1 1 0\n3 1 2 2 0")
+            .replace("1 bonds\n", "1 bonds\n# a comment line\n1 bond types\n")
+            + "\nBond Coeffs\n\n1 30.0 1.5\n";
+        let file = DataFile::parse(&source).unwrap();
+        assert_eq!(file.atoms.len(), 3);
+        assert_eq!(file.masses.len(), 2);
+    }
+
+    #[test]
+    fn rejects_invalid_headers() {
+        let valid = atomic("1 1 0 0 0\n2 2 1 1 0\n3 1 2 2 0");
+        for (from, to, message) in [
+            ("3 atoms\n", "", "missing `atoms` header line"),
+            ("2 atom types\n", "", "missing `atom types` header line"),
+            ("3 atoms", "4 atoms", "the header announces 4 atoms"),
+            (
+                "2 atom types",
+                "3 atom types",
+                "positive mass for all 3 atom types",
+            ),
+            (
+                "2 39.9 # argon",
+                "2 0.0",
+                "positive mass for all 2 atom types",
+            ),
+            (
+                "-0.5 0.5 zlo zhi",
+                "0.5 0.0 0.0 xy xz yz",
+                "triclinic boxes are not supported",
+            ),
+        ] {
+            let source = valid.replacen(from, to, 1);
+            assert!(error(&source).contains(message), "{}", error(&source));
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_atoms() {
+        for (atoms, message) in [
+            (
+                "1 0 0 0 0\n2 2 1 1 0\n3 1 2 2 0",
+                "line 17: atom types start at 1",
+            ),
+            (
+                "1 1 0 0 0\n2 3 1 1 0\n3 1 2 2 0",
+                "atom 2 has type 3, but there are only 2",
+            ),
+            (
+                "1 1 0 0 0\n2 2 1 x 0\n3 1 2 2 0",
+                "line 18: invalid number `x`",
+            ),
+            ("1 1 0 0 0\n2 2 1\n3 1 2 2 0", "line 18: missing field"),
+        ] {
+            assert!(
+                error(&atomic(atoms)).contains(message),
+                "{}",
+                error(&atomic(atoms))
+            );
+        }
+
+        let source = data_file("Atoms # sphere", "1 1 0 0 0\n2 2 1 1 0\n3 1 2 2 0");
+        assert!(error(&source).contains("unsupported atom style `sphere`"));
+        let source = atomic("1 1 0 0 0\n2 2 1 1 0\n3 1 2 2 0").replace("1 1.0\n", "0 1.0\n");
+        assert!(error(&source).contains("line 12: atom types start at 1"));
+    }
+}
@@ -0,0 +1,4 @@
+pub mod checkpoint;
+pub mod force_field;
+pub mod lammps;
+pub mod xyz;
@@ -0,0 +1,57 @@
+use crate::simulation::properties::Properties;
+use crate::simulation::{Atom, Boundary, SimulationParameters};
+use std::io::{Result, Write};
+
+/// appends one frame in the extended xyz format, as read by ovito and ase. The simulation is
+/// two dimensional, so all z components are written as 0 and the box is given a unit height.
+/// The seed of the run is recorded in the comment line, the properties of the atoms in the
+/// columns following the species.
+pub fn write_frame(
+    writer: &mut impl Write,
+    step: u64,
+    seed: u64,
+    parameters: &SimulationParameters,
+    atoms: &[Atom],
+    properties: &Properties,
+) -> Result<()> {
+    let side_length = parameters.grid_side_length;
+    let pbc = |boundary: Boundary| match boundary {
+        Boundary::Periodic => "T",
+        Boundary::Open | Boundary::Reflective => "F",
+    };
+
+    writeln!(writer, "{}", atoms.len())?;
+    writeln!(
+        writer,
+        "Lattice=\"{side_length} 0 0 0 {side_length} 0 0 0 1\" \
+         Properties=species:S:1:id:I:1:charge:R:1:mass:R:1:group:I:1:flags:I:1:\
+         pos:R:3:velo:R:3:forces:R:3 \
+         Time={} Step={step} Seed={seed} pbc=\"{} {} F\"",
+        step as f64 * parameters.time_step as f64,
+        pbc(parameters.boundaries[0]),
+        pbc(parameters.boundaries[1]),
+    )?;
+
+    for (slot, atom) in atoms.iter().enumerate() {
+        let (position, velocity, force) = (atom.position(), atom.velocity(), atom.force());
+        let atom_properties = properties.get(slot);
+        writeln!(
+            writer,
+            "{} {} {} {} {} {} {} {} 0 {} {} 0 {} {} 0",
+            parameters.species[atom.species() as usize].name,
+            atom_properties.id,
+            atom_properties.charge,
+            atom_properties.mass,
+            atom_properties.groups,
+            atom_properties.flags,
+            position.x,
+            position.y,
+            velocity.x,
+            velocity.y,
+            force.x,
+            force.y
+        )?;
+    }
+
+    Ok(())
+}
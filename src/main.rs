@@ -1,35 +1,88 @@
 use crate::render::{PushConstants, RenderState};
-use crate::simulation::hashgrid::HashGrid;
-use crate::simulation::Atom;
+use crate::runner::Runner;
+use crate::scenario::{Overrides, Setup, DEFAULT_SCENARIO};
+use crate::simulation::hashgrid::{HashGrid, Instances};
+use clap::Parser;
 use eyre::Result;
-use nalgebra::Vector2;
-use rand::rngs::StdRng;
-use rand::{Rng, SeedableRng};
 use std::mem::size_of;
-use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
 use wgpu::{
-    Backends, CommandEncoderDescriptor, CompositeAlphaMode, DeviceDescriptor, Features, Instance,
-    Limits, Maintain, MaintainBase, PowerPreference, PresentMode, QuerySetDescriptor, QueryType,
-    RequestAdapterOptions, SurfaceConfiguration, TextureUsages, TextureViewDescriptor,
+    Adapter, Backends, CommandEncoderDescriptor, CompositeAlphaMode, Device, DeviceDescriptor,
+    Features, Instance, Limits, PowerPreference, PresentMode, Queue, RequestAdapterOptions,
+    SurfaceConfiguration, TextureUsages, TextureViewDescriptor,
 };
 use winit::event::{DeviceEvent, Event, MouseScrollDelta, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::WindowBuilder;
 
+pub mod benchmark;
+pub mod io;
 pub mod render;
+pub mod runner;
+pub mod scenario;
 pub mod simulation;
+pub mod validation;
 
-/// grid side length
-const GRID_SIZE: f32 = 100.0;
-
-/// cell side length
-const CELL_SIZE: f32 = 2.0;
+#[derive(Parser)]
+struct Args {
+    /// scenario file describing the system and the stages of the run. Runs
+    /// `scenarios/default.toml` if omitted.
+    #[arg(long)]
+    scenario: Option<PathBuf>,
+    /// run the stages without opening a window and exit once they are done
+    #[arg(long)]
+    headless: bool,
+    /// resume the run from a checkpoint written by the scenario's `[checkpoint]` instead of
+    /// generating the atoms. Stages that were completed already are skipped.
+    #[arg(long)]
+    restart: Option<PathBuf>,
+    /// seed of all random numbers, takes precedence over the seed of the scenario. When
+    /// restarting, the seed of the checkpoint is used instead.
+    #[arg(long)]
+    seed: Option<u64>,
+    /// draw the initial velocities from the maxwell-boltzmann distribution at this
+    /// temperature, takes precedence over the `[velocities]` of the scenario
+    #[arg(long)]
+    temperature: Option<f32>,
+    /// time this many steps of the dynamics of the scenario's atoms without a window,
+    /// skipping its stages and outputs, with per-cell and per-atom dispatch, and print the
+    /// throughput of both. Fails if a cell overflows, as per-cell dispatch then skips atoms.
+    #[arg(long)]
+    benchmark: Option<u64>,
+    /// evaluate the coulomb energy of the scenario's atoms on the gpu and with the reference
+    /// implementation on the cpu, print both and fail if they disagree
+    #[arg(long)]
+    check_electrostatics: bool,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let mut rng = StdRng::from_entropy();
+    let args = Args::parse();
+    let overrides = Overrides {
+        seed: args.seed,
+        temperature: args.temperature,
+    };
+    let setup = match &args.scenario {
+        Some(path) => Setup::load(path, overrides)?,
+        None => Setup::parse(DEFAULT_SCENARIO, Path::new("."), overrides)?,
+    };
+    if args.restart.is_none() {
+        eprintln!("seed: {}", setup.seed);
+    }
+
+    if let Some(steps) = args.benchmark {
+        let (device, queue) = headless_device().await?;
+        return benchmark::run(&device, &queue, &setup, steps).await;
+    }
+    if args.check_electrostatics {
+        let (device, queue) = headless_device().await?;
+        return validation::electrostatics(&device, &queue, &setup).await;
+    }
+    if args.headless {
+        return run_headless(setup, args.restart.as_deref()).await;
+    }
 
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new().build(&event_loop)?;
@@ -45,44 +98,11 @@ async fn main() -> Result<()> {
         .await
         .expect("failed to get adapter");
 
-    let (device, queue) = adapter
-        .request_device(
-            &DeviceDescriptor {
-                label: Some("Device"),
-                features: Features::PUSH_CONSTANTS | Features::TIMESTAMP_QUERY,
-                limits: Limits {
-                    max_push_constant_size: size_of::<PushConstants>() as u32,
-                    ..Default::default()
-                },
-            },
-            None,
-        )
-        .await?;
+    let (device, queue) = adapter.request_device(&device_descriptor(), None).await?;
 
     let device = Arc::new(device);
     let queue = Arc::new(queue);
 
-    let hexagonal_lattice = |i: usize, rng: &mut StdRng| -> Vector2<f32> {
-        let n = GRID_SIZE.floor() as usize;
-        Vector2::new(
-            (i % n) as f32 + if (i / n) % 2 == 0 { 0.5 } else { 0.0 },
-            (i / n) as f32 * 3.0f32.sqrt() * 0.5,
-        )
-    };
-
-    let count = (GRID_SIZE / 1.0).floor() as usize
-        * (GRID_SIZE / (3.0f32.sqrt() * 0.5) / 1.0).floor() as usize; // hex
-    let atoms = (0..count)
-        .filter_map(|i| {
-            if rng.gen::<f32>() < 0.01 {
-                //  return None;
-            }
-
-            let pos = hexagonal_lattice(i, &mut rng);
-            Some(Atom::new(pos, Vector2::zeros(), Vector2::zeros()))
-        })
-        .collect::<Vec<_>>();
-
     let texture_format = surface.get_supported_formats(&adapter)[0];
 
     let mut surface_configuration = SurfaceConfiguration {
@@ -95,57 +115,31 @@ async fn main() -> Result<()> {
     };
     surface.configure(&device, &surface_configuration);
 
-    let hash_grid = HashGrid::from_slice(&device, &atoms, GRID_SIZE, CELL_SIZE);
+    let hash_grid = create_hash_grid(&device, &setup, args.restart.as_deref())?;
+    let grid_size = hash_grid.parameters().grid_side_length;
     let mut render_state = RenderState::new(
         &device,
         texture_format,
-        GRID_SIZE,
+        grid_size,
         surface_configuration.width as f32 / surface_configuration.height as f32,
         &queue,
+        hash_grid.options().layout,
     );
 
-    let ib = hash_grid.instance_buffer().clone();
+    let instances = hash_grid.instances();
 
     let running = Arc::new(AtomicBool::new(true));
 
+    let runner = Runner::new(device.clone(), queue.clone(), hash_grid, setup.protocol)?;
     tokio::spawn({
-        let device = device.clone();
-        let queue = queue.clone();
         let running = running.clone();
-
-        let qs = device.create_query_set(&QuerySetDescriptor {
-            label: Some("compute timer"),
-            count: 2,
-            ty: QueryType::Timestamp,
-        });
-
         async move {
-            let mut tick = Arc::new(AtomicU64::new(0));
-            let mut i = 0;
-
-            while running.load(Ordering::Relaxed) {
-                // tokio::time::sleep(Duration::from_millis(500)).await;
-                let mut command_encoder =
-                    device.create_command_encoder(&CommandEncoderDescriptor::default());
-                command_encoder.write_timestamp(&qs, 0);
-                hash_grid.update(&mut command_encoder);
-                command_encoder.write_timestamp(&qs, 1);
-
-                queue.submit(Some(command_encoder.finish()));
-                queue.on_submitted_work_done({
-                    let tick = tick.clone();
-                    move || {
-                        let _ = tick.fetch_add(1, Ordering::Relaxed);
-                    }
-                });
-                i += 1;
-                //println!("{} {}", tick.load(Ordering::Relaxed), i);
-                // while !device.poll(Maintain::Wait) {}
+            if let Err(error) = runner.run(running).await {
+                eprintln!("simulation failed: {error:?}");
             }
         }
     });
 
-    let mut last_frame = Instant::now();
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
             window_id,
@@ -189,7 +183,8 @@ async fn main() -> Result<()> {
             let mut command_encoder =
                 device.create_command_encoder(&CommandEncoderDescriptor::default());
 
-            render_state.render(&mut command_encoder, &view, atoms.len() as u32, &ib);
+            let Instances { buffer, count } = instances.lock().unwrap().clone();
+            render_state.render(&mut command_encoder, &view, count, &buffer);
             queue.submit(Some(command_encoder.finish()));
 
             frame.present();
@@ -200,3 +195,77 @@ async fn main() -> Result<()> {
         _ => {}
     });
 }
+
+fn device_descriptor() -> DeviceDescriptor<'static> {
+    DeviceDescriptor {
+        label: Some("Device"),
+        features: Features::PUSH_CONSTANTS,
+        limits: Limits {
+            max_push_constant_size: size_of::<PushConstants>()
+                .max(size_of::<simulation::hashgrid::PushConstants>())
+                as u32,
+            // the simulation passes with neighbour lists bind more than the default of 8
+            max_storage_buffers_per_shader_stage: 16,
+            ..Default::default()
+        },
+    }
+}
+
+/// the hash grid of the setup, or the one saved in the checkpoint to restart from
+fn create_hash_grid(device: &Device, setup: &Setup, restart: Option<&Path>) -> Result<HashGrid> {
+    match restart {
+        Some(path) => HashGrid::load(device, path, setup.gpu),
+        None => Ok(HashGrid::from_slice(
+            device,
+            &setup.atoms,
+            &setup.properties,
+            &setup.topology,
+            &setup.parameters,
+            setup.seed,
+            setup.gpu,
+        )),
+    }
+}
+
+/// the adapter for running simulations without a window or a surface, if there is one
+async fn headless_adapter() -> Option<Adapter> {
+    Instance::new(Backends::VULKAN)
+        .request_adapter(&RequestAdapterOptions {
+            power_preference: PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        })
+        .await
+}
+
+/// a device for running simulations without a window or a surface
+async fn headless_device() -> Result<(Arc<Device>, Arc<Queue>)> {
+    let adapter = headless_adapter().await.expect("failed to get adapter");
+    let (device, queue) = adapter.request_device(&device_descriptor(), None).await?;
+    Ok((Arc::new(device), Arc::new(queue)))
+}
+
+/// a headless device for tests, or none if the machine has no adapter, in which case the tests
+/// that need one pass without running
+#[cfg(test)]
+pub async fn test_device() -> Option<(Arc<Device>, Arc<Queue>)> {
+    let Some(adapter) = headless_adapter().await else {
+        eprintln!("no adapter, skipping");
+        return None;
+    };
+    let (device, queue) = adapter
+        .request_device(&device_descriptor(), None)
+        .await
+        .expect("failed to get device");
+    Some((Arc::new(device), Arc::new(queue)))
+}
+
+/// runs the stages of the setup without a window or a surface
+async fn run_headless(setup: Setup, restart: Option<&Path>) -> Result<()> {
+    let (device, queue) = headless_device().await?;
+    let hash_grid = create_hash_grid(&device, &setup, restart)?;
+
+    Runner::new(device, queue, hash_grid, setup.protocol)?
+        .run(Arc::new(AtomicBool::new(true)))
+        .await
+}
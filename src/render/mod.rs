@@ -1,3 +1,4 @@
+use crate::simulation::layout::AtomLayout;
 use crate::simulation::Atom;
 use crate::TextureUsages;
 use bytemuck::{Pod, Zeroable};
@@ -15,7 +16,7 @@ use wgpu::{
     RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, SamplerBindingType,
     SamplerDescriptor, ShaderStages, Texture, TextureAspect, TextureDescriptor, TextureDimension,
     TextureFormat, TextureSampleType, TextureView, TextureViewDescriptor, TextureViewDimension,
-    VertexAttribute, VertexBufferLayout, VertexState, VertexStepMode,
+    VertexAttribute, VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
 };
 use winit::dpi::PhysicalSize;
 
@@ -43,12 +44,15 @@ impl Vertex {
 
 pub struct RenderState {
     pipeline: RenderPipeline,
+    /// the layout of the atom buffers the instances are read from
+    layout: AtomLayout,
     vertex_buffer: Buffer,
     index_buffer: Buffer,
     index_count: u32,
     push_constants: PushConstants,
 
-    colormap_tex: Texture,
+    /// only held so the texture outlives `colormap_bg`, which samples it
+    _colormap_tex: Texture,
     colormap_bg: BindGroup,
 }
 
@@ -65,12 +69,32 @@ impl RenderState {
 impl RenderState {
     const VERTEX_COUNT: usize = 25;
 
+    /// the position and the visual of an atom, at their offsets within [`Atom`]
+    const ATOM_ATTRIBS: &'static [VertexAttribute] = &[
+        VertexAttribute {
+            format: VertexFormat::Float32x2,
+            offset: 0,
+            shader_location: 2,
+        },
+        VertexAttribute {
+            format: VertexFormat::Float32,
+            offset: 24,
+            shader_location: 3,
+        },
+    ];
+
+    /// the field of a struct of arrays atom buffer holding the positions, and the one holding
+    /// the visuals followed by the species, see [`AtomLayout`]
+    const POSITION_FIELD: usize = 0;
+    const VISUAL_FIELD: usize = 3;
+
     pub fn new(
         device: &Device,
         surface_format: TextureFormat,
         grid_size: f32,
         aspect_ratio: f32,
         queue: &Queue,
+        layout: AtomLayout,
     ) -> Self {
         let vertex_fragment_shader =
             device.create_shader_module(include_wgsl!("shaders/vertex_fragment.wgsl"));
@@ -135,24 +159,43 @@ impl RenderState {
                 range: 0..size_of::<PushConstants>() as u32,
             }],
         });
+        let vertex_layout = VertexBufferLayout {
+            array_stride: size_of::<Vertex>() as BufferAddress,
+            step_mode: VertexStepMode::Vertex,
+            attributes: Vertex::VERTEX_ATTRS,
+        };
+        // the instances are read from the atom buffer, which holds either whole atoms or one
+        // region per field that is bound as a vertex buffer of its own
+        let vertex_buffers = match layout {
+            AtomLayout::ArrayOfStructs => vec![
+                vertex_layout,
+                VertexBufferLayout {
+                    array_stride: size_of::<Atom>() as BufferAddress,
+                    step_mode: VertexStepMode::Instance,
+                    attributes: Self::ATOM_ATTRIBS,
+                },
+            ],
+            AtomLayout::StructOfArrays => vec![
+                vertex_layout,
+                VertexBufferLayout {
+                    array_stride: layout.field_size() as BufferAddress,
+                    step_mode: VertexStepMode::Instance,
+                    attributes: &vertex_attr_array![2 => Float32x2],
+                },
+                VertexBufferLayout {
+                    array_stride: layout.field_size() as BufferAddress,
+                    step_mode: VertexStepMode::Instance,
+                    attributes: &vertex_attr_array![3 => Float32],
+                },
+            ],
+        };
         let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
             label: Some("Render Pipeline"),
             layout: Some(&render_pipeline_layout),
             vertex: VertexState {
                 module: &vertex_fragment_shader,
                 entry_point: "main_vs",
-                buffers: &[
-                    VertexBufferLayout {
-                        array_stride: size_of::<Vertex>() as BufferAddress,
-                        step_mode: VertexStepMode::Vertex,
-                        attributes: Vertex::VERTEX_ATTRS,
-                    },
-                    VertexBufferLayout {
-                        array_stride: size_of::<Atom>() as BufferAddress,
-                        step_mode: VertexStepMode::Instance,
-                        attributes: Atom::INSTANCE_ATTRIBS,
-                    },
-                ],
+                buffers: &vertex_buffers,
             },
             primitive: PrimitiveState::default(),
             depth_stencil: None,
@@ -223,6 +266,7 @@ impl RenderState {
 
         Self {
             pipeline: render_pipeline,
+            layout,
             vertex_buffer,
             index_buffer,
             index_count: indices.len() as u32,
@@ -231,7 +275,7 @@ impl RenderState {
                 inv_aspect: 1.0 / aspect_ratio,
                 scale: 1.0,
             },
-            colormap_tex,
+            _colormap_tex: colormap_tex,
             colormap_bg,
         }
     }
@@ -263,7 +307,21 @@ impl RenderState {
 
         render_pass.set_pipeline(&self.pipeline);
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+        match self.layout {
+            AtomLayout::ArrayOfStructs => {
+                render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+            }
+            AtomLayout::StructOfArrays => {
+                let capacity = (instance_buffer.size() / size_of::<Atom>() as BufferAddress) as u32;
+                for (slot, field) in [Self::POSITION_FIELD, Self::VISUAL_FIELD]
+                    .into_iter()
+                    .enumerate()
+                {
+                    let range = self.layout.field_range(field, 0..capacity, capacity);
+                    render_pass.set_vertex_buffer(1 + slot as u32, instance_buffer.slice(range));
+                }
+            }
+        }
         render_pass.set_bind_group(0, &self.colormap_bg, &[]);
 
         render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint16);
@@ -2,11 +2,9 @@ struct VertexInput {
     // per vertex inputs
     @location(0) position: vec2<f32>,
     @location(1) color_variable: f32,
-    // per instance inputs
+    // per instance inputs, read straight from the current atom buffer
     @location(2) instance_position: vec2<f32>,
-    @location(3) instance_velocity: vec2<f32>,
-    @location(4) instance_force:    vec2<f32>,
-    @location(5) instance_visual:   f32,
+    @location(3) instance_visual:   f32,
 }
 
 struct VertexOutput {
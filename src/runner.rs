@@ -0,0 +1,654 @@
+use crate::io::{lammps, xyz};
+use crate::simulation::defects;
+use crate::simulation::gcmc::Gcmc;
+use crate::simulation::generators::Shape;
+use crate::simulation::hashgrid::{HashGrid, MAX_NEIGHBOURS};
+use crate::simulation::monte_carlo::MonteCarlo;
+use crate::simulation::ordering::Curve;
+use crate::simulation::readback::{AtomReadback, Frame};
+use crate::simulation::topology::{BondPotential, Topology};
+use crate::simulation::{kinetic_energy_and_temperature, Atom, SimulationParameters};
+use eyre::{bail, Result, WrapErr};
+use nalgebra::Vector2;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use wgpu::{CommandEncoderDescriptor, Device, Maintain, Queue};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StageKind {
+    /// quick-min energy minimization, the thermostat is disabled
+    Minimize,
+    /// dynamics with the thermostat enabled, no outputs are written
+    Equilibrate,
+    /// dynamics with the thermostat enabled if there is one, outputs are written
+    Produce,
+    /// dynamics with the thermostat enabled, interleaved with the grand canonical monte carlo
+    /// cycles of [`Protocol::gcmc`], outputs are written
+    Gcmc,
+    /// metropolis monte carlo sweeps with the settings of [`Protocol::monte_carlo`] instead of
+    /// dynamics, one sweep per time step, outputs are written
+    MonteCarlo,
+}
+
+impl StageKind {
+    fn writes_outputs(self) -> bool {
+        matches!(
+            self,
+            StageKind::Produce | StageKind::Gcmc | StageKind::MonteCarlo
+        )
+    }
+
+    /// whether the [`Event`]s are applied, which only happens during plain dynamics
+    fn applies_events(self) -> bool {
+        matches!(self, StageKind::Equilibrate | StageKind::Produce)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Stage {
+    pub kind: StageKind,
+    /// the amount of time steps, `None` runs until the simulation is stopped
+    pub steps: Option<u64>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Output {
+    /// step, time, temperature, kinetic energy, the amount of atoms and the largest relative
+    /// deviation of a constrained bond from its length, written to `path` or stdout
+    Thermo { every: u64, path: Option<PathBuf> },
+    /// trajectory frames in the extended xyz format
+    Xyz { every: u64, path: PathBuf },
+    /// trajectory frames in the style of lammps' `dump custom`
+    LammpsDump { every: u64, path: PathBuf },
+    /// the position and velocity of the atoms with the given ids, as long as they exist
+    Track {
+        every: u64,
+        path: PathBuf,
+        ids: Vec<u32>,
+    },
+    /// a hash of the atoms and their properties, written to `path` or stdout. Two runs with
+    /// identical hashes at a step are bitwise identical up to that step, see
+    /// [`crate::simulation::hashgrid::GpuOptions::deterministic`].
+    Hash { every: u64, path: Option<PathBuf> },
+}
+
+/// a periodic change of the atoms during the dynamics of equilibration and production stages
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// inserts `count` atoms with the given velocity at random positions in the rectangle
+    /// `origin..origin + size`, at least `min_separation` away from all other atoms
+    Deposit {
+        every: u64,
+        species: u32,
+        count: usize,
+        origin: Vector2<f32>,
+        size: Vector2<f32>,
+        min_separation: f32,
+        velocity: Vector2<f32>,
+    },
+    /// removes the atoms inside the shape
+    Evaporate { every: u64, shape: Shape },
+}
+
+impl Event {
+    pub fn every(&self) -> u64 {
+        match *self {
+            Event::Deposit { every, .. } | Event::Evaporate { every, .. } => every,
+        }
+    }
+}
+
+/// everything a [`Runner`] does besides advancing the simulation
+pub struct Protocol {
+    pub stages: Vec<Stage>,
+    pub outputs: Vec<Output>,
+    pub events: Vec<Event>,
+    pub checkpointing: Option<Checkpointing>,
+    /// required by gcmc stages
+    pub gcmc: Option<Gcmc>,
+    /// required by monte carlo stages
+    pub monte_carlo: Option<MonteCarlo>,
+    pub sorting: Option<Sorting>,
+}
+
+/// periodically reordering the atoms in memory, see [`HashGrid::sort`]
+#[derive(Copy, Clone, Debug)]
+pub struct Sorting {
+    pub every: u64,
+    pub curve: Curve,
+}
+
+/// periodically saving the complete state of the hash grid, see [`HashGrid::save`]
+#[derive(Clone, Debug)]
+pub struct Checkpointing {
+    pub every: u64,
+    pub path: PathBuf,
+}
+
+/// an output together with its open file and the next step it is due at
+struct OutputWriter {
+    output: Output,
+    writer: Box<dyn Write + Send + Sync>,
+    next_step: u64,
+    /// whether the atoms of the pending readback are meant for this output
+    pending: bool,
+}
+
+impl OutputWriter {
+    fn new(output: Output, seed: u64) -> Result<Self> {
+        let writer = match &output {
+            Output::Thermo { path, .. } | Output::Hash { path, .. } => {
+                create_writer(path.as_deref())?
+            }
+            Output::Xyz { path, .. }
+            | Output::LammpsDump { path, .. }
+            | Output::Track { path, .. } => create_writer(Some(path))?,
+        };
+
+        let mut output_writer = Self {
+            output,
+            writer,
+            next_step: 0,
+            pending: false,
+        };
+        output_writer.write_header(seed)?;
+        Ok(output_writer)
+    }
+
+    fn every(&self) -> u64 {
+        match self.output {
+            Output::Thermo { every, .. }
+            | Output::Xyz { every, .. }
+            | Output::LammpsDump { every, .. }
+            | Output::Track { every, .. }
+            | Output::Hash { every, .. } => every,
+        }
+    }
+
+    fn write_header(&mut self, seed: u64) -> Result<()> {
+        match self.output {
+            Output::Thermo { .. } => {
+                writeln!(self.writer, "# seed {seed}")?;
+                writeln!(
+                    self.writer,
+                    "# step time temperature kinetic_energy atoms constraint_violation"
+                )?
+            }
+            Output::Track { .. } => {
+                writeln!(self.writer, "# seed {seed}")?;
+                writeln!(self.writer, "# step id x y vx vy")?
+            }
+            Output::Hash { .. } => {
+                writeln!(self.writer, "# seed {seed}")?;
+                writeln!(self.writer, "# step hash")?
+            }
+            Output::Xyz { .. } | Output::LammpsDump { .. } => {}
+        }
+        Ok(())
+    }
+
+    fn write(
+        &mut self,
+        frame: &Frame,
+        seed: u64,
+        parameters: &SimulationParameters,
+        topology: &Topology,
+    ) -> Result<()> {
+        let Frame {
+            step,
+            atoms,
+            properties,
+        } = frame;
+        let step = *step;
+        match &self.output {
+            Output::Thermo { .. } => {
+                let (kinetic_energy, temperature) = kinetic_energy_and_temperature(
+                    atoms,
+                    &properties.masses,
+                    topology.constraint_count(&parameters.bond_types),
+                );
+                writeln!(
+                    self.writer,
+                    "{} {} {} {} {} {}",
+                    step,
+                    step as f64 * parameters.time_step as f64,
+                    temperature,
+                    kinetic_energy,
+                    atoms.len(),
+                    constraint_violation(frame, parameters, topology)
+                )?;
+            }
+            Output::Xyz { .. } => {
+                xyz::write_frame(&mut self.writer, step, seed, parameters, atoms, properties)?
+            }
+            Output::LammpsDump { .. } => {
+                lammps::write_dump_frame(&mut self.writer, step, parameters, atoms, properties)?
+            }
+            Output::Track { ids, .. } => {
+                for &id in ids {
+                    if let Some((atom, _)) = frame.get(id) {
+                        let (position, velocity) = (atom.position(), atom.velocity());
+                        writeln!(
+                            self.writer,
+                            "{step} {id} {} {} {} {}",
+                            position.x, position.y, velocity.x, velocity.y
+                        )?;
+                    }
+                }
+            }
+            Output::Hash { .. } => writeln!(self.writer, "{step} {:016x}", frame.hash())?,
+        }
+        Ok(())
+    }
+}
+
+/// a buffered writer of the file at `path`, or stdout if there is none
+fn create_writer(path: Option<&Path>) -> Result<Box<dyn Write + Send + Sync>> {
+    Ok(match path {
+        Some(path) => {
+            Box::new(BufWriter::new(File::create(path).wrap_err_with(|| {
+                format!("failed to create output {}", path.display())
+            })?))
+        }
+        None => Box::new(std::io::stdout()),
+    })
+}
+
+/// drives a [`HashGrid`] through the stages of a run and writes its outputs
+pub struct Runner {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    hash_grid: HashGrid,
+    readback: AtomReadback,
+    stages: Vec<Stage>,
+    outputs: Vec<OutputWriter>,
+    /// the events together with the next step they are due at
+    events: Vec<(Event, u64)>,
+    checkpointing: Option<Checkpointing>,
+    gcmc: Option<Gcmc>,
+    monte_carlo: Option<MonteCarlo>,
+    /// where the acceptance ratios of monte carlo stages are reported, see [`MonteCarlo::path`]
+    monte_carlo_report: Option<Box<dyn Write + Send + Sync>>,
+    sorting: Option<Sorting>,
+}
+
+impl Runner {
+    pub fn new(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        hash_grid: HashGrid,
+        protocol: Protocol,
+    ) -> Result<Self> {
+        let readback = AtomReadback::new(&device, &hash_grid);
+        let outputs = protocol
+            .outputs
+            .into_iter()
+            .map(|output| OutputWriter::new(output, hash_grid.seed()))
+            .collect::<Result<Vec<_>>>()?;
+        let monte_carlo_report = match &protocol.monte_carlo {
+            Some(monte_carlo) => {
+                let mut writer = create_writer(monte_carlo.path.as_deref())?;
+                writeln!(writer, "# seed {}", hash_grid.seed())?;
+                writeln!(writer, "# step acceptance max_displacement")?;
+                Some(writer)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            device,
+            queue,
+            hash_grid,
+            readback,
+            stages: protocol.stages,
+            outputs,
+            events: protocol
+                .events
+                .into_iter()
+                .map(|event| (event, 0))
+                .collect(),
+            checkpointing: protocol.checkpointing,
+            gcmc: protocol.gcmc,
+            monte_carlo: protocol.monte_carlo,
+            monte_carlo_report,
+            sorting: protocol.sorting,
+        })
+    }
+
+    /// runs all stages until they are done or `running` is cleared. Note that every update
+    /// advances the simulation by two time steps, so odd step counts are rounded up, except
+    /// in monte carlo stages, which advance by one sweep at a time.
+    ///
+    /// Stages are scheduled by absolute step, so a hash grid restored from a checkpoint skips
+    /// the stages it has already completed and continues the interrupted one.
+    ///
+    /// Outputs are written from asynchronous readbacks: the atoms are copied into a staging
+    /// buffer along with the update that makes an output due, and written once mapping has
+    /// finished, while the following updates are already running. Events on the other hand
+    /// read the atoms back synchronously, as the following updates depend on their changes.
+    pub async fn run(mut self, running: Arc<AtomicBool>) -> Result<()> {
+        let mut next_checkpoint = self
+            .checkpointing
+            .as_ref()
+            .map(|checkpointing| next_multiple(self.hash_grid.step(), checkpointing.every));
+        let mut next_sort = self
+            .sorting
+            .map(|sorting| next_multiple(self.hash_grid.step(), sorting.every));
+
+        let mut stage_start = 0;
+        for stage in std::mem::take(&mut self.stages) {
+            let stage_end = stage.steps.map(|steps| stage_start + steps);
+            if stage_end.is_some_and(|end| self.hash_grid.step() >= end) {
+                stage_start = stage_end.unwrap();
+                continue;
+            }
+
+            self.hash_grid.set_mode(
+                &self.queue,
+                stage.kind == StageKind::Minimize,
+                stage.kind != StageKind::Minimize,
+            );
+
+            let first_step = self.hash_grid.step();
+            for output in &mut self.outputs {
+                output.next_step = first_step;
+            }
+            for (event, next_step) in &mut self.events {
+                *next_step = next_multiple(first_step, event.every());
+            }
+            let mut next_cycle = self
+                .gcmc
+                .as_ref()
+                .filter(|_| stage.kind == StageKind::Gcmc)
+                .map(|gcmc| next_multiple(first_step, gcmc.every));
+
+            // the maximum displacement of monte carlo moves and the next step its acceptance
+            // is reported at
+            let mut monte_carlo = self
+                .monte_carlo
+                .clone()
+                .filter(|_| stage.kind == StageKind::MonteCarlo)
+                .map(|monte_carlo| {
+                    self.hash_grid.set_monte_carlo(
+                        &self.queue,
+                        monte_carlo.temperature,
+                        monte_carlo.max_displacement,
+                        monte_carlo.moves,
+                    );
+                    let next_report = next_multiple(first_step, monte_carlo.every);
+                    (monte_carlo.max_displacement, next_report)
+                });
+
+            while running.load(Ordering::Relaxed)
+                && stage_end.is_none_or(|end| self.hash_grid.step() < end)
+            {
+                let mut command_encoder = self
+                    .device
+                    .create_command_encoder(&CommandEncoderDescriptor::default());
+                if monte_carlo.is_some() {
+                    // a stream per step like deposits, so a restarted run shifts the cells
+                    // the same way
+                    let mut rng =
+                        ChaCha8Rng::seed_from_u64(self.hash_grid.seed() ^ MONTE_CARLO_SEED);
+                    rng.set_stream(self.hash_grid.step());
+                    let offset = Vector2::new(rng.gen::<f32>(), rng.gen::<f32>())
+                        * self.hash_grid.parameters().cell_side_length;
+                    self.hash_grid
+                        .monte_carlo_sweep(&mut command_encoder, offset);
+                } else {
+                    self.hash_grid.update(&mut command_encoder);
+                }
+
+                let step = self.hash_grid.step();
+                let due = stage.kind.writes_outputs()
+                    && self.outputs.iter().any(|output| step >= output.next_step);
+                if due {
+                    if self.readback.is_pending() {
+                        let frame = self.readback.take(&self.device).await?;
+                        self.write_outputs(&frame)?;
+                    }
+
+                    self.readback
+                        .copy(&self.device, &mut command_encoder, &self.hash_grid);
+                    for output in &mut self.outputs {
+                        if step >= output.next_step {
+                            output.pending = true;
+                            output.next_step = next_multiple(step, output.every());
+                        }
+                    }
+                }
+
+                self.queue.submit(Some(command_encoder.finish()));
+                if due {
+                    self.readback.map();
+                }
+
+                if stage.kind.applies_events() {
+                    self.apply_events(step).await?;
+                }
+
+                if let Some((max_displacement, next_report)) = &mut monte_carlo {
+                    if step >= *next_report {
+                        let settings = self.monte_carlo.as_ref().unwrap();
+                        let (attempted, accepted) = self
+                            .hash_grid
+                            .monte_carlo_acceptance(&self.device, &self.queue)
+                            .await?;
+                        let acceptance = accepted as f32 / attempted.max(1) as f32;
+                        if let Some(report) = &mut self.monte_carlo_report {
+                            writeln!(report, "{step} {acceptance} {max_displacement}")?;
+                        }
+
+                        *max_displacement = settings.tune(
+                            *max_displacement,
+                            acceptance,
+                            self.hash_grid.parameters().cell_side_length,
+                        );
+                        self.hash_grid.set_monte_carlo(
+                            &self.queue,
+                            settings.temperature,
+                            *max_displacement,
+                            settings.moves,
+                        );
+                        *next_report = next_multiple(step, settings.every);
+                    }
+                }
+
+                if next_cycle.is_some_and(|next| step >= next) {
+                    let gcmc = self.gcmc.clone().unwrap();
+                    // a stream per step like deposits, but of a different generator
+                    let mut rng = ChaCha8Rng::seed_from_u64(self.hash_grid.seed() ^ GCMC_SEED);
+                    rng.set_stream(step);
+                    gcmc.cycle(&self.device, &self.queue, &mut self.hash_grid, &mut rng)
+                        .await?;
+                    next_cycle = Some(next_multiple(step, gcmc.every));
+                }
+
+                if let Some(sorting) = self
+                    .sorting
+                    .filter(|_| next_sort.is_some_and(|next| step >= next))
+                {
+                    self.hash_grid
+                        .sort(&self.device, &self.queue, sorting.curve)
+                        .await?;
+                    next_sort = Some(next_multiple(step, sorting.every));
+                }
+
+                if next_checkpoint.is_some_and(|next| step >= next) {
+                    self.save_checkpoint().await?;
+                    next_checkpoint = self
+                        .checkpointing
+                        .as_ref()
+                        .map(|checkpointing| next_multiple(step, checkpointing.every));
+                }
+
+                self.device.poll(Maintain::Poll);
+                if let Some(frame) = self.readback.try_take()? {
+                    self.write_outputs(&frame)?;
+                }
+            }
+
+            self.check_neighbour_lists().await?;
+            stage_start = stage_end.unwrap_or(stage_start);
+        }
+
+        if self.readback.is_pending() {
+            let frame = self.readback.take(&self.device).await?;
+            self.write_outputs(&frame)?;
+        }
+
+        for output in &mut self.outputs {
+            output.writer.flush()?;
+        }
+        if let Some(report) = &mut self.monte_carlo_report {
+            report.flush()?;
+        }
+
+        // the final state, so that interrupted runs can be resumed where they stopped
+        self.save_checkpoint().await?;
+
+        Ok(())
+    }
+
+    /// applies the events that are due at `step` to the current atoms
+    async fn apply_events(&mut self, step: u64) -> Result<()> {
+        for index in 0..self.events.len() {
+            let (event, next_step) = &mut self.events[index];
+            if step < *next_step {
+                continue;
+            }
+            *next_step = next_multiple(step, event.every());
+            let event = event.clone();
+
+            let mut atoms = self.hash_grid.read_atoms(&self.device, &self.queue).await?;
+            match event {
+                Event::Deposit {
+                    species,
+                    count,
+                    origin,
+                    size,
+                    min_separation,
+                    velocity,
+                    ..
+                } => {
+                    // a stream per step, so a restarted run deposits the same atoms
+                    let mut rng = ChaCha8Rng::seed_from_u64(self.hash_grid.seed());
+                    rng.set_stream(step);
+
+                    let existing = atoms.len();
+                    defects::interstitials(
+                        &mut atoms,
+                        origin,
+                        size,
+                        count,
+                        min_separation,
+                        species,
+                        &mut rng,
+                    );
+                    let deposited = atoms[existing..]
+                        .iter()
+                        .map(|atom| {
+                            Atom::new(atom.position(), velocity, Vector2::zeros())
+                                .with_species(species)
+                        })
+                        .collect::<Vec<_>>();
+                    self.hash_grid.insert(&self.device, &self.queue, &deposited);
+                }
+                Event::Evaporate { shape, .. } => {
+                    let evaporated = atoms
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, atom)| shape.contains(atom.position()))
+                        .map(|(index, _)| index as u32)
+                        .collect::<Vec<_>>();
+                    self.hash_grid
+                        .remove(&self.device, &self.queue, &evaporated);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// fails if any neighbour list was cut off, as the run then missed interactions
+    async fn check_neighbour_lists(&self) -> Result<()> {
+        let overflows = self
+            .hash_grid
+            .neighbour_overflows(&self.device, &self.queue)
+            .await?;
+        if overflows > 0 {
+            bail!(
+                "{overflows} neighbour lists had more than {MAX_NEIGHBOURS} atoms in range and \
+                 missed interactions, use a smaller `neighbour_list.skin`"
+            );
+        }
+        Ok(())
+    }
+
+    /// saves a checkpoint if checkpointing is enabled, but never of a run that missed
+    /// interactions
+    async fn save_checkpoint(&self) -> Result<()> {
+        if let Some(checkpointing) = &self.checkpointing {
+            self.check_neighbour_lists().await?;
+            self.hash_grid
+                .save(&self.device, &self.queue, &checkpointing.path)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// writes the atoms of a finished readback to the outputs that were due when it started
+    fn write_outputs(&mut self, frame: &Frame) -> Result<()> {
+        let parameters = self.hash_grid.parameters();
+        let topology = self.hash_grid.topology();
+        let seed = self.hash_grid.seed();
+        for output in self.outputs.iter_mut().filter(|output| output.pending) {
+            output.write(frame, seed, parameters, topology)?;
+            output.pending = false;
+        }
+
+        Ok(())
+    }
+}
+
+/// the largest relative deviation of a constrained bond from its length in the frame, zero if
+/// there are none. Bonds of atoms that were removed since the frame was read back are skipped.
+fn constraint_violation(
+    frame: &Frame,
+    parameters: &SimulationParameters,
+    topology: &Topology,
+) -> f32 {
+    topology
+        .bonds
+        .iter()
+        .filter_map(|bond| {
+            let BondPotential::Constraint { length } = parameters.bond_types[bond.kind as usize]
+            else {
+                return None;
+            };
+            let (a, _) = frame.get(bond.a)?;
+            let (b, _) = frame.get(bond.b)?;
+            let distance = parameters.separation(a.position(), b.position()).norm();
+            Some((distance - length).abs() / length)
+        })
+        .fold(0.0, f32::max)
+}
+
+/// mixed into the seed of the run for the random numbers of gcmc cycles, so they are
+/// independent of those of deposit events at the same step
+const GCMC_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// like [`GCMC_SEED`], for the cell offsets of monte carlo sweeps
+const MONTE_CARLO_SEED: u64 = 0xbf58_476d_1ce4_e5b9;
+
+/// the first multiple of `every` after `step`
+fn next_multiple(step: u64, every: u64) -> u64 {
+    step - step % every + every
+}
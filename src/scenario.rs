@@ -0,0 +1,2084 @@
+use crate::io::force_field;
+use crate::io::lammps::DataFile;
+use crate::runner::{Checkpointing, Event, Output, Protocol, Sorting, Stage, StageKind};
+use crate::simulation::defects;
+use crate::simulation::ewald::{Ewald, Reciprocal, MAX_K, MAX_MESH, MESH_ORDERS};
+use crate::simulation::external::{ExternalForces, HarmonicTrap};
+use crate::simulation::gcmc::Gcmc;
+use crate::simulation::generators::{self, Architecture, PolymerGenerator, Shape, Walk};
+use crate::simulation::hashgrid::{GpuOptions, MAX_NEIGHBOURS};
+use crate::simulation::monte_carlo::MonteCarlo;
+use crate::simulation::ordering::Curve;
+use crate::simulation::properties::Properties;
+use crate::simulation::topology::{
+    Angle, AnglePotential, Bond, BondPotential, ConstraintSolver, Topology, MAX_ANGLES, MAX_BONDS,
+};
+use crate::simulation::{
+    whole_cells, Atom, Boundary, LennardJones, SimulationParameters, Species, Thermostat,
+};
+use eyre::{eyre, Result, WrapErr};
+use nalgebra::Vector2;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+/// the scenario that is run if no scenario file is given
+pub const DEFAULT_SCENARIO: &str = include_str!("../scenarios/default.toml");
+
+/// everything needed to set up and run a simulation, as described by a scenario file. A scenario
+/// is a toml file describing the simulated system and the stages of the run, see
+/// `scenarios/default.toml` for an annotated example.
+pub struct Setup {
+    pub parameters: SimulationParameters,
+    pub atoms: Vec<Atom>,
+    pub properties: Properties,
+    pub topology: Topology,
+    pub protocol: Protocol,
+    /// the seed of the run, which determines all random numbers on the cpu and the gpu
+    pub seed: u64,
+    /// how the hash grid runs on the gpu
+    pub gpu: GpuOptions,
+}
+
+/// settings given on the command line, which take precedence over the scenario
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Overrides {
+    /// if neither this nor the scenario gives a seed, a random seed is chosen
+    pub seed: Option<u64>,
+    /// the temperature of the initial velocities, see the `[velocities]` section
+    pub temperature: Option<f32>,
+}
+
+impl Setup {
+    pub fn load(path: &Path, overrides: Overrides) -> Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read scenario {}", path.display()))?;
+        let directory = path.parent().unwrap_or(Path::new("."));
+        Self::parse(&source, directory, overrides)
+            .wrap_err_with(|| format!("invalid scenario {}", path.display()))
+    }
+
+    /// parses a scenario, input files it refers to are looked up relative to `directory`
+    pub fn parse(source: &str, directory: &Path, overrides: Overrides) -> Result<Self> {
+        let scenario = toml::from_str::<Scenario>(source)?;
+        scenario.into_setup(directory, overrides)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Scenario {
+    seed: Option<u64>,
+    #[serde(rename = "box")]
+    simulation_box: BoxSection,
+    #[serde(default)]
+    boundaries: BoundariesSection,
+    species: Vec<SpeciesSection>,
+    #[serde(default)]
+    potentials: Vec<PotentialSection>,
+    generators: Vec<GeneratorSection>,
+    #[serde(default)]
+    properties: Vec<PropertiesSection>,
+    #[serde(default)]
+    bond_types: Vec<BondTypeSection>,
+    #[serde(default)]
+    bonds: Vec<BondSection>,
+    #[serde(default)]
+    angle_types: Vec<AngleTypeSection>,
+    #[serde(default)]
+    angles: Vec<AngleSection>,
+    #[serde(default)]
+    integrator: IntegratorSection,
+    #[serde(default)]
+    gpu: GpuOptions,
+    neighbour_list: Option<NeighbourListSection>,
+    constraints: Option<ConstraintsSection>,
+    electrostatics: Option<ElectrostaticsSection>,
+    #[serde(default)]
+    external_forces: Vec<ExternalForceSection>,
+    velocities: Option<VelocitiesSection>,
+    thermostat: Option<ThermostatSection>,
+    #[serde(default)]
+    outputs: Vec<OutputSection>,
+    #[serde(default)]
+    events: Vec<EventSection>,
+    sorting: Option<SortingSection>,
+    checkpoint: Option<CheckpointSection>,
+    gcmc: Option<GcmcSection>,
+    monte_carlo: Option<MonteCarloSection>,
+    stages: Vec<StageSection>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BoxSection {
+    side_length: f32,
+    cell_size: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BoundariesSection {
+    x: BoundaryKind,
+    y: BoundaryKind,
+}
+
+impl Default for BoundariesSection {
+    fn default() -> Self {
+        Self {
+            x: BoundaryKind::Periodic,
+            y: BoundaryKind::Periodic,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BoundaryKind {
+    Open,
+    Periodic,
+    Reflective,
+}
+
+impl From<BoundaryKind> for Boundary {
+    fn from(kind: BoundaryKind) -> Self {
+        match kind {
+            BoundaryKind::Open => Boundary::Open,
+            BoundaryKind::Periodic => Boundary::Periodic,
+            BoundaryKind::Reflective => Boundary::Reflective,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpeciesSection {
+    name: String,
+    mass: f32,
+    #[serde(default)]
+    charge: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum PotentialSection {
+    LennardJones {
+        species: [String; 2],
+        epsilon: f32,
+        sigma: f32,
+        /// defaults to `2.5 * sigma`
+        cutoff: Option<f32>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum GeneratorSection {
+    SquareLattice(LatticeSection),
+    HexagonalLattice(LatticeSection),
+    HoneycombLattice(LatticeSection),
+    RandomGas {
+        species: String,
+        count: usize,
+        min_separation: f32,
+        origin: Option<[f32; 2]>,
+        size: Option<[f32; 2]>,
+        shape: Option<ShapeSection>,
+    },
+    PoissonDisk {
+        species: String,
+        min_separation: f32,
+        origin: Option<[f32; 2]>,
+        size: Option<[f32; 2]>,
+        shape: Option<ShapeSection>,
+    },
+    /// removes the atoms of the previous generators that lie inside the shape
+    Void {
+        shape: ShapeSection,
+    },
+    /// removes a random fraction of the previously generated atoms
+    Vacancies {
+        fraction: f32,
+        /// only atoms of this species are removed
+        species: Option<String>,
+    },
+    /// turns a random fraction of the previously generated atoms into `species`
+    Substitution {
+        species: String,
+        fraction: f32,
+        /// only atoms of this species are replaced
+        replaces: Option<String>,
+    },
+    /// inserts atoms at random positions at least `min_separation` away from all other atoms
+    Interstitials {
+        species: String,
+        count: usize,
+        min_separation: f32,
+        origin: Option<[f32; 2]>,
+        size: Option<[f32; 2]>,
+    },
+    /// displaces the previously generated atoms by the field of an edge dislocation
+    EdgeDislocation {
+        core: [f32; 2],
+        /// the length of the burgers vector, which points along x
+        burgers: f32,
+        /// defaults to 1/3
+        poisson_ratio: Option<f32>,
+    },
+    /// two lattices rotated by `angles` in degrees, left and right of the middle of the region
+    GrainBoundary {
+        lattice: LatticeKind,
+        species: String,
+        spacing: f32,
+        angles: [f32; 2],
+        origin: Option<[f32; 2]>,
+        size: Option<[f32; 2]>,
+    },
+    /// `count` polymers of `species`, grown as walks with bonds of `bond_type` that are
+    /// `bond_length` long
+    Polymers {
+        species: String,
+        count: usize,
+        architecture: ArchitectureSection,
+        /// defaults to a random walk
+        walk: Option<WalkSection>,
+        bond_type: String,
+        bond_length: f32,
+        origin: Option<[f32; 2]>,
+        size: Option<[f32; 2]>,
+    },
+    /// atoms and velocities of a lammps data file, shifted so `xlo ylo` is the box origin
+    LammpsData {
+        path: PathBuf,
+        /// the species of every lammps atom type, in order
+        types: Vec<String>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LatticeSection {
+    species: String,
+    /// the nearest neighbour distance
+    spacing: f32,
+    /// defaults to the origin of the box
+    origin: Option<[f32; 2]>,
+    /// defaults to the rest of the box
+    size: Option<[f32; 2]>,
+    /// cuts the lattice to the shape
+    shape: Option<ShapeSection>,
+    /// radius of a random displacement applied to every atom
+    jitter: Option<f32>,
+}
+
+#[derive(Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum LatticeKind {
+    Square,
+    Hexagonal,
+    Honeycomb,
+}
+
+impl LatticeKind {
+    fn generator(self) -> fn(Vector2<f32>, Vector2<f32>, f32, u32) -> Vec<Atom> {
+        match self {
+            LatticeKind::Square => generators::square_lattice,
+            LatticeKind::Hexagonal => generators::hexagonal_lattice,
+            LatticeKind::Honeycomb => generators::honeycomb_lattice,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum ShapeSection {
+    Droplet { center: [f32; 2], radius: f32 },
+    Slab { axis: Axis, from: f32, to: f32 },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum ArchitectureSection {
+    Linear { length: usize },
+    Ring { length: usize },
+    Star { arms: usize, arm_length: usize },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum WalkSection {
+    Random,
+    SelfAvoiding { min_separation: f32 },
+}
+
+#[derive(Copy, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Axis {
+    X,
+    Y,
+}
+
+/// overrides the default properties of the generated atoms, of all of them or only of those of
+/// one species or inside a rectangle
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PropertiesSection {
+    species: Option<String>,
+    origin: Option<[f32; 2]>,
+    size: Option<[f32; 2]>,
+    charge: Option<f32>,
+    mass: Option<f32>,
+    /// adds the atoms to the group with this index
+    group: Option<u32>,
+    flags: Option<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum BondTypeSection {
+    Harmonic {
+        name: String,
+        k: f32,
+        length: f32,
+    },
+    Fene {
+        name: String,
+        k: f32,
+        max_length: f32,
+    },
+    Constraint {
+        name: String,
+        length: f32,
+    },
+}
+
+/// bonds between the atoms with the given ids, consecutive ids are bonded
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BondSection {
+    #[serde(rename = "type")]
+    bond_type: String,
+    ids: Vec<u32>,
+}
+
+/// angle types, with the rest angle in degrees
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum AngleTypeSection {
+    Harmonic { name: String, k: f32, angle: f32 },
+    Cosine { name: String, k: f32, angle: f32 },
+}
+
+/// angles between the atoms with the given ids, every three consecutive ids form an angle at
+/// the middle one
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AngleSection {
+    #[serde(rename = "type")]
+    angle_type: String,
+    ids: Vec<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IntegratorSection {
+    time_step: f32,
+}
+
+impl Default for IntegratorSection {
+    fn default() -> Self {
+        Self {
+            time_step: crate::simulation::DELTA_T,
+        }
+    }
+}
+
+/// verlet neighbour lists instead of scanning the cells in every time step
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NeighbourListSection {
+    skin: f32,
+}
+
+/// the settings of the constraint solver, see [`ConstraintSolver`]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConstraintsSection {
+    tolerance: Option<f32>,
+    max_iterations: Option<u32>,
+}
+
+/// the long-range coulomb interactions of the charges, see [`Ewald`]
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum ElectrostaticsSection {
+    Ewald {
+        cutoff: f32,
+        alpha: Option<f32>,
+        k_max: Option<u32>,
+    },
+    Pme {
+        cutoff: f32,
+        alpha: Option<f32>,
+        mesh: Option<u32>,
+        order: Option<u32>,
+    },
+}
+
+/// a force of a field outside the system, see [`ExternalForces`]. Fields of the same kind add
+/// up, except for force fields, of which there is at most one.
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum ExternalForceSection {
+    Gravity {
+        acceleration: [f32; 2],
+    },
+    ElectricField {
+        field: [f32; 2],
+    },
+    HarmonicTrap {
+        center: [f32; 2],
+        stiffness: f32,
+        species: Option<String>,
+    },
+    ForceField {
+        path: PathBuf,
+    },
+}
+
+/// initial velocities drawn from the maxwell-boltzmann distribution, replacing the velocities
+/// of the generators
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VelocitiesSection {
+    temperature: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum ThermostatSection {
+    Langevin { temperature: f32, damping: f32 },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum OutputSection {
+    Thermo {
+        every: u64,
+        path: Option<PathBuf>,
+    },
+    Xyz {
+        every: u64,
+        path: PathBuf,
+    },
+    LammpsDump {
+        every: u64,
+        path: PathBuf,
+    },
+    Track {
+        every: u64,
+        path: PathBuf,
+        ids: Vec<u32>,
+    },
+    Hash {
+        every: u64,
+        path: Option<PathBuf>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+enum EventSection {
+    Deposit {
+        every: u64,
+        species: String,
+        count: usize,
+        min_separation: f32,
+        origin: Option<[f32; 2]>,
+        size: Option<[f32; 2]>,
+        velocity: Option<[f32; 2]>,
+    },
+    Evaporate {
+        every: u64,
+        shape: ShapeSection,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SortingSection {
+    every: u64,
+    curve: Curve,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CheckpointSection {
+    every: u64,
+    path: PathBuf,
+}
+
+/// grand canonical monte carlo moves of one species during gcmc stages
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GcmcSection {
+    species: String,
+    chemical_potential: f32,
+    temperature: f32,
+    attempts: usize,
+    every: u64,
+    origin: Option<[f32; 2]>,
+    size: Option<[f32; 2]>,
+}
+
+/// metropolis monte carlo sweeps during monte carlo stages
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MonteCarloSection {
+    temperature: f32,
+    max_displacement: f32,
+    moves: Option<u32>,
+    every: u64,
+    target_acceptance: Option<f32>,
+    path: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StageSection {
+    kind: StageKind,
+    /// may only be omitted for the last stage, which then runs until it is interrupted
+    steps: Option<u64>,
+}
+
+/// an error about the value of the given key
+fn invalid(key: impl Display, message: impl Display) -> eyre::Report {
+    eyre!("invalid value for `{key}`: {message}")
+}
+
+fn ensure_fraction(key: impl Display, value: f32) -> Result<()> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(())
+    } else {
+        Err(invalid(
+            key,
+            format!("expected a number between 0 and 1, got {value}"),
+        ))
+    }
+}
+
+fn ensure_positive(key: impl Display, value: f32) -> Result<()> {
+    if value > 0.0 && value.is_finite() {
+        Ok(())
+    } else {
+        Err(invalid(
+            key,
+            format!("expected a positive number, got {value}"),
+        ))
+    }
+}
+
+/// the rectangle a generator fills, defaulting to the entire box
+fn region(
+    key: impl Fn(&str) -> String,
+    origin: Option<[f32; 2]>,
+    size: Option<[f32; 2]>,
+    side_length: f32,
+) -> Result<(Vector2<f32>, Vector2<f32>)> {
+    let origin = Vector2::from(origin.unwrap_or([0.0; 2]));
+    let size = size
+        .map(Vector2::from)
+        .unwrap_or_else(|| Vector2::repeat(side_length) - origin);
+    let end = origin + size;
+    if origin.min() < 0.0 || end.max() > side_length || size.min() <= 0.0 {
+        return Err(invalid(
+            key("size"),
+            "the generated region must be non-empty and lie inside the box",
+        ));
+    }
+    Ok((origin, size))
+}
+
+impl ShapeSection {
+    fn into_shape(self, key: impl Fn(&str) -> String) -> Result<Shape> {
+        match self {
+            ShapeSection::Droplet { center, radius } => {
+                ensure_positive(key("shape.radius"), radius)?;
+                Ok(Shape::Droplet {
+                    center: center.into(),
+                    radius,
+                })
+            }
+            ShapeSection::Slab { axis, from, to } => {
+                if from >= to {
+                    return Err(invalid(key("shape.to"), "must be greater than `from`"));
+                }
+                Ok(Shape::Slab {
+                    axis: axis as usize,
+                    from,
+                    to,
+                })
+            }
+        }
+    }
+}
+
+impl ArchitectureSection {
+    fn into_architecture(self, key: impl Fn(&str) -> String) -> Result<Architecture> {
+        match self {
+            ArchitectureSection::Linear { length } => {
+                if length < 2 {
+                    return Err(invalid(key("architecture.length"), "must be at least 2"));
+                }
+                Ok(Architecture::Linear { length })
+            }
+            ArchitectureSection::Ring { length } => {
+                if length < 3 {
+                    return Err(invalid(key("architecture.length"), "must be at least 3"));
+                }
+                Ok(Architecture::Ring { length })
+            }
+            ArchitectureSection::Star { arms, arm_length } => {
+                // every arm is bonded to the center
+                if !(1..=MAX_BONDS).contains(&arms) {
+                    return Err(invalid(
+                        key("architecture.arms"),
+                        format!("must be between 1 and {MAX_BONDS}"),
+                    ));
+                }
+                if arm_length == 0 {
+                    return Err(invalid(
+                        key("architecture.arm_length"),
+                        "must be at least 1",
+                    ));
+                }
+                Ok(Architecture::Star { arms, arm_length })
+            }
+        }
+    }
+}
+
+impl WalkSection {
+    fn into_walk(self, key: impl Fn(&str) -> String, bond_length: f32) -> Result<Walk> {
+        match self {
+            WalkSection::Random => Ok(Walk::Random),
+            WalkSection::SelfAvoiding { min_separation } => {
+                ensure_positive(key("walk.min_separation"), min_separation)?;
+                if min_separation >= bond_length {
+                    return Err(invalid(
+                        key("walk.min_separation"),
+                        "must be less than `bond_length`",
+                    ));
+                }
+                Ok(Walk::SelfAvoiding { min_separation })
+            }
+        }
+    }
+}
+
+impl LatticeSection {
+    /// the atoms of the lattice created by `generate`, cut to the shape and jittered
+    fn generate(
+        self,
+        generate: impl Fn(Vector2<f32>, Vector2<f32>, f32, u32) -> Vec<Atom>,
+        key: impl Fn(&str) -> String,
+        species_id: impl Fn(String, &str) -> Result<u32>,
+        side_length: f32,
+        rng: &mut impl Rng,
+    ) -> Result<Vec<Atom>> {
+        let species = species_id(key("species"), &self.species)?;
+        ensure_positive(key("spacing"), self.spacing)?;
+        let (origin, size) = region(&key, self.origin, self.size, side_length)?;
+
+        let mut atoms = generate(origin, size, self.spacing, species);
+        if let Some(shape) = self.shape {
+            atoms = generators::carve(atoms, &shape.into_shape(&key)?);
+        }
+        if let Some(radius) = self.jitter {
+            ensure_positive(key("jitter"), radius)?;
+            generators::jitter(&mut atoms, radius, rng);
+        }
+        Ok(atoms)
+    }
+}
+
+impl Scenario {
+    fn into_setup(self, directory: &Path, overrides: Overrides) -> Result<Setup> {
+        let seed = overrides
+            .seed
+            .or(self.seed)
+            .unwrap_or_else(|| rand::thread_rng().gen());
+        // chacha rather than `StdRng`, whose algorithm may change between versions of rand
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+        let side_length = self.simulation_box.side_length;
+        let cell_size = self.simulation_box.cell_size;
+        ensure_positive("box.side_length", side_length)?;
+        ensure_positive("box.cell_size", cell_size)?;
+
+        let boundaries = [self.boundaries.x.into(), self.boundaries.y.into()];
+        let cells = side_length / cell_size;
+        for (axis, boundary) in ["x", "y"].into_iter().zip(boundaries) {
+            if boundary == Boundary::Periodic && cells < 3.0 {
+                return Err(invalid(
+                    format!("boundaries.{axis}"),
+                    "periodic boundaries need at least three cells per side",
+                ));
+            }
+            // a partial last cell would be a neighbour of the first one across the boundary,
+            // although their atoms may be further apart than the stencil reaches
+            if boundary == Boundary::Periodic && !whole_cells(cells) {
+                return Err(invalid(
+                    format!("boundaries.{axis}"),
+                    format!(
+                        "periodic boundaries need `box.side_length` {side_length} to be a whole \
+                         multiple of `box.cell_size` {cell_size}"
+                    ),
+                ));
+            }
+        }
+
+        if self.species.is_empty() {
+            return Err(eyre!("at least one `[[species]]` is required"));
+        }
+        let mut species_ids = HashMap::new();
+        let mut species = Vec::with_capacity(self.species.len());
+        for (index, section) in self.species.into_iter().enumerate() {
+            ensure_positive(format!("species[{index}].mass"), section.mass)?;
+            if species_ids
+                .insert(section.name.clone(), index as u32)
+                .is_some()
+            {
+                return Err(invalid(
+                    format!("species[{index}].name"),
+                    format!("species `{}` is defined twice", section.name),
+                ));
+            }
+            species.push(Species {
+                name: section.name,
+                mass: section.mass,
+                charge: section.charge,
+            });
+        }
+        let species_id = |key: String, name: &str| {
+            species_ids
+                .get(name)
+                .copied()
+                .ok_or_else(|| invalid(key, format!("unknown species `{name}`")))
+        };
+
+        let pair_potentials = {
+            let n = species.len();
+            let mut pairs = vec![None; n * n];
+            for (index, section) in self.potentials.into_iter().enumerate() {
+                let key = |field: &str| format!("potentials[{index}].{field}");
+                match section {
+                    PotentialSection::LennardJones {
+                        species: [a, b],
+                        epsilon,
+                        sigma,
+                        cutoff,
+                    } => {
+                        let a = species_id(key("species"), &a)? as usize;
+                        let b = species_id(key("species"), &b)? as usize;
+                        ensure_positive(key("epsilon"), epsilon)?;
+                        ensure_positive(key("sigma"), sigma)?;
+                        let cutoff = cutoff.unwrap_or(2.5 * sigma);
+                        ensure_positive(key("cutoff"), cutoff)?;
+                        if cutoff > cell_size {
+                            return Err(invalid(
+                                key("cutoff"),
+                                format!("the cutoff {cutoff} exceeds `box.cell_size` {cell_size}"),
+                            ));
+                        }
+
+                        let lj = LennardJones {
+                            epsilon,
+                            sigma,
+                            cutoff,
+                        };
+                        pairs[a * n + b] = Some(lj);
+                        pairs[b * n + a] = Some(lj);
+                    }
+                }
+            }
+
+            for a in 0..n {
+                if pairs[a * n + a].is_none() {
+                    return Err(eyre!(
+                        "missing `[[potentials]]` entry for species pair (`{0}`, `{0}`)",
+                        species[a].name
+                    ));
+                }
+            }
+
+            (0..n * n)
+                .map(|i| {
+                    let (a, b) = (i / n, i % n);
+                    pairs[i].unwrap_or_else(|| {
+                        let (aa, bb) = (pairs[a * n + a].unwrap(), pairs[b * n + b].unwrap());
+                        aa.mix(&bb)
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut bond_type_ids = HashMap::new();
+        let mut bond_types = Vec::with_capacity(self.bond_types.len());
+        for (index, section) in self.bond_types.into_iter().enumerate() {
+            let key = |field: &str| format!("bond_types[{index}].{field}");
+            let (name, bond_type) = match section {
+                BondTypeSection::Harmonic { name, k, length } => {
+                    ensure_positive(key("k"), k)?;
+                    ensure_positive(key("length"), length)?;
+                    (name, BondPotential::Harmonic { k, length })
+                }
+                BondTypeSection::Fene {
+                    name,
+                    k,
+                    max_length,
+                } => {
+                    ensure_positive(key("k"), k)?;
+                    ensure_positive(key("max_length"), max_length)?;
+                    (name, BondPotential::Fene { k, max_length })
+                }
+                BondTypeSection::Constraint { name, length } => {
+                    ensure_positive(key("length"), length)?;
+                    (name, BondPotential::Constraint { length })
+                }
+            };
+            if bond_type_ids
+                .insert(name.clone(), bond_types.len() as u32)
+                .is_some()
+            {
+                return Err(invalid(
+                    key("name"),
+                    format!("bond type `{name}` is defined twice"),
+                ));
+            }
+            bond_types.push(bond_type);
+        }
+
+        let mut angle_type_ids = HashMap::new();
+        let mut angle_types = Vec::with_capacity(self.angle_types.len());
+        for (index, section) in self.angle_types.into_iter().enumerate() {
+            let key = |field: &str| format!("angle_types[{index}].{field}");
+            let (name, angle_type) = match section {
+                AngleTypeSection::Harmonic { name, k, angle } => {
+                    let angle = angle.to_radians();
+                    (name, AnglePotential::Harmonic { k, angle })
+                }
+                AngleTypeSection::Cosine { name, k, angle } => {
+                    let angle = angle.to_radians();
+                    (name, AnglePotential::Cosine { k, angle })
+                }
+            };
+            ensure_positive(key("k"), angle_type.k())?;
+            if !(0.0..=std::f32::consts::PI).contains(&angle_type.angle()) {
+                return Err(invalid(key("angle"), "must be between 0 and 180 degrees"));
+            }
+            if angle_type_ids
+                .insert(name.clone(), angle_types.len() as u32)
+                .is_some()
+            {
+                return Err(invalid(
+                    key("name"),
+                    format!("angle type `{name}` is defined twice"),
+                ));
+            }
+            angle_types.push(angle_type);
+        }
+
+        let mut constraint_solver = ConstraintSolver::default();
+        if let Some(section) = self.constraints {
+            if let Some(tolerance) = section.tolerance {
+                ensure_positive("constraints.tolerance", tolerance)?;
+                constraint_solver.tolerance = tolerance;
+            }
+            if let Some(max_iterations) = section.max_iterations {
+                if max_iterations == 0 {
+                    return Err(invalid("constraints.max_iterations", "must be at least 1"));
+                }
+                constraint_solver.max_iterations = max_iterations;
+            }
+        }
+
+        let neighbour_skin = match self.neighbour_list {
+            Some(NeighbourListSection { skin }) => {
+                ensure_positive("neighbour_list.skin", skin)?;
+                // the lists are built from the neighbouring cells
+                let cutoff = pair_potentials
+                    .iter()
+                    .map(|pair| pair.cutoff)
+                    .fold(0.0, f32::max);
+                if cutoff + skin > cell_size {
+                    return Err(invalid(
+                        "neighbour_list.skin",
+                        format!(
+                            "the largest cutoff {cutoff} plus the skin exceeds `box.cell_size` {cell_size}"
+                        ),
+                    ));
+                }
+                Some(skin)
+            }
+            None => None,
+        };
+
+        let electrostatics = match self.electrostatics {
+            Some(section) => {
+                let (ElectrostaticsSection::Ewald { cutoff, alpha, .. }
+                | ElectrostaticsSection::Pme { cutoff, alpha, .. }) = section;
+                let key = |field: &str| format!("electrostatics.{field}");
+                if boundaries
+                    .iter()
+                    .any(|&boundary| boundary != Boundary::Periodic)
+                {
+                    return Err(invalid(
+                        "electrostatics.kind",
+                        "ewald summation requires periodic boundaries along both axes",
+                    ));
+                }
+                ensure_positive(key("cutoff"), cutoff)?;
+                // the real space sum runs along with the lennard-jones forces
+                if cutoff + neighbour_skin.unwrap_or(0.0) > cell_size {
+                    let message = match neighbour_skin {
+                        Some(_) => "plus `neighbour_list.skin` exceeds",
+                        None => "exceeds",
+                    };
+                    return Err(invalid(
+                        key("cutoff"),
+                        format!("the cutoff {cutoff} {message} `box.cell_size` {cell_size}"),
+                    ));
+                }
+                // erfc(3) is about 2e-5, which both sums are truncated at by default
+                let alpha = alpha.unwrap_or(3.0 / cutoff);
+                ensure_positive(key("alpha"), alpha)?;
+                let default_k_max =
+                    (3.0 * alpha * side_length / std::f32::consts::PI).ceil() as u32;
+                let reciprocal = match section {
+                    ElectrostaticsSection::Ewald { k_max, .. } => {
+                        let k_max = k_max.unwrap_or(default_k_max);
+                        if !(1..=MAX_K).contains(&k_max) {
+                            return Err(invalid(
+                                key("k_max"),
+                                format!("expected a number between 1 and {MAX_K}, got {k_max}"),
+                            ));
+                        }
+                        Reciprocal::Direct { k_max }
+                    }
+                    ElectrostaticsSection::Pme { mesh, order, .. } => {
+                        // the mesh resolves the wave vectors up to half its size
+                        let size = mesh.unwrap_or_else(|| {
+                            (2 * default_k_max).next_power_of_two().clamp(4, MAX_MESH)
+                        });
+                        if !size.is_power_of_two() || !(4..=MAX_MESH).contains(&size) {
+                            return Err(invalid(
+                                key("mesh"),
+                                format!(
+                                    "expected a power of two between 4 and {MAX_MESH}, got {size}"
+                                ),
+                            ));
+                        }
+                        let order = order.unwrap_or(4);
+                        if !MESH_ORDERS.contains(&order) || order > size {
+                            return Err(invalid(
+                                key("order"),
+                                format!(
+                                    "expected a number between {} and {}, got {order}",
+                                    MESH_ORDERS.start(),
+                                    MESH_ORDERS.end().min(&size),
+                                ),
+                            ));
+                        }
+                        // every mesh point gathers its charges from the cells around it
+                        let support = order as f32 * side_length / size as f32;
+                        if support > 2.0 * cell_size {
+                            return Err(invalid(
+                                key("mesh"),
+                                format!(
+                                    "the b-splines of order {order} span {support} on a mesh of \
+                                     size {size}, more than twice `box.cell_size` {cell_size}"
+                                ),
+                            ));
+                        }
+                        Reciprocal::Mesh { size, order }
+                    }
+                };
+                Some(Ewald {
+                    alpha,
+                    cutoff,
+                    reciprocal,
+                })
+            }
+            None => None,
+        };
+
+        if let Some(skin) = neighbour_skin {
+            // atoms packed as closely as the smallest sigma allows put 2π/√3 (range / sigma)²
+            // others in range of an atom, which the lists must have room for
+            let coulomb_cutoff = electrostatics.as_ref().map_or(0.0, |ewald| ewald.cutoff);
+            let range = pair_potentials
+                .iter()
+                .map(|pair| pair.cutoff.max(coulomb_cutoff) + skin)
+                .fold(0.0, f32::max);
+            let sigma = pair_potentials
+                .iter()
+                .map(|pair| pair.sigma)
+                .fold(f32::INFINITY, f32::min);
+            let packed = 2.0 * std::f32::consts::PI / 3f32.sqrt() * (range / sigma).powi(2);
+            if packed > MAX_NEIGHBOURS as f32 {
+                return Err(invalid(
+                    "neighbour_list.skin",
+                    format!(
+                        "closely packed atoms would have about {packed:.0} others within the \
+                         cutoff plus the skin, more than the {MAX_NEIGHBOURS} the lists hold"
+                    ),
+                ));
+            }
+        }
+
+        let mut external_forces = ExternalForces::default();
+        for (index, section) in self.external_forces.into_iter().enumerate() {
+            let key = |field: &str| format!("external_forces[{index}].{field}");
+            let ensure_finite = |field: &str, vector: [f32; 2]| {
+                if vector.iter().all(|value| value.is_finite()) {
+                    Ok(Vector2::from(vector))
+                } else {
+                    Err(invalid(key(field), "expected finite components"))
+                }
+            };
+            match section {
+                ExternalForceSection::Gravity { acceleration } => {
+                    external_forces.gravity += ensure_finite("acceleration", acceleration)?;
+                }
+                ExternalForceSection::ElectricField { field } => {
+                    external_forces.electric_field += ensure_finite("field", field)?;
+                }
+                ExternalForceSection::HarmonicTrap {
+                    center,
+                    stiffness,
+                    species,
+                } => {
+                    let center = ensure_finite("center", center)?;
+                    if center.min() < 0.0 || center.max() > side_length {
+                        return Err(invalid(key("center"), "the center must lie inside the box"));
+                    }
+                    ensure_positive(key("stiffness"), stiffness)?;
+                    let species = species
+                        .map(|name| species_id(key("species"), &name))
+                        .transpose()?;
+                    external_forces.traps.push(HarmonicTrap {
+                        center,
+                        stiffness,
+                        species,
+                    });
+                }
+                ExternalForceSection::ForceField { path } => {
+                    if external_forces.force_field.is_some() {
+                        return Err(invalid(key("kind"), "there is at most one force field"));
+                    }
+                    let field = force_field::read(&directory.join(path))
+                        .wrap_err_with(|| format!("failed to load `{}`", key("path")))?;
+                    external_forces.force_field = Some(field);
+                }
+            }
+        }
+
+        let mut atoms = Vec::new();
+        // the bonds of generated polymers, by the index of the atoms in `atoms`
+        let mut generated_bonds = Vec::new();
+        for (index, section) in self.generators.into_iter().enumerate() {
+            let key = |field: &str| format!("generators[{index}].{field}");
+            let generated_before = atoms.len();
+            match section {
+                GeneratorSection::SquareLattice(lattice) => atoms.extend(lattice.generate(
+                    generators::square_lattice,
+                    key,
+                    species_id,
+                    side_length,
+                    &mut rng,
+                )?),
+                GeneratorSection::HexagonalLattice(lattice) => atoms.extend(lattice.generate(
+                    generators::hexagonal_lattice,
+                    key,
+                    species_id,
+                    side_length,
+                    &mut rng,
+                )?),
+                GeneratorSection::HoneycombLattice(lattice) => atoms.extend(lattice.generate(
+                    generators::honeycomb_lattice,
+                    key,
+                    species_id,
+                    side_length,
+                    &mut rng,
+                )?),
+                GeneratorSection::RandomGas {
+                    species,
+                    count,
+                    min_separation,
+                    origin,
+                    size,
+                    shape,
+                } => {
+                    let species = species_id(key("species"), &species)?;
+                    ensure_positive(key("min_separation"), min_separation)?;
+                    let (origin, size) = region(key, origin, size, side_length)?;
+                    let shape = shape.map(|shape| shape.into_shape(key)).transpose()?;
+
+                    let generated = generators::random_gas(
+                        origin,
+                        size,
+                        count,
+                        min_separation,
+                        species,
+                        shape.as_ref(),
+                        &mut rng,
+                    );
+                    if generated.len() < count {
+                        return Err(invalid(
+                            key("count"),
+                            format!(
+                                "only {} of {count} atoms could be placed {min_separation} apart",
+                                generated.len()
+                            ),
+                        ));
+                    }
+                    atoms.extend(generated);
+                }
+                GeneratorSection::PoissonDisk {
+                    species,
+                    min_separation,
+                    origin,
+                    size,
+                    shape,
+                } => {
+                    let species = species_id(key("species"), &species)?;
+                    ensure_positive(key("min_separation"), min_separation)?;
+                    let (origin, size) = region(key, origin, size, side_length)?;
+
+                    let mut generated =
+                        generators::poisson_disk(origin, size, min_separation, species, &mut rng);
+                    if let Some(shape) = shape {
+                        generated = generators::carve(generated, &shape.into_shape(key)?);
+                    }
+                    atoms.extend(generated);
+                }
+                GeneratorSection::Void { shape } => {
+                    atoms = generators::void(atoms, &shape.into_shape(key)?);
+                }
+                GeneratorSection::Vacancies { fraction, species } => {
+                    ensure_fraction(key("fraction"), fraction)?;
+                    let species = species
+                        .map(|name| species_id(key("species"), &name))
+                        .transpose()?;
+                    atoms = defects::vacancies(atoms, fraction, species, &mut rng);
+                }
+                GeneratorSection::Substitution {
+                    species,
+                    fraction,
+                    replaces,
+                } => {
+                    let species = species_id(key("species"), &species)?;
+                    ensure_fraction(key("fraction"), fraction)?;
+                    let replaces = replaces
+                        .map(|name| species_id(key("replaces"), &name))
+                        .transpose()?;
+                    defects::substitute(&mut atoms, fraction, replaces, species, &mut rng);
+                }
+                GeneratorSection::Interstitials {
+                    species,
+                    count,
+                    min_separation,
+                    origin,
+                    size,
+                } => {
+                    let species = species_id(key("species"), &species)?;
+                    ensure_positive(key("min_separation"), min_separation)?;
+                    let (origin, size) = region(key, origin, size, side_length)?;
+
+                    let inserted = defects::interstitials(
+                        &mut atoms,
+                        origin,
+                        size,
+                        count,
+                        min_separation,
+                        species,
+                        &mut rng,
+                    );
+                    if inserted < count {
+                        return Err(invalid(
+                            key("count"),
+                            format!(
+                                "only {inserted} of {count} atoms could be inserted \
+                                 {min_separation} away from all other atoms"
+                            ),
+                        ));
+                    }
+                }
+                GeneratorSection::EdgeDislocation {
+                    core,
+                    burgers,
+                    poisson_ratio,
+                } => {
+                    ensure_positive(key("burgers"), burgers)?;
+                    let poisson_ratio = poisson_ratio.unwrap_or(1.0 / 3.0);
+                    if !(-1.0..0.5).contains(&poisson_ratio) {
+                        return Err(invalid(key("poisson_ratio"), "must lie between -1 and 0.5"));
+                    }
+                    defects::edge_dislocation(&mut atoms, core.into(), burgers, poisson_ratio);
+                }
+                GeneratorSection::GrainBoundary {
+                    lattice,
+                    species,
+                    spacing,
+                    angles,
+                    origin,
+                    size,
+                } => {
+                    let species = species_id(key("species"), &species)?;
+                    ensure_positive(key("spacing"), spacing)?;
+                    let (origin, size) = region(key, origin, size, side_length)?;
+
+                    atoms.extend(defects::grain_boundary(
+                        origin,
+                        size,
+                        spacing,
+                        species,
+                        angles.map(f32::to_radians),
+                        lattice.generator(),
+                    ));
+                }
+                GeneratorSection::Polymers {
+                    species,
+                    count,
+                    architecture,
+                    walk,
+                    bond_type,
+                    bond_length,
+                    origin,
+                    size,
+                } => {
+                    let species = species_id(key("species"), &species)?;
+                    let kind = bond_type_ids.get(&bond_type).copied().ok_or_else(|| {
+                        invalid(key("bond_type"), format!("unknown bond type `{bond_type}`"))
+                    })?;
+                    ensure_positive(key("bond_length"), bond_length)?;
+                    match bond_types[kind as usize] {
+                        BondPotential::Fene { max_length, .. } if bond_length >= max_length => {
+                            return Err(invalid(
+                                key("bond_length"),
+                                format!("must be less than the maximum length {max_length}"),
+                            ));
+                        }
+                        BondPotential::Constraint { length } if bond_length != length => {
+                            return Err(invalid(
+                                key("bond_length"),
+                                format!("must equal the length {length} of the constraint"),
+                            ));
+                        }
+                        _ => {}
+                    }
+                    let architecture = architecture.into_architecture(key)?;
+                    let walk =
+                        walk.map_or(Ok(Walk::Random), |walk| walk.into_walk(key, bond_length))?;
+                    let (origin, size) = region(key, origin, size, side_length)?;
+
+                    let generator = PolymerGenerator {
+                        architecture,
+                        walk,
+                        bond_length,
+                        species,
+                    };
+                    let generated = generator.generate(origin, size, count, &mut rng);
+                    let grown = generated.atoms.len() / architecture.atom_count();
+                    if grown < count {
+                        return Err(invalid(
+                            key("count"),
+                            format!(
+                                "only {grown} of {count} polymers could be grown in the region"
+                            ),
+                        ));
+                    }
+                    let offset = atoms.len() as u32;
+                    generated_bonds.extend(generated.bonds.into_iter().map(|[a, b]| Bond {
+                        a: offset + a as u32,
+                        b: offset + b as u32,
+                        kind,
+                    }));
+                    atoms.extend(generated.atoms);
+                }
+                GeneratorSection::LammpsData { path, types } => {
+                    let data = DataFile::read(&directory.join(path))
+                        .wrap_err_with(|| format!("failed to load `{}`", key("path")))?;
+                    if types.len() != data.masses.len() {
+                        return Err(invalid(
+                            key("types"),
+                            format!(
+                                "expected a species for each of the {} atom types",
+                                data.masses.len()
+                            ),
+                        ));
+                    }
+                    let types = types
+                        .iter()
+                        .map(|name| species_id(key("types"), name))
+                        .collect::<Result<Vec<_>>>()?;
+                    for (atom_type, (&id, &mass)) in types.iter().zip(&data.masses).enumerate() {
+                        let expected = species[id as usize].mass;
+                        if (mass - expected).abs() > 1e-6 * expected {
+                            return Err(invalid(
+                                key("types"),
+                                format!(
+                                    "atom type {} has mass {mass}, but species `{}` has mass {expected}",
+                                    atom_type + 1,
+                                    species[id as usize].name
+                                ),
+                            ));
+                        }
+                    }
+
+                    let extent = data.hi - data.lo;
+                    for (axis, boundary) in boundaries.iter().enumerate() {
+                        let fits = match boundary {
+                            Boundary::Periodic => (extent[axis] - side_length).abs() < 1e-4,
+                            Boundary::Open | Boundary::Reflective => extent[axis] <= side_length,
+                        };
+                        if !fits {
+                            return Err(invalid(
+                                key("path"),
+                                format!(
+                                    "the box of the data file has size {} along {}, which does \
+                                     not fit `box.side_length` {side_length}",
+                                    extent[axis],
+                                    ["x", "y"][axis]
+                                ),
+                            ));
+                        }
+                    }
+
+                    atoms.extend(data.atoms.iter().map(|atom| {
+                        Atom::new(atom.position - data.lo, atom.velocity, Vector2::zeros())
+                            .with_species(types[atom.atom_type as usize])
+                    }));
+                }
+            }
+            // the bonds refer to the atoms by their index, which removing atoms would shift
+            if atoms.len() < generated_before && !generated_bonds.is_empty() {
+                return Err(invalid(
+                    key("kind"),
+                    "atoms cannot be removed after polymers were generated",
+                ));
+            }
+        }
+        if atoms.is_empty() {
+            return Err(invalid(
+                "generators",
+                "the generators did not create any atoms",
+            ));
+        }
+
+        let mut properties = Properties::new(&atoms, &species, 0);
+        for (index, section) in self.properties.into_iter().enumerate() {
+            let key = |field: &str| format!("properties[{index}].{field}");
+            let species = section
+                .species
+                .map(|name| species_id(key("species"), &name))
+                .transpose()?;
+            let region = match (section.origin, section.size) {
+                (None, None) => None,
+                (origin, size) => Some(region(key, origin, size, side_length)?),
+            };
+            if let Some(mass) = section.mass {
+                ensure_positive(key("mass"), mass)?;
+            }
+            if section.group.is_some_and(|group| group >= u32::BITS) {
+                return Err(invalid(
+                    key("group"),
+                    format!("must be less than {}", u32::BITS),
+                ));
+            }
+
+            for (slot, atom) in atoms.iter().enumerate() {
+                let inside = region.is_none_or(|(origin, size)| {
+                    let relative = atom.position() - origin;
+                    (0.0..size.x).contains(&relative.x) && (0.0..size.y).contains(&relative.y)
+                });
+                if !inside || species.is_some_and(|species| atom.species() != species) {
+                    continue;
+                }
+
+                let mut atom_properties = properties.get(slot);
+                atom_properties.charge = section.charge.unwrap_or(atom_properties.charge);
+                atom_properties.mass = section.mass.unwrap_or(atom_properties.mass);
+                atom_properties.groups |= section.group.map_or(0, |group| 1 << group);
+                atom_properties.flags = section.flags.unwrap_or(atom_properties.flags);
+                properties.set(slot, atom_properties);
+            }
+        }
+
+        if electrostatics.is_some() {
+            let total = properties.charges.iter().map(|&q| q as f64).sum::<f64>();
+            let magnitude = properties
+                .charges
+                .iter()
+                .map(|&q| q.abs() as f64)
+                .sum::<f64>();
+            if total.abs() > 1e-4 * magnitude {
+                return Err(eyre!(
+                    "ewald summation requires a neutral system, but the atoms have a total charge of {total}"
+                ));
+            }
+        }
+
+        ensure_positive("integrator.time_step", self.integrator.time_step)?;
+
+        let thermostat = match self.thermostat {
+            Some(ThermostatSection::Langevin {
+                temperature,
+                damping,
+            }) => {
+                ensure_positive("thermostat.temperature", temperature)?;
+                ensure_positive("thermostat.damping", damping)?;
+                Some(Thermostat::Langevin {
+                    temperature,
+                    damping,
+                })
+            }
+            None => None,
+        };
+
+        let outputs = self
+            .outputs
+            .into_iter()
+            .enumerate()
+            .map(|(index, section)| {
+                let (every, output) = match section {
+                    OutputSection::Thermo { every, path } => {
+                        (every, Output::Thermo { every, path })
+                    }
+                    OutputSection::Xyz { every, path } => (every, Output::Xyz { every, path }),
+                    OutputSection::LammpsDump { every, path } => {
+                        (every, Output::LammpsDump { every, path })
+                    }
+                    OutputSection::Track { every, path, ids } => {
+                        (every, Output::Track { every, path, ids })
+                    }
+                    OutputSection::Hash { every, path } => (every, Output::Hash { every, path }),
+                };
+                if every == 0 {
+                    return Err(invalid(format!("outputs[{index}].every"), "must not be 0"));
+                }
+                Ok(output)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let events = self
+            .events
+            .into_iter()
+            .enumerate()
+            .map(|(index, section)| {
+                let key = |field: &str| format!("events[{index}].{field}");
+                let event = match section {
+                    EventSection::Deposit {
+                        every,
+                        species,
+                        count,
+                        min_separation,
+                        origin,
+                        size,
+                        velocity,
+                    } => {
+                        let species = species_id(key("species"), &species)?;
+                        ensure_positive(key("min_separation"), min_separation)?;
+                        let (origin, size) = region(key, origin, size, side_length)?;
+                        Event::Deposit {
+                            every,
+                            species,
+                            count,
+                            origin,
+                            size,
+                            min_separation,
+                            velocity: Vector2::from(velocity.unwrap_or([0.0; 2])),
+                        }
+                    }
+                    EventSection::Evaporate { every, shape } => Event::Evaporate {
+                        every,
+                        shape: shape.into_shape(key)?,
+                    },
+                };
+                if event.every() == 0 {
+                    return Err(invalid(key("every"), "must not be 0"));
+                }
+                Ok(event)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let checkpointing = match self.checkpoint {
+            Some(CheckpointSection { every: 0, .. }) => {
+                return Err(invalid("checkpoint.every", "must not be 0"))
+            }
+            Some(CheckpointSection { every, path }) => Some(Checkpointing { every, path }),
+            None => None,
+        };
+
+        let sorting = match self.sorting {
+            Some(SortingSection { every: 0, .. }) => {
+                return Err(invalid("sorting.every", "must not be 0"))
+            }
+            Some(SortingSection { every, curve }) => Some(Sorting { every, curve }),
+            None => None,
+        };
+
+        let gcmc = match self.gcmc {
+            Some(GcmcSection {
+                species,
+                chemical_potential,
+                temperature,
+                attempts,
+                every,
+                origin,
+                size,
+            }) => {
+                let key = |field: &str| format!("gcmc.{field}");
+                let species = species_id(key("species"), &species)?;
+                ensure_positive(key("temperature"), temperature)?;
+                if every == 0 {
+                    return Err(invalid(key("every"), "must not be 0"));
+                }
+                let (origin, size) = region(key, origin, size, side_length)?;
+                Some(Gcmc {
+                    species,
+                    chemical_potential,
+                    temperature,
+                    attempts,
+                    every,
+                    origin,
+                    size,
+                })
+            }
+            None => None,
+        };
+
+        let monte_carlo = match self.monte_carlo {
+            Some(MonteCarloSection {
+                temperature,
+                max_displacement,
+                moves,
+                every,
+                target_acceptance,
+                path,
+            }) => {
+                let key = |field: &str| format!("monte_carlo.{field}");
+                ensure_positive(key("temperature"), temperature)?;
+                ensure_positive(key("max_displacement"), max_displacement)?;
+                if max_displacement > cell_size {
+                    return Err(invalid(
+                        key("max_displacement"),
+                        format!("must not exceed `box.cell_size` {cell_size}"),
+                    ));
+                }
+                if moves == Some(0) {
+                    return Err(invalid(key("moves"), "must not be 0"));
+                }
+                if every == 0 {
+                    return Err(invalid(key("every"), "must not be 0"));
+                }
+                if let Some(target) = target_acceptance {
+                    if !(target > 0.0 && target < 1.0) {
+                        return Err(invalid(
+                            key("target_acceptance"),
+                            format!("expected a number between 0 and 1, got {target}"),
+                        ));
+                    }
+                }
+                Some(MonteCarlo {
+                    temperature,
+                    max_displacement,
+                    moves: moves.unwrap_or(1),
+                    every,
+                    target_acceptance,
+                    path,
+                })
+            }
+            None => None,
+        };
+        // the checkerboard of monte carlo sweeps needs an even amount of cells along periodic
+        // axes, so that cells of the same color never touch across the boundary
+        let checkerboard = boundaries.iter().all(|boundary| {
+            *boundary != Boundary::Periodic || (cells.round() as u64).is_multiple_of(2)
+        });
+
+        if self.stages.is_empty() {
+            return Err(eyre!("at least one `[[stages]]` entry is required"));
+        }
+        let last_stage = self.stages.len() - 1;
+        let stages = self
+            .stages
+            .into_iter()
+            .enumerate()
+            .map(|(index, section)| {
+                match section.steps {
+                    Some(0) => {
+                        return Err(invalid(format!("stages[{index}].steps"), "must not be 0"))
+                    }
+                    None if index != last_stage => {
+                        return Err(invalid(
+                            format!("stages[{index}].steps"),
+                            "only the last stage may run indefinitely",
+                        ))
+                    }
+                    _ => {}
+                }
+                if section.kind == StageKind::Equilibrate && thermostat.is_none() {
+                    return Err(invalid(
+                        format!("stages[{index}].kind"),
+                        "equilibration requires a `[thermostat]`",
+                    ));
+                }
+                if section.kind == StageKind::MonteCarlo {
+                    if monte_carlo.is_none() {
+                        return Err(invalid(
+                            format!("stages[{index}].kind"),
+                            "monte carlo stages require a `[monte_carlo]` section",
+                        ));
+                    }
+                    if !checkerboard {
+                        return Err(invalid(
+                            format!("stages[{index}].kind"),
+                            "monte carlo stages require `box.side_length` to be an even multiple of `box.cell_size` along periodic axes",
+                        ));
+                    }
+                }
+                // the energies of trial moves leave out the coulomb interactions
+                if matches!(section.kind, StageKind::MonteCarlo | StageKind::Gcmc)
+                    && electrostatics.is_some()
+                {
+                    return Err(invalid(
+                        format!("stages[{index}].kind"),
+                        "monte carlo and gcmc stages do not support `[electrostatics]`",
+                    ));
+                }
+                // and the external forces
+                if matches!(section.kind, StageKind::MonteCarlo | StageKind::Gcmc)
+                    && !external_forces.is_empty()
+                {
+                    return Err(invalid(
+                        format!("stages[{index}].kind"),
+                        "monte carlo and gcmc stages do not support `[[external_forces]]`",
+                    ));
+                }
+                if section.kind == StageKind::Gcmc && (thermostat.is_none() || gcmc.is_none()) {
+                    return Err(invalid(
+                        format!("stages[{index}].kind"),
+                        "gcmc stages require a `[thermostat]` and a `[gcmc]` section",
+                    ));
+                }
+
+                Ok(Stage {
+                    kind: section.kind,
+                    steps: section.steps,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let parameters = SimulationParameters {
+            grid_side_length: side_length,
+            cell_side_length: cell_size,
+            time_step: self.integrator.time_step,
+            boundaries,
+            species,
+            pair_potentials,
+            bond_types,
+            angle_types,
+            constraint_solver,
+            thermostat,
+            neighbour_skin,
+            electrostatics,
+            external_forces,
+        };
+
+        // the atoms have the ids 0 to n - 1 at this point
+        let mut bonded = HashSet::new();
+        let mut bond_counts = vec![0; atoms.len()];
+        for bond in &generated_bonds {
+            bonded.insert((bond.a.min(bond.b), bond.a.max(bond.b)));
+            bond_counts[bond.a as usize] += 1;
+            bond_counts[bond.b as usize] += 1;
+        }
+        let mut topology = Topology {
+            bonds: generated_bonds,
+            angles: Vec::new(),
+        };
+        for (index, section) in self.bonds.into_iter().enumerate() {
+            let key = |field: &str| format!("bonds[{index}].{field}");
+            let kind = bond_type_ids
+                .get(&section.bond_type)
+                .copied()
+                .ok_or_else(|| {
+                    invalid(
+                        key("type"),
+                        format!("unknown bond type `{}`", section.bond_type),
+                    )
+                })?;
+            if section.ids.len() < 2 {
+                return Err(invalid(key("ids"), "a bond needs at least two atoms"));
+            }
+            for pair in section.ids.windows(2) {
+                let (a, b) = (pair[0], pair[1]);
+                if let Some(&id) = [a, b].iter().find(|&&id| id as usize >= atoms.len()) {
+                    return Err(invalid(key("ids"), format!("there is no atom {id}")));
+                }
+                if a == b || !bonded.insert((a.min(b), a.max(b))) {
+                    return Err(invalid(
+                        key("ids"),
+                        format!("atoms {a} and {b} can not be bonded twice or to themselves"),
+                    ));
+                }
+                for id in [a, b] {
+                    bond_counts[id as usize] += 1;
+                    if bond_counts[id as usize] > MAX_BONDS {
+                        return Err(invalid(
+                            key("ids"),
+                            format!("atom {id} has more than {MAX_BONDS} bonds"),
+                        ));
+                    }
+                }
+                if let BondPotential::Fene { max_length, .. } = parameters.bond_types[kind as usize]
+                {
+                    let length = parameters
+                        .separation(atoms[a as usize].position(), atoms[b as usize].position())
+                        .norm();
+                    if length >= max_length {
+                        return Err(invalid(
+                            key("ids"),
+                            format!(
+                                "atoms {a} and {b} are {length} apart, which exceeds the \
+                                 maximum length {max_length} of their bond"
+                            ),
+                        ));
+                    }
+                }
+                topology.bonds.push(Bond { a, b, kind });
+            }
+        }
+
+        let mut angled = HashSet::new();
+        let mut angle_counts = vec![0; atoms.len()];
+        for (index, section) in self.angles.into_iter().enumerate() {
+            let key = |field: &str| format!("angles[{index}].{field}");
+            let kind = angle_type_ids
+                .get(&section.angle_type)
+                .copied()
+                .ok_or_else(|| {
+                    invalid(
+                        key("type"),
+                        format!("unknown angle type `{}`", section.angle_type),
+                    )
+                })?;
+            if section.ids.len() < 3 {
+                return Err(invalid(key("ids"), "an angle needs at least three atoms"));
+            }
+            for triple in section.ids.windows(3) {
+                let (a, b, c) = (triple[0], triple[1], triple[2]);
+                if let Some(&id) = triple.iter().find(|&&id| id as usize >= atoms.len()) {
+                    return Err(invalid(key("ids"), format!("there is no atom {id}")));
+                }
+                // an angle and its reverse are the same angle
+                if a == b || b == c || a == c || !angled.insert((a.min(c), b, a.max(c))) {
+                    return Err(invalid(
+                        key("ids"),
+                        format!(
+                            "the angle between atoms {a}, {b} and {c} is defined twice or \
+                             repeats an atom"
+                        ),
+                    ));
+                }
+                for id in [a, b, c] {
+                    angle_counts[id as usize] += 1;
+                    if angle_counts[id as usize] > MAX_ANGLES {
+                        return Err(invalid(
+                            key("ids"),
+                            format!("atom {id} takes part in more than {MAX_ANGLES} angles"),
+                        ));
+                    }
+                }
+                topology.angles.push(Angle { a, b, c, kind });
+            }
+        }
+
+        let temperature = overrides
+            .temperature
+            .or(self.velocities.map(|velocities| velocities.temperature));
+        if let Some(temperature) = temperature {
+            ensure_positive("velocities.temperature", temperature)?;
+            generators::maxwell_boltzmann(
+                &mut atoms,
+                &properties.masses,
+                topology.constraint_count(&parameters.bond_types),
+                temperature,
+                &mut rng,
+            );
+        }
+
+        Ok(Setup {
+            parameters,
+            atoms,
+            properties,
+            topology,
+            protocol: Protocol {
+                stages,
+                outputs,
+                events,
+                checkpointing,
+                gcmc,
+                monte_carlo,
+                sorting,
+            },
+            seed,
+            gpu: self.gpu,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::{kinetic_energy_and_temperature, DELTA_T};
+
+    /// a gas of 50 atoms in a periodic box, the smallest scenario with every required section
+    const GAS: &str = r#"
+        [box]
+        side_length = 20.0
+        cell_size = 2.5
+        [[species]]
+        name = "Ar"
+        mass = 1.0
+        [[generators]]
+        kind = "random_gas"
+        species = "Ar"
+        count = 50
+        min_separation = 1.0
+        [[potentials]]
+        kind = "lennard_jones"
+        species = ["Ar", "Ar"]
+        epsilon = 1.0
+        sigma = 1.0
+        [[stages]]
+        kind = "produce"
+        steps = 10
+    "#;
+
+    fn parse(source: &str, overrides: Overrides) -> Result<Setup> {
+        Setup::parse(source, Path::new("."), overrides)
+    }
+
+    fn error(source: &str) -> String {
+        match parse(source, Overrides::default()) {
+            Ok(_) => panic!("expected an error"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn fills_in_the_defaults() {
+        let setup = parse(&format!("seed = 7\n{GAS}"), Overrides::default()).unwrap();
+        assert_eq!(setup.seed, 7);
+        assert_eq!(setup.atoms.len(), 50);
+        assert_eq!(setup.parameters.boundaries, [Boundary::Periodic; 2]);
+        assert_eq!(setup.parameters.time_step, DELTA_T);
+        assert_eq!(setup.parameters.pair_potentials[0].cutoff, 2.5);
+        assert!(setup.parameters.neighbour_skin.is_none());
+        assert!(setup.parameters.electrostatics.is_none());
+        assert!(!setup.gpu.deterministic);
+        assert_eq!(setup.protocol.stages.len(), 1);
+        // without `[velocities]`, the generated atoms are at rest
+        assert!(setup
+            .atoms
+            .iter()
+            .all(|atom| atom.velocity() == Vector2::zeros()));
+    }
+
+    #[test]
+    fn applies_the_overrides() {
+        let overrides = Overrides {
+            seed: Some(11),
+            temperature: Some(2.0),
+        };
+        let setup = parse(&format!("seed = 7\n{GAS}"), overrides).unwrap();
+        assert_eq!(setup.seed, 11);
+        let (_, temperature) = kinetic_energy_and_temperature(
+            &setup.atoms,
+            &setup.properties.masses,
+            setup
+                .topology
+                .constraint_count(&setup.parameters.bond_types),
+        );
+        assert!((temperature - 2.0).abs() < 1e-4, "{temperature}");
+
+        // the same seed generates the same atoms
+        let again = parse(&format!("seed = 11\n{GAS}"), Overrides::default()).unwrap();
+        let positions = |setup: &Setup| {
+            setup
+                .atoms
+                .iter()
+                .map(|atom| atom.position())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(positions(&setup), positions(&again));
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        for (from, to, message) in [
+            ("[box]", "colour = 1\n[box]", "unknown field `colour`"),
+            (
+                "cell_size = 2.5",
+                "cell_size = 2.5\ndepth = 1.0",
+                "unknown field `depth`",
+            ),
+            (
+                "mass = 1.0",
+                "mass = 1.0\nradius = 0.5",
+                "unknown field `radius`",
+            ),
+            (
+                "count = 50",
+                "count = 50\nspacing = 1.0",
+                "unknown field `spacing`",
+            ),
+            (
+                "steps = 10",
+                "steps = 10\nrepeat = 2",
+                "unknown field `repeat`",
+            ),
+            (
+                "\"random_gas\"",
+                "\"random_gass\"",
+                "unknown variant `random_gass`",
+            ),
+            ("[box]", "[boxes]", "unknown field `boxes`"),
+            ("[[stages]]", "[[stage]]", "unknown field `stage`"),
+            (
+                "[[potentials]]",
+                "[[potential]]",
+                "unknown field `potential`",
+            ),
+            ("epsilon = 1.0", "", "missing field `epsilon`"),
+        ] {
+            let source = GAS.replacen(from, to, 1);
+            let error = error(&source);
+            assert!(error.contains(message), "{from} -> {to}: {error}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let sections = |extra: &str| format!("{GAS}\n{extra}");
+        let bonds = r#"
+            [[bond_types]]
+            kind = "harmonic"
+            name = "spring"
+            k = 100.0
+            length = 1.0
+        "#;
+        let angles = r#"
+            [[angle_types]]
+            kind = "cosine"
+            name = "stiff"
+            k = 5.0
+            angle = 180.0
+        "#;
+        let ewald = "[electrostatics]\nkind = \"ewald\"\ncutoff = 2.5";
+        for (source, message) in [
+            (
+                GAS.replace("side_length = 20.0", "side_length = -1.0"),
+                "`box.side_length`: expected a positive number",
+            ),
+            (
+                GAS.replace("cell_size = 2.5", "cell_size = 3.0"),
+                "`boundaries.x`: periodic boundaries need `box.side_length` 20 to be a whole",
+            ),
+            (
+                GAS.replace("cell_size = 2.5", "cell_size = 10.0"),
+                "periodic boundaries need at least three cells per side",
+            ),
+            (
+                GAS.replace("sigma = 1.0", "sigma = 1.0\ncutoff = 3.0"),
+                "`potentials[0].cutoff`: the cutoff 3 exceeds `box.cell_size` 2.5",
+            ),
+            (
+                GAS.replace("species = [\"Ar\", \"Ar\"]", "species = [\"Ar\", \"Xe\"]"),
+                "unknown species `Xe`",
+            ),
+            (
+                GAS.replace("kind = \"produce\"", "kind = \"equilibrate\""),
+                "`stages[0].kind`: equilibration requires a `[thermostat]`",
+            ),
+            (
+                GAS.replace("steps = 10", "steps = 0"),
+                "`stages[0].steps`: must not be 0",
+            ),
+            (
+                sections("[[outputs]]\nkind = \"thermo\"\nevery = 0"),
+                "`outputs[0].every`: must not be 0",
+            ),
+            (
+                sections("[velocities]\ntemperature = -1.0"),
+                "`velocities.temperature`: expected a positive number",
+            ),
+            (
+                sections("[neighbour_list]\nskin = 0.5"),
+                "the largest cutoff 2.5 plus the skin exceeds `box.cell_size` 2.5",
+            ),
+            (
+                GAS.replace("cell_size = 2.5", "cell_size = 5.0") + "[neighbour_list]\nskin = 2.0",
+                "closely packed atoms would have about 73 others",
+            ),
+            (
+                GAS.replace("mass = 1.0", "mass = 1.0\ncharge = 1.0") + ewald,
+                "ewald summation requires a neutral system",
+            ),
+            (
+                sections(&format!(
+                    "[boundaries]\nx = \"periodic\"\ny = \"reflective\"\n{ewald}"
+                )),
+                "ewald summation requires periodic boundaries along both axes",
+            ),
+            (
+                sections("[electrostatics]\nkind = \"pme\"\ncutoff = 2.5\nmesh = 48"),
+                "`electrostatics.mesh`: expected a power of two between 4 and",
+            ),
+            (
+                sections(&format!(
+                    "{bonds}\n[[bonds]]\ntype = \"spring\"\nids = [0, 999]"
+                )),
+                "`bonds[0].ids`: there is no atom 999",
+            ),
+            (
+                sections(&format!(
+                    "{bonds}\n[[bonds]]\ntype = \"rope\"\nids = [0, 1]"
+                )),
+                "`bonds[0].type`",
+            ),
+            (
+                sections(&format!(
+                    "{angles}\n[[angles]]\ntype = \"stiff\"\nids = [0, 1]"
+                )),
+                "`angles[0].ids`: an angle needs at least three atoms",
+            ),
+            (
+                GAS.replace("kind = \"produce\"", "kind = \"monte_carlo\""),
+                "monte carlo stages require a `[monte_carlo]` section",
+            ),
+        ] {
+            let error = error(&source);
+            assert!(
+                error.contains(message),
+                "expected `{message}`, got `{error}`"
+            );
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use crate::simulation::Atom;
+use nalgebra::{Rotation2, Vector2};
+use rand::seq::index;
+use rand::Rng;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+/// the atoms closer than this fraction of the lattice spacing to an atom of the other grain
+/// are removed from a grain boundary
+const GRAIN_BOUNDARY_OVERLAP: f32 = 0.8;
+
+/// the indices of a random `fraction` of the atoms, only counting atoms of `species` if given
+fn choose(atoms: &[Atom], fraction: f32, species: Option<u32>, rng: &mut impl Rng) -> Vec<usize> {
+    let candidates = atoms
+        .iter()
+        .enumerate()
+        .filter(|(_, atom)| species.is_none_or(|species| atom.species == species))
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    let amount = (fraction * candidates.len() as f32).round() as usize;
+
+    index::sample(rng, candidates.len(), amount.min(candidates.len()))
+        .into_iter()
+        .map(|candidate| candidates[candidate])
+        .collect()
+}
+
+/// removes a random `fraction` of the atoms, or of the atoms of `species` if given
+pub fn vacancies(
+    atoms: Vec<Atom>,
+    fraction: f32,
+    species: Option<u32>,
+    rng: &mut impl Rng,
+) -> Vec<Atom> {
+    let mut removed = vec![false; atoms.len()];
+    for index in choose(&atoms, fraction, species, rng) {
+        removed[index] = true;
+    }
+
+    atoms
+        .into_iter()
+        .zip(removed)
+        .filter(|(_, removed)| !removed)
+        .map(|(atom, _)| atom)
+        .collect()
+}
+
+/// turns a random `fraction` of the atoms, or of the atoms of `replaced` if given, into
+/// substitutional impurities of `species`
+pub fn substitute(
+    atoms: &mut [Atom],
+    fraction: f32,
+    replaced: Option<u32>,
+    species: u32,
+    rng: &mut impl Rng,
+) {
+    for index in choose(atoms, fraction, replaced, rng) {
+        atoms[index].species = species;
+    }
+}
+
+/// inserts up to `count` interstitial atoms of `species` at random positions in the rectangle
+/// `origin..origin + size` that are at least `min_separation` away from all other atoms.
+/// Positions are drawn by rejection, returns the amount of atoms that could be inserted.
+pub fn interstitials(
+    atoms: &mut Vec<Atom>,
+    origin: Vector2<f32>,
+    size: Vector2<f32>,
+    count: usize,
+    min_separation: f32,
+    species: u32,
+    rng: &mut impl Rng,
+) -> usize {
+    const ATTEMPTS_PER_ATOM: usize = 1000;
+
+    // bins of the size of the separation, so only the neighbouring bins need to be checked
+    let bin = |position: Vector2<f32>| {
+        let bin = (position / min_separation).map(|x| x.floor() as i64);
+        (bin.x, bin.y)
+    };
+    let mut bins = HashMap::<_, Vec<Vector2<f32>>>::new();
+    for atom in atoms.iter() {
+        bins.entry(bin(atom.position))
+            .or_default()
+            .push(atom.position);
+    }
+
+    let mut inserted = 0;
+    for _ in 0..count * ATTEMPTS_PER_ATOM {
+        if inserted == count {
+            break;
+        }
+        let position = origin + size.component_mul(&Vector2::new(rng.gen(), rng.gen()));
+        let (x, y) = bin(position);
+        let free = (x - 1..=x + 1)
+            .flat_map(|x| (y - 1..=y + 1).map(move |y| (x, y)))
+            .filter_map(|key| bins.get(&key))
+            .flatten()
+            .all(|other| (other - position).norm() >= min_separation);
+
+        if free {
+            bins.entry((x, y)).or_default().push(position);
+            atoms.push(
+                Atom::new(position, Vector2::zeros(), Vector2::zeros()).with_species(species),
+            );
+            inserted += 1;
+        }
+    }
+    inserted
+}
+
+/// displaces the atoms by the isotropic elastic field of an edge dislocation at `core` with
+/// its burgers vector along x, the slip plane along negative x and the extra half plane along
+/// positive y. The burgers vector should be a lattice vector so the slipped atoms are back in
+/// registry. The field is singular at the core, no atom should lie on it exactly.
+pub fn edge_dislocation(atoms: &mut [Atom], core: Vector2<f32>, burgers: f32, poisson_ratio: f32) {
+    let nu = poisson_ratio;
+    for atom in atoms {
+        let relative = atom.position - core;
+        let (x, y) = (relative.x, relative.y);
+        let r_sq = relative.norm_squared().max(1e-12);
+        // the branch cut of the angle lies on the slip plane, where the atoms on either side
+        // are displaced by a full burgers vector relative to each other
+        let theta = y.atan2(x);
+
+        let displacement = Vector2::new(
+            theta + x * y / (2.0 * (1.0 - nu) * r_sq),
+            -((1.0 - 2.0 * nu) / (4.0 * (1.0 - nu)) * r_sq.ln()
+                + (x * x - y * y) / (4.0 * (1.0 - nu) * r_sq)),
+        ) * burgers
+            / (2.0 * PI);
+        atom.position += displacement;
+    }
+}
+
+/// a bicrystal filling the rectangle `origin..origin + size`, split into two grains at the
+/// middle of its x extent. Each grain is a lattice created by `generate` and rotated by the
+/// respective angle in radians, atoms of the second grain that overlap with the first are
+/// removed.
+pub fn grain_boundary(
+    origin: Vector2<f32>,
+    size: Vector2<f32>,
+    spacing: f32,
+    species: u32,
+    angles: [f32; 2],
+    generate: impl Fn(Vector2<f32>, Vector2<f32>, f32, u32) -> Vec<Atom>,
+) -> Vec<Atom> {
+    let center = origin + size * 0.5;
+    // a square large enough to cover the rectangle at any rotation
+    let diagonal = size.norm();
+    let lattice = generate(
+        center - Vector2::repeat(diagonal * 0.5),
+        Vector2::repeat(diagonal),
+        spacing,
+        species,
+    );
+    let inside = |position: Vector2<f32>, grain: usize| {
+        let from = origin.x + size.x * 0.5 * grain as f32;
+        (from..from + size.x * 0.5).contains(&position.x)
+            && (origin.y..origin.y + size.y).contains(&position.y)
+    };
+
+    let mut grains = angles.into_iter().enumerate().map(|(grain, angle)| {
+        let rotation = Rotation2::new(angle);
+        lattice
+            .iter()
+            .map(|atom| Atom {
+                position: center + rotation * (atom.position - center),
+                ..*atom
+            })
+            .filter(|atom| inside(atom.position, grain))
+            .collect::<Vec<_>>()
+    });
+    let (mut atoms, second) = (grains.next().unwrap(), grains.next().unwrap());
+
+    // only atoms next to the boundary can overlap
+    let min_separation = GRAIN_BOUNDARY_OVERLAP * spacing;
+    let boundary = atoms
+        .iter()
+        .map(|atom| atom.position)
+        .filter(|position| position.x >= center.x - min_separation)
+        .collect::<Vec<_>>();
+    atoms.extend(second.into_iter().filter(|atom| {
+        atom.position.x >= center.x + min_separation
+            || boundary
+                .iter()
+                .all(|position| (position - atom.position).norm() >= min_separation)
+    }));
+    atoms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::generators::{hexagonal_lattice, square_lattice};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    /// the smallest distance between two of the atoms
+    fn min_distance(atoms: &[Atom]) -> f32 {
+        atoms
+            .iter()
+            .enumerate()
+            .flat_map(|(index, a)| {
+                atoms[index + 1..]
+                    .iter()
+                    .map(move |b| (a.position - b.position).norm())
+            })
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    #[test]
+    fn vacancies_and_substitutions_take_a_fraction() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let atoms = square_lattice(Vector2::zeros(), Vector2::repeat(10.0), 1.0, 0);
+        let mut atoms = vacancies(atoms, 0.1, None, &mut rng);
+        assert_eq!(atoms.len(), 90);
+
+        substitute(&mut atoms, 0.5, Some(0), 2, &mut rng);
+        assert_eq!(atoms.iter().filter(|atom| atom.species == 2).count(), 45);
+        // only the atoms of the replaced species are candidates
+        substitute(&mut atoms, 1.0, Some(2), 1, &mut rng);
+        assert_eq!(atoms.iter().filter(|atom| atom.species == 1).count(), 45);
+        assert_eq!(atoms.iter().filter(|atom| atom.species == 0).count(), 45);
+    }
+
+    #[test]
+    fn interstitials_keep_their_separation() {
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let mut atoms = square_lattice(Vector2::zeros(), Vector2::repeat(10.0), 2.0, 0);
+        let lattice = atoms.len();
+        let inserted = interstitials(
+            &mut atoms,
+            Vector2::zeros(),
+            Vector2::repeat(10.0),
+            10,
+            1.2,
+            1,
+            &mut rng,
+        );
+        assert_eq!(inserted, 10);
+        assert_eq!(atoms.len(), lattice + 10);
+        assert!(atoms[lattice..].iter().all(|atom| atom.species == 1));
+        assert!(min_distance(&atoms) >= 1.2);
+
+        // the centers of the square cells are the only gaps wide enough, so at most one
+        // atom more per cell fits in
+        let crowded = interstitials(
+            &mut atoms,
+            Vector2::zeros(),
+            Vector2::repeat(10.0),
+            100,
+            1.2,
+            1,
+            &mut rng,
+        );
+        assert!(crowded < 100);
+        assert!(min_distance(&atoms) >= 1.2);
+    }
+
+    #[test]
+    fn grain_boundaries_remove_overlaps() {
+        let origin = Vector2::new(2.0, 3.0);
+        let size = Vector2::new(20.0, 16.0);
+        let angles = [10f32.to_radians(), -15f32.to_radians()];
+        let atoms = grain_boundary(origin, size, 1.0, 0, angles, hexagonal_lattice);
+
+        assert!(min_distance(&atoms) >= GRAIN_BOUNDARY_OVERLAP);
+        assert!(atoms.iter().all(|atom| {
+            let relative = atom.position - origin;
+            relative.x >= 0.0 && relative.y >= 0.0 && relative.x < size.x && relative.y < size.y
+        }));
+        // both grains fill their halves at about the density of the lattice
+        let density = 2.0 / 3f32.sqrt();
+        for half in [0.0, 1.0] {
+            let from = origin.x + size.x * 0.5 * half;
+            let count = atoms
+                .iter()
+                .filter(|atom| (from..from + size.x * 0.5).contains(&atom.position.x))
+                .count() as f32;
+            let expected = density * size.x * 0.5 * size.y;
+            assert!(
+                (count - expected).abs() < 0.1 * expected,
+                "{count} {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn edge_dislocations_slip_by_the_burgers_vector() {
+        let core = Vector2::new(0.0, 0.25);
+        let mut atoms = [-10.0, 10.0]
+            .map(|x| Atom::new(Vector2::new(x, 0.0), Vector2::zeros(), Vector2::zeros()));
+        edge_dislocation(&mut atoms, core, 1.0, 0.3);
+        // far from the core, the atom just below the slip plane is displaced by half a
+        // burgers vector, while the one the same distance across the core barely moves
+        assert!(
+            (atoms[0].position.x + 10.0 + 0.5).abs() < 0.05,
+            "{:?}",
+            atoms[0].position
+        );
+        assert!(
+            (atoms[1].position.x - 10.0).abs() < 0.05,
+            "{:?}",
+            atoms[1].position
+        );
+    }
+}
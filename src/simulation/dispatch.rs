@@ -0,0 +1,14 @@
+use serde::Deserialize;
+
+/// how the interaction and integration passes are split into invocations. Both give the same
+/// results as long as no cell overflows.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dispatch {
+    /// one invocation per atom, in workgroups of 64
+    #[default]
+    Atoms,
+    /// one invocation per cell, which loops over the atoms of its cell. Atoms beyond the 16th
+    /// of a cell are skipped. Only kept to compare against, see [`crate::benchmark`].
+    Cells,
+}
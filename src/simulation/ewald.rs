@@ -0,0 +1,598 @@
+use crate::simulation::Atom;
+use nalgebra::Vector2;
+use std::f64::consts::PI;
+use std::ops::RangeInclusive;
+
+/// the largest `k_max`, which keeps the amount of wave vectors below the amount of workgroups
+/// a single dispatch may have, as the structure factors take one workgroup each
+pub const MAX_K: u32 = 200;
+
+/// the largest side of a mesh, which bounds the workgroup memory of the fourier transforms,
+/// see `main_fft_rows` in `interact.wgsl`
+pub const MAX_MESH: u32 = 512;
+
+/// the supported orders of the b-splines that spread the charges onto a mesh
+pub const MESH_ORDERS: RangeInclusive<u32> = 3..=8;
+
+/// long-range coulomb interactions of the charges of the atoms, by ewald summation over the
+/// periodic images along both axes, in units where the coulomb constant is 1.
+///
+/// All charges lie in the plane, so the sum over the images is the two dimensional ewald sum
+/// of `1 / r` (Parry). The interaction is split by `erfc(alpha r) / r`, which is summed over
+/// the pairs within `cutoff` along with the lennard-jones forces, and a smooth remainder, which
+/// is summed over the wave vectors in reciprocal space as given by `reciprocal`. The energy of
+/// a system with a net charge depends on how it is neutralized, so systems should be neutral.
+///
+/// Unlike the lennard-jones potential, the coulomb interaction is not excluded between bonded
+/// atoms.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ewald {
+    /// the inverse width of the splitting function. Larger values shift work from the real
+    /// space sum to the reciprocal space sum.
+    pub alpha: f32,
+    /// the real space cutoff, at most the cell size of the grid
+    pub cutoff: f32,
+    pub reciprocal: Reciprocal,
+}
+
+/// how the reciprocal space sum of [`Ewald`] is evaluated
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Reciprocal {
+    /// the structure factors of the wave vectors `2 pi / L (m, n)` with `m^2 + n^2 <= k_max^2`
+    /// are summed over the atoms directly, which takes time proportional to the amount of atoms
+    /// times the amount of wave vectors
+    Direct { k_max: u32 },
+    /// particle mesh ewald (Essmann et al. 1995): the charges are spread onto a periodic mesh of
+    /// `size` by `size` points by cardinal b-splines of the given order, whose fourier transform
+    /// approximates the structure factors of all wave vectors the mesh resolves. Takes time
+    /// proportional to the amount of atoms plus the amount of mesh points times its logarithm.
+    /// The size is a power of two.
+    Mesh { size: u32, order: u32 },
+}
+
+/// a wave vector of the reciprocal space sum, see [`Ewald::wave_vectors`]
+#[derive(Copy, Clone, Debug)]
+pub struct WaveVector {
+    pub k: Vector2<f64>,
+    /// the energy of the wave vector per squared structure factor
+    pub coefficient: f64,
+}
+
+impl Ewald {
+    /// the wave vectors `2 pi / L (m, n)` with `m^2 + n^2 <= k_max^2` in a box of the given
+    /// side length. Only one of `k` and `-k` is listed, as both contribute the same, so their
+    /// coefficients count both.
+    pub fn wave_vectors(&self, side_length: f32, k_max: u32) -> Vec<WaveVector> {
+        let side_length = side_length as f64;
+        let alpha = self.alpha as f64;
+        let area = side_length * side_length;
+        let k_max = k_max as i64;
+
+        let mut wave_vectors = Vec::new();
+        for m in 0..=k_max {
+            for n in -k_max..=k_max {
+                if (m == 0 && n <= 0) || m * m + n * n > k_max * k_max {
+                    continue;
+                }
+                let k = Vector2::new(m as f64, n as f64) * (2.0 * PI / side_length);
+                let norm = k.norm();
+                wave_vectors.push(WaveVector {
+                    k,
+                    coefficient: 2.0 * coefficient(norm, alpha, area),
+                });
+            }
+        }
+        wave_vectors
+    }
+
+    /// the `k_max` of the reference energy: the one of direct sums, so that both evaluate the
+    /// same sum, or the one where `erfc(k / (2 alpha))` drops below 1e-8 for meshes, so that the
+    /// error of the mesh is measured against the converged sum
+    pub fn reference_k_max(&self, side_length: f32) -> u32 {
+        match self.reciprocal {
+            Reciprocal::Direct { k_max } => k_max,
+            Reciprocal::Mesh { .. } => {
+                (4.0 * self.alpha as f64 * side_length as f64 / PI).ceil() as u32
+            }
+        }
+    }
+
+    /// the influence function of a mesh of `size` by `size` points, i.e. the energy of every
+    /// wave vector per squared fourier coefficient of the spread charges, indexed like the mesh
+    /// with the frequencies from `size / 2` on standing for the negative ones. It includes the
+    /// squared moduli of the euler exponential splines, which undo the smoothing by the
+    /// b-splines.
+    pub fn influence_function(&self, side_length: f32, size: u32, order: u32) -> Vec<f64> {
+        let side_length = side_length as f64;
+        let alpha = self.alpha as f64;
+        let area = side_length * side_length;
+
+        // the b-spline at the integers, which is what the moduli are made of
+        let knots = bspline_weights(0.0, order);
+        let moduli = (0..size)
+            .map(|m| {
+                let (cos, sin) = (0..order as usize - 1).fold((0.0, 0.0), |(cos, sin), j| {
+                    let phase = 2.0 * PI * (m as f64) * (j as f64) / size as f64;
+                    (
+                        cos + knots[j + 1] * phase.cos(),
+                        sin + knots[j + 1] * phase.sin(),
+                    )
+                });
+                cos * cos + sin * sin
+            })
+            .collect::<Vec<_>>();
+        let frequency = |m: u32| {
+            if m < size / 2 {
+                m as f64
+            } else {
+                m as f64 - size as f64
+            }
+        };
+
+        let mut influence = vec![0.0; (size * size) as usize];
+        for my in 0..size {
+            for mx in 0..size {
+                let modulus = moduli[mx as usize] * moduli[my as usize];
+                // odd orders have no modulus at the nyquist frequency, whose wave vectors are
+                // left out instead
+                if (mx, my) == (0, 0) || modulus < 1e-10 {
+                    continue;
+                }
+                let k = Vector2::new(frequency(mx), frequency(my)) * (2.0 * PI / side_length);
+                influence[(my * size + mx) as usize] = coefficient(k.norm(), alpha, area) / modulus;
+            }
+        }
+        influence
+    }
+
+    /// the coulomb energy of the given atoms with the given charges in a box of the given side
+    /// length, by the same splitting as on the gpu, but always with the direct reciprocal space
+    /// sum over the wave vectors up to [`Ewald::reference_k_max`]. Meant as a reference for the
+    /// gpu, the real space sum goes over all pairs.
+    pub fn energy(&self, atoms: &[Atom], charges: &[f32], side_length: f32) -> f64 {
+        let reciprocal = self.reciprocal_energy(atoms, charges, side_length);
+        let side_length = side_length as f64;
+        let alpha = self.alpha as f64;
+        let cutoff = self.cutoff as f64;
+        let positions = atoms
+            .iter()
+            .map(|atom| atom.position().cast::<f64>())
+            .collect::<Vec<_>>();
+        let charges = charges.iter().map(|&q| q as f64).collect::<Vec<_>>();
+
+        let mut real = 0.0;
+        for i in 0..positions.len() {
+            for j in i + 1..positions.len() {
+                let d = (positions[j] - positions[i])
+                    .map(|d| d - side_length * (d / side_length).round());
+                let r = d.norm();
+                if r < cutoff {
+                    real += charges[i] * charges[j] * erfc(alpha * r) / r;
+                }
+            }
+        }
+
+        let own = -alpha / PI.sqrt() * charges.iter().map(|q| q * q).sum::<f64>();
+        real + reciprocal + own
+    }
+
+    /// the reciprocal space part of [`Ewald::energy`]
+    fn reciprocal_energy(&self, atoms: &[Atom], charges: &[f32], side_length: f32) -> f64 {
+        let k_max = self.reference_k_max(side_length);
+        self.wave_vectors(side_length, k_max)
+            .iter()
+            .map(|wave_vector| {
+                let (cos, sin) = structure_factor(wave_vector, atoms, charges);
+                wave_vector.coefficient * (cos * cos + sin * sin)
+            })
+            .sum()
+    }
+}
+
+/// the real and imaginary part of the structure factor of the given atoms with the given
+/// charges
+fn structure_factor(wave_vector: &WaveVector, atoms: &[Atom], charges: &[f32]) -> (f64, f64) {
+    atoms
+        .iter()
+        .zip(charges)
+        .fold((0.0, 0.0), |(cos, sin), (atom, &q)| {
+            let phase = wave_vector.k.dot(&atom.position().cast::<f64>());
+            (cos + q as f64 * phase.cos(), sin + q as f64 * phase.sin())
+        })
+}
+
+/// the energy of the wave vector with the given length per squared structure factor, counting
+/// only the wave vector itself
+fn coefficient(k: f64, alpha: f64, area: f64) -> f64 {
+    PI / area * erfc(k / (2.0 * alpha)) / k
+}
+
+/// the cardinal b-spline of the given order at `t + j` for `j` from 0 to `order - 1`, where `t`
+/// is between 0 and 1. These are the weights of the mesh points `j` below the mesh point below
+/// a charge, if the charge lies `t` mesh spacings above it. `bspline_weights` in
+/// `interact.wgsl` evaluates them the same way.
+pub fn bspline_weights(t: f64, order: u32) -> Vec<f64> {
+    let mut weights = vec![0.0; order as usize];
+    weights[0] = t;
+    weights[1] = 1.0 - t;
+    for n in 3..=order as usize {
+        for j in (0..n).rev() {
+            let upper = if j < n - 1 {
+                (t + j as f64) * weights[j]
+            } else {
+                0.0
+            };
+            let lower = if j > 0 {
+                (n as f64 - t - j as f64) * weights[j - 1]
+            } else {
+                0.0
+            };
+            weights[j] = (upper + lower) / (n - 1) as f64;
+        }
+    }
+    weights
+}
+
+/// the complementary error function, to about double precision. The gpu uses a cheaper
+/// approximation, see `erfc` in `interact.wgsl`.
+pub fn erfc(x: f64) -> f64 {
+    if x < 0.0 {
+        return 2.0 - erfc(-x);
+    }
+    if x < 2.0 {
+        // the taylor series of erf, whose terms stay small enough in this range
+        let mut term = x;
+        let mut sum = x;
+        for n in 1..60 {
+            term *= -x * x / n as f64;
+            sum += term / (2 * n + 1) as f64;
+        }
+        1.0 - 2.0 / PI.sqrt() * sum
+    } else {
+        // the continued fraction, evaluated from its tail
+        let mut fraction = x;
+        for n in (1..60).rev() {
+            fraction = x + n as f64 / 2.0 / fraction;
+        }
+        (-x * x).exp() / (PI.sqrt() * fraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `count` atoms at uniformly random positions in a box of side `side_length`, half of them
+    /// with charge 1 and half with charge -1, from a fixed linear congruential generator
+    fn random_charges(count: usize, side_length: f32) -> (Vec<Atom>, Vec<f32>) {
+        let mut state = 12345u64;
+        let mut uniform = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (state >> 40) as f32 / (1u64 << 24) as f32
+        };
+        let atoms = (0..count)
+            .map(|_| {
+                let position = Vector2::new(uniform(), uniform()) * side_length;
+                Atom::new(position, Vector2::zeros(), Vector2::zeros())
+            })
+            .collect();
+        let charges = (0..count)
+            .map(|index| if index % 2 == 0 { 1.0 } else { -1.0 })
+            .collect();
+        (atoms, charges)
+    }
+
+    /// `side * side` alternating unit charges on a square lattice filling a box of side
+    /// `side_length`, shifted off the mesh points
+    fn checkerboard(side: usize, side_length: f32) -> (Vec<Atom>, Vec<f32>) {
+        let spacing = side_length / side as f32;
+        let mut atoms = Vec::new();
+        let mut charges = Vec::new();
+        for y in 0..side {
+            for x in 0..side {
+                let position = Vector2::new(x as f32 + 0.3, y as f32 + 0.6) * spacing;
+                atoms.push(Atom::new(position, Vector2::zeros(), Vector2::zeros()));
+                charges.push(if (x + y) % 2 == 0 { 1.0 } else { -1.0 });
+            }
+        }
+        (atoms, charges)
+    }
+
+    /// the reciprocal space forces of the direct sum up to [`Ewald::reference_k_max`], like
+    /// `main_reciprocal` in `interact.wgsl`
+    fn reciprocal_forces(
+        ewald: &Ewald,
+        atoms: &[Atom],
+        charges: &[f32],
+        side_length: f32,
+    ) -> Vec<Vector2<f64>> {
+        let k_max = ewald.reference_k_max(side_length);
+        let mut forces = vec![Vector2::zeros(); atoms.len()];
+        for wave_vector in ewald.wave_vectors(side_length, k_max) {
+            let (cos, sin) = structure_factor(&wave_vector, atoms, charges);
+            for ((force, atom), &q) in forces.iter_mut().zip(atoms).zip(charges) {
+                let phase = wave_vector.k.dot(&atom.position().cast::<f64>());
+                let magnitude = 2.0 * wave_vector.coefficient * q as f64;
+                *force += wave_vector.k * magnitude * (cos * phase.sin() - sin * phase.cos());
+            }
+        }
+        forces
+    }
+
+    /// the discrete fourier transform with positive exponent of the `size` points of the mesh
+    /// from `start` on with the given stride, by the butterflies of `transform_line` in
+    /// `interact.wgsl`. Points are complex numbers as vectors like on the gpu.
+    fn transform_line(mesh: &mut [Vector2<f64>], start: usize, stride: usize, size: usize) {
+        let bits = (size - 1).count_ones();
+        let mut line = vec![Vector2::zeros(); size];
+        for i in 0..size {
+            line[(i as u32).reverse_bits() as usize >> (32 - bits)] = mesh[start + i * stride];
+        }
+        let mut half = 1;
+        while half < size {
+            for i in 0..size / 2 {
+                let j = i % half;
+                let even_index = (i / half) * 2 * half + j;
+                let angle = PI * j as f64 / half as f64;
+                let twiddle = Vector2::new(angle.cos(), angle.sin());
+                let even = line[even_index];
+                let odd = line[even_index + half];
+                let product = Vector2::new(
+                    twiddle.x * odd.x - twiddle.y * odd.y,
+                    twiddle.x * odd.y + twiddle.y * odd.x,
+                );
+                line[even_index] = even + product;
+                line[even_index + half] = even - product;
+            }
+            half *= 2;
+        }
+        for (i, value) in line.into_iter().enumerate() {
+            mesh[start + i * stride] = value;
+        }
+    }
+
+    /// the reciprocal space energy and forces of a mesh, by the passes of the mesh in
+    /// `interact.wgsl`: `main_spread`, `main_fft_rows` and `main_fft_columns`, `main_convolve`,
+    /// the transforms once more, and `mesh_potential` for `main_interpolate` and
+    /// `main_mesh_coulomb_energy`. The charges are scattered onto the mesh instead of gathered,
+    /// which sums the same terms.
+    fn mesh_energy_and_forces(
+        ewald: &Ewald,
+        atoms: &[Atom],
+        charges: &[f32],
+        side_length: f32,
+    ) -> (f64, Vec<Vector2<f64>>) {
+        let Reciprocal::Mesh { size, order } = ewald.reciprocal else {
+            panic!("not a mesh");
+        };
+        let n = size as i64;
+        let wrap = |point: i64| point.rem_euclid(n) as usize;
+        // the mesh point below every charge and the offset of the charge above it
+        let coordinates = |atom: &Atom| {
+            let u = atom.position().cast::<f64>() * size as f64 / side_length as f64;
+            let base = u.map(|u| u.floor() as i64);
+            (base, u - base.cast::<f64>())
+        };
+
+        let mut mesh = vec![Vector2::zeros(); (size * size) as usize];
+        for (atom, &q) in atoms.iter().zip(charges) {
+            let (base, t) = coordinates(atom);
+            let weights_x = bspline_weights(t.x, order);
+            let weights_y = bspline_weights(t.y, order);
+            for (j_y, weight_y) in weights_y.iter().enumerate() {
+                for (j_x, weight_x) in weights_x.iter().enumerate() {
+                    let index =
+                        wrap(base.y - j_y as i64) * size as usize + wrap(base.x - j_x as i64);
+                    mesh[index].x += q as f64 * weight_x * weight_y;
+                }
+            }
+        }
+
+        let influence = ewald.influence_function(side_length, size, order);
+        let size = size as usize;
+        let transform = |mesh: &mut [Vector2<f64>]| {
+            for row in 0..size {
+                transform_line(mesh, row * size, 1, size);
+            }
+            for column in 0..size {
+                transform_line(mesh, column, size, size);
+            }
+        };
+        transform(&mut mesh);
+        for (value, influence) in mesh.iter_mut().zip(&influence) {
+            *value = Vector2::new(value.x, -value.y) * *influence;
+        }
+        transform(&mut mesh);
+
+        let spacing = side_length as f64 / size as f64;
+        let mut energy = 0.0;
+        let mut forces = Vec::with_capacity(atoms.len());
+        for (atom, &q) in atoms.iter().zip(charges) {
+            let (base, t) = coordinates(atom);
+            let weights_x = bspline_weights(t.x, order);
+            let weights_y = bspline_weights(t.y, order);
+            let lower_x = bspline_weights(t.x, order - 1);
+            let lower_y = bspline_weights(t.y, order - 1);
+            // the derivative of a b-spline is the difference of two of the next lower order
+            let slope = |lower: &[f64], j: usize| {
+                lower.get(j).copied().unwrap_or(0.0) - if j > 0 { lower[j - 1] } else { 0.0 }
+            };
+
+            let mut potential = 0.0;
+            let mut gradient = Vector2::zeros();
+            for (j_y, weight_y) in weights_y.iter().enumerate() {
+                for (j_x, weight_x) in weights_x.iter().enumerate() {
+                    let index = wrap(base.y - j_y as i64) * size + wrap(base.x - j_x as i64);
+                    let value = mesh[index].x;
+                    potential += value * weight_x * weight_y;
+                    gradient += value
+                        * Vector2::new(
+                            slope(&lower_x, j_x) * weight_y,
+                            weight_x * slope(&lower_y, j_y),
+                        );
+                }
+            }
+            energy += q as f64 * potential;
+            // the energy is quadratic in the spread charges
+            forces.push(-2.0 * q as f64 * gradient / spacing);
+        }
+        (energy, forces)
+    }
+
+    #[test]
+    fn mesh_matches_direct_sum() {
+        let side_length = 10.0;
+        let systems = [
+            random_charges(40, side_length),
+            checkerboard(6, side_length),
+            random_charges(2, side_length),
+        ];
+        // the largest error of the energy relative to the total coulomb energy, and the largest
+        // error of a force, in units of unit charges and lengths, for every mesh
+        for (size, order, energy_tolerance, force_tolerance) in [
+            (32, 4, 2e-3, 5e-3),
+            (32, 6, 5e-5, 1e-4),
+            (32, 8, 5e-6, 1e-5),
+            (64, 4, 1e-4, 5e-4),
+            (64, 6, 5e-7, 5e-6),
+        ] {
+            let ewald = Ewald {
+                alpha: 1.0,
+                cutoff: 4.0,
+                reciprocal: Reciprocal::Mesh { size, order },
+            };
+            for (atoms, charges) in &systems {
+                let (energy, forces) = mesh_energy_and_forces(&ewald, atoms, charges, side_length);
+                let reference = ewald.reciprocal_energy(atoms, charges, side_length);
+                let total = ewald.energy(atoms, charges, side_length);
+                let energy_error = ((energy - reference) / total).abs();
+                assert!(
+                    energy_error < energy_tolerance,
+                    "{size} {order}: {energy_error}"
+                );
+
+                let reference_forces = reciprocal_forces(&ewald, atoms, charges, side_length);
+                let force_error = forces
+                    .iter()
+                    .zip(&reference_forces)
+                    .map(|(force, reference)| (force - reference).norm())
+                    .fold(0.0, f64::max);
+                assert!(
+                    force_error < force_tolerance,
+                    "{size} {order}: {force_error}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn energy_of_square_lattice_is_madelung_energy() {
+        // a checkerboard of unit charges with unit spacing, whose energy per ion is the
+        // madelung constant of the square lattice
+        let side = 8;
+        let mut atoms = Vec::new();
+        let mut charges = Vec::new();
+        for y in 0..side {
+            for x in 0..side {
+                let position = Vector2::new(x as f32 + 0.5, y as f32 + 0.5);
+                atoms.push(Atom::new(position, Vector2::zeros(), Vector2::zeros()));
+                charges.push(if (x + y) % 2 == 0 { 1.0 } else { -1.0 });
+            }
+        }
+        let ewald = Ewald {
+            alpha: 1.5,
+            cutoff: 4.0,
+            reciprocal: Reciprocal::Direct { k_max: 30 },
+        };
+        let energy = ewald.energy(&atoms, &charges, side as f32) / atoms.len() as f64;
+        assert!((energy + 0.807771).abs() < 1e-6, "{energy}");
+    }
+
+    #[test]
+    fn energy_does_not_depend_on_alpha() {
+        let side_length = 10.0;
+        let (atoms, charges) = random_charges(40, side_length);
+        let energy = |alpha| {
+            let ewald = Ewald {
+                alpha,
+                cutoff: side_length / 2.0,
+                reciprocal: Reciprocal::Mesh { size: 32, order: 4 },
+            };
+            ewald.energy(&atoms, &charges, side_length)
+        };
+        let reference = energy(1.5);
+        for alpha in [1.0, 2.0, 3.0] {
+            let difference = (energy(alpha) - reference).abs();
+            assert!(difference < 1e-6 * reference.abs(), "{alpha}: {difference}");
+        }
+    }
+
+    /// the largest relative difference between the influence function of a mesh and the
+    /// coefficients of the wave vectors up to `k_max = 5` it resolves
+    fn influence_error(size: u32, order: u32) -> f64 {
+        let side_length = 10.0;
+        let ewald = Ewald {
+            alpha: 1.0,
+            cutoff: 4.0,
+            reciprocal: Reciprocal::Mesh { size, order },
+        };
+        let influence = ewald.influence_function(side_length, size, order);
+        let mesh_index = |frequency: f64| (frequency.round() as i64).rem_euclid(size as i64);
+        ewald
+            .wave_vectors(side_length, 5)
+            .iter()
+            .map(|wave_vector| {
+                let frequency = wave_vector.k * side_length as f64 / (2.0 * PI);
+                let index = mesh_index(frequency.y) * size as i64 + mesh_index(frequency.x);
+                // the coefficients count both k and -k
+                (2.0 * influence[index as usize] / wave_vector.coefficient - 1.0).abs()
+            })
+            .fold(0.0, f64::max)
+    }
+
+    #[test]
+    fn influence_function_approaches_coefficients_on_fine_meshes() {
+        // the moduli of the b-splines differ from 1 by about `order (2 pi m / size)^2 / 12`,
+        // so the error drops fourfold whenever the mesh doubles
+        for order in [4, 6] {
+            let coarse = influence_error(256, order);
+            let fine = influence_error(512, order);
+            assert!(fine < 2e-3, "{order}: {fine}");
+            assert!(
+                (coarse / fine - 4.0).abs() < 0.1,
+                "{order}: {coarse} {fine}"
+            );
+        }
+    }
+
+    #[test]
+    fn bspline_weights_sum_to_one() {
+        for order in MESH_ORDERS {
+            for step in 0..10 {
+                let weights = bspline_weights(step as f64 / 10.0, order);
+                let sum = weights.iter().sum::<f64>();
+                assert!((sum - 1.0).abs() < 1e-12, "{order}: {sum}");
+                assert!(weights.iter().all(|&weight| weight >= 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn erfc_matches_known_values() {
+        for (x, expected) in [
+            (0.0, 1.0),
+            (0.5, 0.4795001221869535),
+            (1.0, 0.15729920705028513),
+            (1.99, 0.004888586800383003),
+            (2.0, 0.004677734981047265),
+            (3.0, 2.2090496998585438e-5),
+            (5.0, 1.5374597944280351e-12),
+            (-1.0, 1.842700792949715),
+        ] {
+            let value = erfc(x);
+            assert!((value / expected - 1.0).abs() < 1e-12, "{x}: {value}");
+        }
+    }
+}
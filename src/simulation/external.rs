@@ -0,0 +1,44 @@
+use nalgebra::Vector2;
+
+/// forces of fields outside the system, which act on every atom on top of its interactions.
+/// They are added in `main_integrate`, so they drive the dynamics and the minimization, but
+/// are neither part of the forces that are read back nor of the energies of monte carlo moves.
+#[derive(Clone, Debug, Default)]
+pub struct ExternalForces {
+    /// the acceleration of gravity, i.e. the force per mass
+    pub gravity: Vector2<f32>,
+    /// a uniform electric field, i.e. the force per charge
+    pub electric_field: Vector2<f32>,
+    pub traps: Vec<HarmonicTrap>,
+    pub force_field: Option<ForceField>,
+}
+
+impl ExternalForces {
+    pub fn is_empty(&self) -> bool {
+        self.gravity == Vector2::zeros()
+            && self.electric_field == Vector2::zeros()
+            && self.traps.is_empty()
+            && self.force_field.is_none()
+    }
+}
+
+/// the potential `stiffness / 2 |r - center|^2`, which holds atoms near its center like an
+/// optical tweezer. The distance to the center uses the nearest periodic image.
+#[derive(Copy, Clone, Debug)]
+pub struct HarmonicTrap {
+    pub center: Vector2<f32>,
+    pub stiffness: f32,
+    /// the species the trap holds, all species if `None`
+    pub species: Option<u32>,
+}
+
+/// forces sampled on a regular grid of `width` by `height` texels covering the box, with the
+/// samples at the texel centers and bilinear interpolation in between. Beyond the outermost
+/// samples, periodic axes wrap around and the others keep the outermost sample.
+#[derive(Clone, Debug)]
+pub struct ForceField {
+    pub width: u32,
+    pub height: u32,
+    /// the samples in row-major order, from the bottom row to the top one
+    pub samples: Vec<Vector2<f32>>,
+}
@@ -0,0 +1,466 @@
+use crate::simulation::generators;
+use crate::simulation::hashgrid::{HashGrid, Probe};
+use crate::simulation::{Atom, SimulationParameters};
+use eyre::Result;
+use nalgebra::Vector2;
+use rand::Rng;
+use std::sync::Arc;
+use wgpu::{Device, Queue};
+
+/// grand canonical monte carlo insertions and deletions of one species inside a rectangle,
+/// in equilibrium with a reservoir at the given chemical potential.
+///
+/// The thermal wavelength is taken as 1, so the activity is `exp(chemical_potential /
+/// temperature)` and a move is accepted with the usual probabilities
+/// `min(1, z V / (N + 1) exp(-ΔU / T))` for insertions and `min(1, N / (z V) exp(-ΔU / T))` for
+/// deletions.
+#[derive(Clone, Debug)]
+pub struct Gcmc {
+    pub species: u32,
+    pub chemical_potential: f32,
+    pub temperature: f32,
+    /// the amount of trial moves per cycle, each one an insertion or a deletion with equal
+    /// probability
+    pub attempts: usize,
+    /// the amount of time steps between cycles
+    pub every: u64,
+    pub origin: Vector2<f32>,
+    pub size: Vector2<f32>,
+}
+
+/// an atom that takes part in a cycle, either one of the hash grid or one inserted during the
+/// cycle
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Particle {
+    Existing(usize),
+    Inserted(usize),
+}
+
+/// the moves of a cycle that were accepted
+#[derive(Debug)]
+struct Accepted {
+    /// indices into the existing atoms of the species inside the region
+    deleted: Vec<usize>,
+    /// indices into the insertions, along with their positions
+    inserted: Vec<(usize, Vector2<f32>)>,
+}
+
+impl Gcmc {
+    fn contains(&self, position: Vector2<f32>) -> bool {
+        let relative = position - self.origin;
+        (0.0..self.size.x).contains(&relative.x) && (0.0..self.size.y).contains(&relative.y)
+    }
+
+    /// performs one cycle of trial moves and applies the accepted ones to the hash grid.
+    ///
+    /// The energies of all candidates, i.e. the existing atoms that could be deleted and the
+    /// trial insertion positions, are evaluated on the gpu in one batch against the atoms at
+    /// the start of the cycle. The moves are then accepted one after the other, correcting
+    /// those energies for the moves accepted before on the cpu, so every move sees the exact
+    /// energy of the current configuration.
+    pub async fn cycle(
+        &self,
+        device: &Arc<Device>,
+        queue: &Queue,
+        hash_grid: &mut HashGrid,
+        rng: &mut impl Rng,
+    ) -> Result<()> {
+        let parameters = hash_grid.parameters().clone();
+        let atoms = hash_grid.read_atoms(device, queue).await?;
+
+        let existing = atoms
+            .iter()
+            .enumerate()
+            .filter(|(_, atom)| atom.species == self.species && self.contains(atom.position))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        let trials = (0..self.attempts)
+            .map(|_| {
+                rng.gen_bool(0.5).then(|| {
+                    self.origin + self.size.component_mul(&Vector2::new(rng.gen(), rng.gen()))
+                })
+            })
+            .collect::<Vec<_>>();
+        let insertions = trials.iter().flatten().copied().collect::<Vec<_>>();
+
+        let probes = existing
+            .iter()
+            .map(|&index| Probe {
+                position: atoms[index].position,
+                species: self.species,
+                exclude: index as i32,
+            })
+            .chain(insertions.iter().map(|&position| Probe {
+                position,
+                species: self.species,
+                exclude: -1,
+            }))
+            .collect::<Vec<_>>();
+        let energies = hash_grid.probe_energies(device, queue, &probes).await?;
+        let (existing_energies, insertion_energies) = energies.split_at(existing.len());
+        let positions = existing
+            .iter()
+            .map(|&index| atoms[index].position)
+            .collect::<Vec<_>>();
+        let accepted = self.accept(
+            &parameters,
+            &positions,
+            existing_energies,
+            &trials,
+            insertion_energies,
+            rng,
+        );
+
+        let removed = accepted
+            .deleted
+            .iter()
+            .map(|&index| existing[index] as u32)
+            .collect::<Vec<_>>();
+        hash_grid.remove(device, queue, &removed);
+
+        let mass = parameters.species[self.species as usize].mass;
+        let added = accepted
+            .inserted
+            .iter()
+            .map(|&(_, position)| {
+                Atom::new(
+                    position,
+                    generators::thermal_velocity(mass, self.temperature, rng),
+                    Vector2::zeros(),
+                )
+                .with_species(self.species)
+            })
+            .collect::<Vec<_>>();
+        hash_grid.insert(device, queue, &added);
+
+        Ok(())
+    }
+
+    /// accepts or rejects the trial moves one after the other: an insertion at the position of
+    /// a trial, or the deletion of a random atom for `None`. `existing` are the positions of
+    /// the atoms of the species inside the region at the start of the cycle. Their energies and
+    /// those of the insertions are the ones with the configuration at the start of the cycle.
+    fn accept(
+        &self,
+        parameters: &SimulationParameters,
+        existing: &[Vector2<f32>],
+        existing_energies: &[f32],
+        trials: &[Option<Vector2<f32>>],
+        insertion_energies: &[f32],
+        rng: &mut impl Rng,
+    ) -> Accepted {
+        let activity = (self.chemical_potential / self.temperature).exp();
+        let volume = self.size.x * self.size.y;
+
+        // the atoms of the species inside the region in the current configuration
+        let mut particles = (0..existing.len())
+            .map(Particle::Existing)
+            .collect::<Vec<_>>();
+        let mut accepted = Accepted {
+            deleted: Vec::new(),
+            inserted: Vec::new(),
+        };
+        let mut next_insertion = 0;
+
+        for &trial in trials {
+            let n = particles.len() as f32;
+            match trial {
+                Some(position) => {
+                    let index = next_insertion;
+                    next_insertion += 1;
+
+                    let delta = self.energy_now(
+                        parameters,
+                        position,
+                        insertion_energies[index],
+                        None,
+                        existing,
+                        &accepted,
+                    );
+                    let acceptance =
+                        activity * volume / (n + 1.0) * (-delta / self.temperature).exp();
+                    if rng.gen::<f32>() < acceptance {
+                        accepted.inserted.push((index, position));
+                        particles.push(Particle::Inserted(index));
+                    }
+                }
+                None => {
+                    if particles.is_empty() {
+                        continue;
+                    }
+
+                    let slot = rng.gen_range(0..particles.len());
+                    let particle = particles[slot];
+                    let (position, initial) = match particle {
+                        Particle::Existing(index) => (existing[index], existing_energies[index]),
+                        Particle::Inserted(index) => (
+                            accepted
+                                .inserted
+                                .iter()
+                                .find(|(i, _)| *i == index)
+                                .unwrap()
+                                .1,
+                            insertion_energies[index],
+                        ),
+                    };
+
+                    let energy = self.energy_now(
+                        parameters,
+                        position,
+                        initial,
+                        Some(particle),
+                        existing,
+                        &accepted,
+                    );
+                    let acceptance = n / (activity * volume) * (energy / self.temperature).exp();
+                    if rng.gen::<f32>() < acceptance {
+                        particles.swap_remove(slot);
+                        match particle {
+                            Particle::Existing(index) => accepted.deleted.push(index),
+                            Particle::Inserted(index) => {
+                                accepted.inserted.retain(|(i, _)| *i != index)
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        accepted
+    }
+
+    /// the energy of an atom of the species at `position` with the configuration after the
+    /// `accepted` moves, given its `initial` energy with the configuration at the start of the
+    /// cycle. `this` is the particle at the position, if any, which does not interact with
+    /// itself.
+    fn energy_now(
+        &self,
+        parameters: &SimulationParameters,
+        position: Vector2<f32>,
+        initial: f32,
+        this: Option<Particle>,
+        existing: &[Vector2<f32>],
+        accepted: &Accepted,
+    ) -> f32 {
+        let pair_energy = |other: Vector2<f32>| {
+            let pair = parameters.pair_potential(self.species, self.species);
+            pair.energy(parameters.separation(position, other).norm_squared())
+        };
+        let removed = accepted
+            .deleted
+            .iter()
+            .filter(|&&index| this != Some(Particle::Existing(index)))
+            .map(|&index| pair_energy(existing[index]))
+            .sum::<f32>();
+        let added = accepted
+            .inserted
+            .iter()
+            .filter(|(index, _)| this != Some(Particle::Inserted(*index)))
+            .map(|&(_, other)| pair_energy(other))
+            .sum::<f32>();
+        initial - removed + added
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario::{Overrides, Setup};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::path::Path;
+
+    /// argon in a periodic box, whose lennard-jones potential the cycles see
+    const ARGON: &str = r#"
+        [box]
+        side_length = 20.0
+        cell_size = 2.5
+        [[species]]
+        name = "Ar"
+        mass = 1.0
+        [[generators]]
+        kind = "random_gas"
+        species = "Ar"
+        count = 1
+        min_separation = 1.0
+        [[potentials]]
+        kind = "lennard_jones"
+        species = ["Ar", "Ar"]
+        epsilon = 1.0
+        sigma = 1.0
+        [[stages]]
+        kind = "produce"
+        steps = 1
+    "#;
+
+    fn parameters() -> SimulationParameters {
+        Setup::parse(ARGON, Path::new("."), Overrides::default())
+            .unwrap()
+            .parameters
+    }
+
+    fn gcmc(chemical_potential: f32) -> Gcmc {
+        Gcmc {
+            species: 0,
+            chemical_potential,
+            temperature: 1.0,
+            attempts: 100,
+            every: 1,
+            origin: Vector2::new(2.0, 3.0),
+            size: Vector2::new(10.0, 10.0),
+        }
+    }
+
+    /// the energy of an atom at `position` with the atoms at `others`, summed directly
+    fn brute_force<'a>(
+        parameters: &SimulationParameters,
+        position: Vector2<f32>,
+        others: impl Iterator<Item = &'a Vector2<f32>>,
+    ) -> f32 {
+        let pair = parameters.pair_potential(0, 0);
+        others
+            .map(|&other| pair.energy(parameters.separation(position, other).norm_squared()))
+            .sum()
+    }
+
+    #[test]
+    fn energy_now_matches_brute_force() {
+        let parameters = parameters();
+        let gcmc = gcmc(0.0);
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let positions = generators::random_gas(gcmc.origin, gcmc.size, 40, 1.0, 0, None, &mut rng)
+            .iter()
+            .map(|atom| atom.position)
+            .collect::<Vec<_>>();
+        let (existing, insertions) = positions.split_at(30);
+
+        // the energies with the configuration at the start of the cycle
+        let initial_existing = (0..existing.len())
+            .map(|index| {
+                let others = existing
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, _)| *other != index);
+                brute_force(&parameters, existing[index], others.map(|(_, other)| other))
+            })
+            .collect::<Vec<_>>();
+        let initial_insertions = insertions
+            .iter()
+            .map(|&position| brute_force(&parameters, position, existing.iter()))
+            .collect::<Vec<_>>();
+
+        let accepted = Accepted {
+            deleted: vec![0, 3, 7, 12],
+            inserted: [1, 4, 6, 9]
+                .map(|index| (index, insertions[index]))
+                .to_vec(),
+        };
+        let current = existing
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !accepted.deleted.contains(index))
+            .map(|(_, &position)| position)
+            .chain(accepted.inserted.iter().map(|&(_, position)| position))
+            .collect::<Vec<_>>();
+        let without = |position: Vector2<f32>| {
+            let others = current.iter().filter(move |&&other| other != position);
+            brute_force(&parameters, position, others)
+        };
+        let assert_close = |now: f32, expected: f32| {
+            assert!(
+                (now - expected).abs() < 1e-4 * expected.abs().max(1.0),
+                "{now} != {expected}"
+            );
+        };
+
+        for index in (0..existing.len()).filter(|index| !accepted.deleted.contains(index)) {
+            let now = gcmc.energy_now(
+                &parameters,
+                existing[index],
+                initial_existing[index],
+                Some(Particle::Existing(index)),
+                existing,
+                &accepted,
+            );
+            assert_close(now, without(existing[index]));
+        }
+        for &(index, position) in &accepted.inserted {
+            let now = gcmc.energy_now(
+                &parameters,
+                position,
+                initial_insertions[index],
+                Some(Particle::Inserted(index)),
+                existing,
+                &accepted,
+            );
+            assert_close(now, without(position));
+        }
+        for index in [0, 2, 5, 8] {
+            let position = insertions[index];
+            let now = gcmc.energy_now(
+                &parameters,
+                position,
+                initial_insertions[index],
+                None,
+                existing,
+                &accepted,
+            );
+            assert_close(now, brute_force(&parameters, position, current.iter()));
+        }
+    }
+
+    /// the average amount of atoms in the region over cycles of an ideal gas, all of whose
+    /// atoms have the given energy
+    fn average_count(gcmc: &Gcmc, energy: f32, cycles: usize) -> f32 {
+        let mut parameters = parameters();
+        parameters.pair_potentials[0].cutoff = 0.0;
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+
+        let mut positions = Vec::<Vector2<f32>>::new();
+        let mut total = 0;
+        for cycle in 0..cycles {
+            let trials = (0..gcmc.attempts)
+                .map(|_| {
+                    rng.gen_bool(0.5).then(|| {
+                        gcmc.origin + gcmc.size.component_mul(&Vector2::new(rng.gen(), rng.gen()))
+                    })
+                })
+                .collect::<Vec<_>>();
+            let insertions = trials.iter().flatten().count();
+            let accepted = gcmc.accept(
+                &parameters,
+                &positions,
+                &vec![energy; positions.len()],
+                &trials,
+                &vec![energy; insertions],
+                &mut rng,
+            );
+
+            positions = positions
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !accepted.deleted.contains(index))
+                .map(|(_, &position)| position)
+                .chain(accepted.inserted.iter().map(|&(_, position)| position))
+                .collect();
+            // the first cycles fill the empty region
+            if cycle >= cycles / 10 {
+                total += positions.len();
+            }
+        }
+        total as f32 / (cycles - cycles / 10) as f32
+    }
+
+    #[test]
+    fn acceptance_samples_the_grand_canonical_ensemble() {
+        // the amount of atoms of an ideal gas is poisson distributed with the mean
+        // `z V exp(-E / T)`, here with `z V = 20`
+        let gcmc = gcmc((20.0f32 / 100.0).ln());
+        for (energy, expected) in [(0.0, 20.0), (2f32.ln(), 10.0), (-2f32.ln(), 40.0)] {
+            let average = average_count(&gcmc, energy, 400);
+            assert!(
+                (average - expected).abs() < 0.05 * expected,
+                "{average} atoms instead of {expected} at an energy of {energy}"
+            );
+        }
+    }
+}
@@ -0,0 +1,705 @@
+use crate::simulation::{kinetic_energy_and_temperature, Atom};
+use nalgebra::Vector2;
+use rand::Rng;
+
+/// atoms at rest of the given species at the given positions
+fn atoms_at(positions: impl Iterator<Item = Vector2<f32>>, species: u32) -> Vec<Atom> {
+    positions
+        .map(|position| {
+            Atom::new(position, Vector2::zeros(), Vector2::zeros()).with_species(species)
+        })
+        .collect()
+}
+
+/// a square lattice with the given lattice constant filling the rectangle
+/// `origin..origin + size`.
+pub fn square_lattice(
+    origin: Vector2<f32>,
+    size: Vector2<f32>,
+    spacing: f32,
+    species: u32,
+) -> Vec<Atom> {
+    let columns = (size.x / spacing).floor() as usize;
+    let rows = (size.y / spacing).floor() as usize;
+
+    let positions = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (row, column)))
+        .map(|(row, column)| {
+            origin + Vector2::new(column as f32 + 0.5, row as f32 + 0.5) * spacing
+        });
+    atoms_at(positions, species)
+}
+
+/// a hexagonal lattice with the given nearest neighbour distance filling the rectangle
+/// `origin..origin + size`. Every other row is shifted by half the spacing.
+pub fn hexagonal_lattice(
+    origin: Vector2<f32>,
+    size: Vector2<f32>,
+    spacing: f32,
+    species: u32,
+) -> Vec<Atom> {
+    let row_height = spacing * 3.0f32.sqrt() * 0.5;
+    let columns = (size.x / spacing).floor() as usize;
+    let rows = (size.y / row_height).floor() as usize;
+
+    let positions = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (row, column)))
+        .map(|(row, column)| {
+            let offset = if row % 2 == 1 { 0.0 } else { 0.5 };
+            origin
+                + Vector2::new(
+                    (column as f32 + offset) * spacing,
+                    (row as f32 + 0.5) * row_height,
+                )
+        });
+    atoms_at(positions, species)
+}
+
+/// a honeycomb (graphene-like) lattice with the given bond length filling the rectangle
+/// `origin..origin + size`, with zigzag rows along x. The rectangular unit cell of
+/// `sqrt(3) * spacing` by `3 * spacing` holds four atoms.
+pub fn honeycomb_lattice(
+    origin: Vector2<f32>,
+    size: Vector2<f32>,
+    spacing: f32,
+    species: u32,
+) -> Vec<Atom> {
+    let cell = Vector2::new(3.0f32.sqrt(), 3.0) * spacing;
+    let basis = [(0.25, 0.25), (0.25, 1.25), (0.75, 1.75), (0.75, 2.75)]
+        .map(|(x, y)| Vector2::new(x * cell.x, y * spacing));
+    let columns = (size.x / cell.x).ceil() as usize;
+    let rows = (size.y / cell.y).ceil() as usize;
+
+    // cells are only partially filled along the upper edges, so atoms outside are dropped
+    let positions = (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (row, column)))
+        .flat_map(|(row, column)| {
+            let corner = Vector2::new(column as f32 * cell.x, row as f32 * cell.y);
+            basis.map(|offset| corner + offset)
+        })
+        .filter(|position| position.x < size.x && position.y < size.y)
+        .map(|position| origin + position);
+    atoms_at(positions, species)
+}
+
+/// a uniform background grid over a rectangle, used to find nearby points when sampling
+/// positions with a minimum separation
+struct PointGrid {
+    origin: Vector2<f32>,
+    cell_size: f32,
+    columns: usize,
+    rows: usize,
+    /// the index of the point in each cell, cells are small enough to hold at most one
+    cells: Vec<Option<usize>>,
+    points: Vec<Vector2<f32>>,
+}
+
+impl PointGrid {
+    fn new(origin: Vector2<f32>, size: Vector2<f32>, min_separation: f32) -> Self {
+        let cell_size = min_separation / 2.0f32.sqrt();
+        let columns = (size.x / cell_size).ceil().max(1.0) as usize;
+        let rows = (size.y / cell_size).ceil().max(1.0) as usize;
+
+        Self {
+            origin,
+            cell_size,
+            columns,
+            rows,
+            cells: vec![None; columns * rows],
+            points: Vec::new(),
+        }
+    }
+
+    fn cell(&self, point: Vector2<f32>) -> (usize, usize) {
+        let relative = (point - self.origin) / self.cell_size;
+        (
+            (relative.x.max(0.0) as usize).min(self.columns - 1),
+            (relative.y.max(0.0) as usize).min(self.rows - 1),
+        )
+    }
+
+    /// whether no point is closer than `min_separation` to `point`
+    fn is_free(&self, point: Vector2<f32>, min_separation: f32) -> bool {
+        let (column, row) = self.cell(point);
+        // the separation spans at most two cells in each direction
+        let columns = column.saturating_sub(2)..(column + 3).min(self.columns);
+        let rows = row.saturating_sub(2)..(row + 3).min(self.rows);
+
+        rows.flat_map(|row| columns.clone().map(move |column| (row, column)))
+            .filter_map(|(row, column)| self.cells[row * self.columns + column])
+            .all(|index| (self.points[index] - point).norm() >= min_separation)
+    }
+
+    fn insert(&mut self, point: Vector2<f32>) {
+        let (column, row) = self.cell(point);
+        self.cells[row * self.columns + column] = Some(self.points.len());
+        self.points.push(point);
+    }
+}
+
+/// up to `count` atoms placed uniformly at random in the rectangle `origin..origin + size`,
+/// and inside `shape` if given, such that no two are closer than `min_separation`. Positions
+/// are drawn by rejection, so fewer atoms are returned if the region is too crowded to place
+/// all of them.
+pub fn random_gas(
+    origin: Vector2<f32>,
+    size: Vector2<f32>,
+    count: usize,
+    min_separation: f32,
+    species: u32,
+    shape: Option<&Shape>,
+    rng: &mut impl Rng,
+) -> Vec<Atom> {
+    const ATTEMPTS_PER_ATOM: usize = 100;
+
+    let mut grid = PointGrid::new(origin, size, min_separation);
+    for _ in 0..count * ATTEMPTS_PER_ATOM {
+        if grid.points.len() == count {
+            break;
+        }
+        let point = origin + size.component_mul(&Vector2::new(rng.gen(), rng.gen()));
+        let inside = shape.is_none_or(|shape| shape.contains(point));
+        if inside && grid.is_free(point, min_separation) {
+            grid.insert(point);
+        }
+    }
+
+    atoms_at(grid.points.into_iter(), species)
+}
+
+/// a poisson-disk sample of the rectangle `origin..origin + size`, i.e. random positions no
+/// closer than `min_separation` that leave no gap an additional atom would fit into. Uses
+/// bridson's algorithm.
+pub fn poisson_disk(
+    origin: Vector2<f32>,
+    size: Vector2<f32>,
+    min_separation: f32,
+    species: u32,
+    rng: &mut impl Rng,
+) -> Vec<Atom> {
+    /// candidates tried around an active point before it is retired
+    const CANDIDATES: usize = 30;
+
+    let mut grid = PointGrid::new(origin, size, min_separation);
+    let inside = |point: Vector2<f32>| {
+        let relative = point - origin;
+        relative.x >= 0.0 && relative.y >= 0.0 && relative.x < size.x && relative.y < size.y
+    };
+
+    grid.insert(origin + size.component_mul(&Vector2::new(rng.gen(), rng.gen())));
+    let mut active = vec![0];
+    while !active.is_empty() {
+        let slot = rng.gen_range(0..active.len());
+        let center = grid.points[active[slot]];
+
+        let candidate = (0..CANDIDATES)
+            .map(|_| {
+                // uniform in the annulus between one and two times the separation
+                let r = min_separation * (1.0 + 3.0 * rng.gen::<f32>()).sqrt();
+                let angle = rng.gen::<f32>() * std::f32::consts::TAU;
+                center + Vector2::new(angle.cos(), angle.sin()) * r
+            })
+            .find(|&point| inside(point) && grid.is_free(point, min_separation));
+
+        match candidate {
+            Some(point) => {
+                active.push(grid.points.len());
+                grid.insert(point);
+            }
+            None => {
+                active.swap_remove(slot);
+            }
+        }
+    }
+
+    atoms_at(grid.points.into_iter(), species)
+}
+
+/// how the atoms of a polymer are bonded
+#[derive(Copy, Clone, Debug)]
+pub enum Architecture {
+    /// a chain of `length` atoms
+    Linear { length: usize },
+    /// a closed chain of `length` atoms. Rings are laid out as regular polygons rather than
+    /// walks, which the dynamics then relax.
+    Ring { length: usize },
+    /// `arms` chains of `arm_length` atoms, all bonded to a common center atom
+    Star { arms: usize, arm_length: usize },
+}
+
+impl Architecture {
+    /// the amount of atoms of one polymer
+    pub fn atom_count(self) -> usize {
+        match self {
+            Architecture::Linear { length } | Architecture::Ring { length } => length,
+            Architecture::Star { arms, arm_length } => 1 + arms * arm_length,
+        }
+    }
+}
+
+/// how the chains of polymers are grown
+#[derive(Copy, Clone, Debug)]
+pub enum Walk {
+    /// every step goes in a uniformly random direction. Chains may cross themselves and each
+    /// other, so atoms can overlap and usually have to be separated by a minimization stage.
+    Random,
+    /// steps that come closer than `min_separation` to another atom of the generator are
+    /// redrawn. `min_separation` must be less than the bond length.
+    SelfAvoiding { min_separation: f32 },
+}
+
+/// generated atoms along with the bonds between them, given as indices into the atoms
+pub struct Polymers {
+    pub atoms: Vec<Atom>,
+    pub bonds: Vec<[usize; 2]>,
+}
+
+/// builds polymers of one architecture and species with bonds of the same length
+pub struct PolymerGenerator {
+    pub architecture: Architecture,
+    pub walk: Walk,
+    pub bond_length: f32,
+    pub species: u32,
+}
+
+impl PolymerGenerator {
+    /// up to `count` polymers in the rectangle `origin..origin + size`. A polymer that cannot
+    /// be grown is started over, and given up on after a number of attempts, so fewer polymers
+    /// are returned if the region is too crowded or too small.
+    pub fn generate(
+        &self,
+        origin: Vector2<f32>,
+        size: Vector2<f32>,
+        count: usize,
+        rng: &mut impl Rng,
+    ) -> Polymers {
+        const ATTEMPTS_PER_POLYMER: usize = 100;
+
+        let mut grid = match self.walk {
+            Walk::Random => None,
+            Walk::SelfAvoiding { min_separation } => {
+                Some(PointGrid::new(origin, size, min_separation))
+            }
+        };
+        let mut polymers = Polymers {
+            atoms: Vec::new(),
+            bonds: Vec::new(),
+        };
+        for _ in 0..count {
+            let Some(polymer) = (0..ATTEMPTS_PER_POLYMER)
+                .find_map(|_| self.polymer(origin, size, grid.as_ref(), rng))
+            else {
+                break;
+            };
+
+            let offset = polymers.atoms.len();
+            polymers.bonds.extend(
+                polymer
+                    .bonds
+                    .into_iter()
+                    .map(|[a, b]| [offset + a, offset + b]),
+            );
+            if let Some(grid) = &mut grid {
+                for atom in &polymer.atoms {
+                    grid.insert(atom.position);
+                }
+            }
+            polymers.atoms.extend(polymer.atoms);
+        }
+        polymers
+    }
+
+    /// the atoms and bonds of a single polymer, or none if it got stuck
+    fn polymer(
+        &self,
+        origin: Vector2<f32>,
+        size: Vector2<f32>,
+        grid: Option<&PointGrid>,
+        rng: &mut impl Rng,
+    ) -> Option<Polymers> {
+        let accept = |point: Vector2<f32>, points: &[Vector2<f32>]| {
+            let relative = point - origin;
+            let inside = relative.x >= 0.0
+                && relative.y >= 0.0
+                && relative.x < size.x
+                && relative.y < size.y;
+            inside && self.is_free(point, points, grid)
+        };
+        let mut points = Vec::with_capacity(self.architecture.atom_count());
+        let mut bonds = Vec::new();
+
+        match self.architecture {
+            Architecture::Linear { length } => {
+                let start = origin + size.component_mul(&Vector2::new(rng.gen(), rng.gen()));
+                accept(start, &points).then_some(())?;
+                points.push(start);
+                self.grow(&mut points, &mut bonds, 0, length - 1, accept, rng)?;
+            }
+            Architecture::Ring { length } => {
+                // the circumradius of a regular polygon with sides of the bond length. The
+                // center is drawn so that the whole ring lies inside the region.
+                let radius =
+                    self.bond_length / (2.0 * (std::f32::consts::PI / length as f32).sin());
+                let free = size - Vector2::repeat(2.0 * radius);
+                if free.x < 0.0 || free.y < 0.0 {
+                    return None;
+                }
+                let center = origin
+                    + Vector2::repeat(radius)
+                    + free.component_mul(&Vector2::new(rng.gen(), rng.gen()));
+                let rotation = rng.gen::<f32>() * std::f32::consts::TAU;
+                for index in 0..length {
+                    let angle = rotation + std::f32::consts::TAU * index as f32 / length as f32;
+                    let point = center + Vector2::new(angle.cos(), angle.sin()) * radius;
+                    // the other atoms of a ring are at least a bond length apart
+                    accept(point, &[]).then_some(())?;
+                    points.push(point);
+                }
+                bonds.extend((0..length).map(|index| [index, (index + 1) % length]));
+            }
+            Architecture::Star { arms, arm_length } => {
+                let center = origin + size.component_mul(&Vector2::new(rng.gen(), rng.gen()));
+                accept(center, &points).then_some(())?;
+                points.push(center);
+                for _ in 0..arms {
+                    self.grow(&mut points, &mut bonds, 0, arm_length, accept, rng)?;
+                }
+            }
+        }
+        Some(Polymers {
+            atoms: atoms_at(points.into_iter(), self.species),
+            bonds,
+        })
+    }
+
+    /// appends a walk of `steps` atoms to `points`, starting with a bond to the atom at index
+    /// `start`. Returns none if a step cannot be placed.
+    fn grow(
+        &self,
+        points: &mut Vec<Vector2<f32>>,
+        bonds: &mut Vec<[usize; 2]>,
+        start: usize,
+        steps: usize,
+        accept: impl Fn(Vector2<f32>, &[Vector2<f32>]) -> bool,
+        rng: &mut impl Rng,
+    ) -> Option<()> {
+        const ATTEMPTS_PER_STEP: usize = 100;
+
+        let mut last = start;
+        for _ in 0..steps {
+            let next = (0..ATTEMPTS_PER_STEP)
+                .map(|_| {
+                    let angle = rng.gen::<f32>() * std::f32::consts::TAU;
+                    points[last] + Vector2::new(angle.cos(), angle.sin()) * self.bond_length
+                })
+                .find(|&point| accept(point, points))?;
+            bonds.push([last, points.len()]);
+            last = points.len();
+            points.push(next);
+        }
+        Some(())
+    }
+
+    /// whether `point` keeps the minimum separation of a self-avoiding walk to the atoms of
+    /// previous polymers in `grid` and to the atoms of the current polymer in `points`
+    fn is_free(
+        &self,
+        point: Vector2<f32>,
+        points: &[Vector2<f32>],
+        grid: Option<&PointGrid>,
+    ) -> bool {
+        match self.walk {
+            Walk::Random => true,
+            Walk::SelfAvoiding { min_separation } => {
+                grid.is_none_or(|grid| grid.is_free(point, min_separation))
+                    && points
+                        .iter()
+                        .all(|&other| (other - point).norm() >= min_separation)
+            }
+        }
+    }
+}
+
+/// a region of the box that generated atoms can be cut to or removed from
+#[derive(Copy, Clone, Debug)]
+pub enum Shape {
+    /// a disk. As the simulation is two dimensional, this is also the cross section of a
+    /// cylinder perpendicular to the plane.
+    Droplet { center: Vector2<f32>, radius: f32 },
+    /// the band `from..to` along `axis`, infinite along the other axis
+    Slab { axis: usize, from: f32, to: f32 },
+}
+
+impl Shape {
+    pub fn contains(&self, position: Vector2<f32>) -> bool {
+        match *self {
+            Shape::Droplet { center, radius } => {
+                (position - center).norm_squared() <= radius * radius
+            }
+            Shape::Slab { axis, from, to } => (from..to).contains(&position[axis]),
+        }
+    }
+}
+
+/// the atoms inside the shape, e.g. a lattice cut to a droplet or a slab
+pub fn carve(atoms: Vec<Atom>, shape: &Shape) -> Vec<Atom> {
+    atoms
+        .into_iter()
+        .filter(|atom| shape.contains(atom.position))
+        .collect()
+}
+
+/// the atoms outside the shape, i.e. the atoms with a void of the given shape cut out
+pub fn void(atoms: Vec<Atom>, shape: &Shape) -> Vec<Atom> {
+    atoms
+        .into_iter()
+        .filter(|atom| !shape.contains(atom.position))
+        .collect()
+}
+
+/// displaces every atom by a random vector drawn uniformly from a disk of the given radius,
+/// e.g. to break the symmetry of a perfect lattice.
+pub fn jitter(atoms: &mut [Atom], radius: f32, rng: &mut impl Rng) {
+    for atom in atoms {
+        let r = radius * rng.gen::<f32>().sqrt();
+        let angle = rng.gen::<f32>() * std::f32::consts::TAU;
+        atom.position += Vector2::new(angle.cos(), angle.sin()) * r;
+    }
+}
+
+/// a velocity drawn from the maxwell-boltzmann distribution of a single atom of the given mass
+/// at the given temperature, in units where `k_B = 1`
+pub fn thermal_velocity(mass: f32, temperature: f32, rng: &mut impl Rng) -> Vector2<f32> {
+    // box-muller, every component is normal with variance `T / m`
+    let r = (-2.0 * (1.0 - rng.gen::<f32>()).ln()).sqrt();
+    let angle = rng.gen::<f32>() * std::f32::consts::TAU;
+    Vector2::new(angle.cos(), angle.sin()) * r * (temperature / mass).sqrt()
+}
+
+/// draws the velocities of the atoms with the given masses from the maxwell-boltzmann
+/// distribution at the given temperature, in units where `k_B = 1`. The centre of mass
+/// momentum is removed and the velocities are rescaled afterwards so that the temperature of
+/// the remaining `2N - 2 - constraints` degrees of freedom is exactly the requested one.
+pub fn maxwell_boltzmann(
+    atoms: &mut [Atom],
+    masses: &[f32],
+    constraints: usize,
+    temperature: f32,
+    rng: &mut impl Rng,
+) {
+    for (atom, &mass) in atoms.iter_mut().zip(masses) {
+        atom.velocity = thermal_velocity(mass, temperature, rng);
+    }
+
+    let total_mass = masses.iter().sum::<f32>();
+    let momentum = atoms
+        .iter()
+        .zip(masses)
+        .map(|(atom, mass)| atom.velocity * *mass)
+        .sum::<Vector2<f32>>();
+    let drift = momentum / total_mass;
+    for atom in atoms.iter_mut() {
+        atom.velocity -= drift;
+    }
+
+    let (_, current) = kinetic_energy_and_temperature(atoms, masses, constraints);
+    // a single atom has no velocity left once its momentum is removed
+    if current > 0.0 {
+        let scale = (temperature / current).sqrt();
+        for atom in atoms.iter_mut() {
+            atom.velocity *= scale;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    const ORIGIN: Vector2<f32> = Vector2::new(1.0, 2.0);
+    const SIZE: Vector2<f32> = Vector2::new(12.0, 10.0);
+
+    fn positions(atoms: &[Atom]) -> Vec<Vector2<f32>> {
+        atoms.iter().map(|atom| atom.position).collect()
+    }
+
+    /// the smallest distance between any two of the positions
+    fn min_distance(positions: &[Vector2<f32>]) -> f32 {
+        positions
+            .iter()
+            .enumerate()
+            .flat_map(|(index, a)| positions[index + 1..].iter().map(move |b| (a - b).norm()))
+            .fold(f32::INFINITY, f32::min)
+    }
+
+    /// the amount of other positions at `distance` from the position at `index`
+    fn neighbours(positions: &[Vector2<f32>], index: usize, distance: f32) -> usize {
+        positions
+            .iter()
+            .filter(|other| ((*other - positions[index]).norm() - distance).abs() < 1e-4)
+            .count()
+    }
+
+    fn inside(position: Vector2<f32>) -> bool {
+        let relative = position - ORIGIN;
+        relative.x >= 0.0 && relative.y >= 0.0 && relative.x < SIZE.x && relative.y < SIZE.y
+    }
+
+    /// whether the position is at least `margin` away from the edges of the region
+    fn interior(position: Vector2<f32>, margin: f32) -> bool {
+        let relative = position - ORIGIN;
+        relative.x >= margin
+            && relative.y >= margin
+            && relative.x <= SIZE.x - margin
+            && relative.y <= SIZE.y - margin
+    }
+
+    #[test]
+    fn lattices_have_their_coordination() {
+        for (atoms, coordination) in [
+            (square_lattice(ORIGIN, SIZE, 1.0, 0), 4),
+            (hexagonal_lattice(ORIGIN, SIZE, 1.0, 0), 6),
+            (honeycomb_lattice(ORIGIN, SIZE, 1.0, 0), 3),
+        ] {
+            let positions = positions(&atoms);
+            assert!(positions.iter().all(|&position| inside(position)));
+            assert!((min_distance(&positions) - 1.0).abs() < 1e-4);
+            let interior = (0..positions.len())
+                .filter(|&index| interior(positions[index], 1.5))
+                .collect::<Vec<_>>();
+            assert!(!interior.is_empty());
+            for index in interior {
+                assert_eq!(neighbours(&positions, index, 1.0), coordination);
+            }
+        }
+
+        assert_eq!(square_lattice(ORIGIN, SIZE, 1.0, 0).len(), 120);
+        assert!(square_lattice(ORIGIN, SIZE, 1.0, 3)
+            .iter()
+            .all(|atom| atom.species == 3));
+    }
+
+    #[test]
+    fn random_gas_keeps_its_separation() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let shape = Shape::Droplet {
+            center: ORIGIN + SIZE * 0.5,
+            radius: 4.0,
+        };
+        let atoms = random_gas(ORIGIN, SIZE, 30, 1.0, 0, Some(&shape), &mut rng);
+        assert_eq!(atoms.len(), 30);
+        assert!(min_distance(&positions(&atoms)) >= 1.0);
+        assert!(atoms.iter().all(|atom| shape.contains(atom.position)));
+
+        // the droplet cannot hold a thousand atoms, so fewer are returned
+        let crowded = random_gas(ORIGIN, SIZE, 1000, 1.0, 0, Some(&shape), &mut rng);
+        assert!(crowded.len() < 1000);
+        assert!(min_distance(&positions(&crowded)) >= 1.0);
+    }
+
+    #[test]
+    fn poisson_disk_is_separated_and_leaves_no_gaps() {
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let positions = positions(&poisson_disk(ORIGIN, SIZE, 1.0, 0, &mut rng));
+        assert!(positions.iter().all(|&position| inside(position)));
+        assert!(min_distance(&positions) >= 1.0);
+
+        // every point of the region is within twice the separation of an atom, or another
+        // atom would have fit in there
+        for x in 0..=24 {
+            for y in 0..=20 {
+                let point = ORIGIN + Vector2::new(x as f32, y as f32) * 0.5;
+                let nearest = positions
+                    .iter()
+                    .map(|position| (position - point).norm())
+                    .fold(f32::INFINITY, f32::min);
+                assert!(nearest < 2.0, "gap of {nearest} at {point:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn self_avoiding_polymers_keep_their_separation() {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        for architecture in [
+            Architecture::Linear { length: 10 },
+            Architecture::Star {
+                arms: 3,
+                arm_length: 4,
+            },
+        ] {
+            let generator = PolymerGenerator {
+                architecture,
+                walk: Walk::SelfAvoiding {
+                    min_separation: 0.9,
+                },
+                bond_length: 1.0,
+                species: 1,
+            };
+            let polymers = generator.generate(ORIGIN, SIZE, 5, &mut rng);
+            let positions = positions(&polymers.atoms);
+            assert_eq!(positions.len(), 5 * architecture.atom_count());
+            assert_eq!(polymers.bonds.len(), 5 * (architecture.atom_count() - 1));
+            assert!(positions.iter().all(|&position| inside(position)));
+            assert!(min_distance(&positions) >= 0.9, "{architecture:?}");
+            for [a, b] in polymers.bonds {
+                assert!(((positions[a] - positions[b]).norm() - 1.0).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn rings_are_closed() {
+        let mut rng = ChaCha8Rng::seed_from_u64(4);
+        let generator = PolymerGenerator {
+            architecture: Architecture::Ring { length: 8 },
+            walk: Walk::SelfAvoiding {
+                min_separation: 0.9,
+            },
+            bond_length: 1.0,
+            species: 0,
+        };
+        let polymers = generator.generate(ORIGIN, SIZE, 3, &mut rng);
+        let positions = positions(&polymers.atoms);
+        assert_eq!(positions.len(), 24);
+        assert!(min_distance(&positions) >= 0.9);
+        for ring in 0..3 {
+            let first = ring * 8;
+            let bonds = &polymers.bonds[first..first + 8];
+            // every atom is bonded to two others, and the last one back to the first
+            assert_eq!(bonds[7], [first + 7, first]);
+            for index in first..first + 8 {
+                let count = bonds
+                    .iter()
+                    .flatten()
+                    .filter(|&&atom| atom == index)
+                    .count();
+                assert_eq!(count, 2);
+            }
+            for &[a, b] in bonds {
+                assert!(((positions[a] - positions[b]).norm() - 1.0).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn maxwell_boltzmann_hits_the_temperature() {
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        let mut atoms = square_lattice(ORIGIN, SIZE, 1.0, 0);
+        let masses = (0..atoms.len())
+            .map(|index| 1.0 + (index % 3) as f32)
+            .collect::<Vec<_>>();
+        maxwell_boltzmann(&mut atoms, &masses, 10, 1.5, &mut rng);
+
+        let momentum = atoms
+            .iter()
+            .zip(&masses)
+            .map(|(atom, mass)| atom.velocity * *mass)
+            .sum::<Vector2<f32>>();
+        assert!(momentum.norm() < 1e-3, "{momentum:?}");
+        let (_, temperature) = kinetic_energy_and_temperature(&atoms, &masses, 10);
+        assert!((temperature - 1.5).abs() < 1e-4, "{temperature}");
+    }
+}
@@ -1,22 +1,293 @@
-use crate::simulation::Atom;
+use crate::io::checkpoint::{Checkpoint, NeighbourListState};
+use crate::simulation::dispatch::Dispatch;
+use crate::simulation::ewald::{Ewald, Reciprocal};
+use crate::simulation::external::ExternalForces;
+use crate::simulation::layout::AtomLayout;
+use crate::simulation::ordering::Curve;
+use crate::simulation::precision::Precision;
+use crate::simulation::properties::{AtomProperties, Properties};
+use crate::simulation::readback::{read_buffer, read_values};
+use crate::simulation::topology::{BondPotential, Topology, MAX_ANGLES, MAX_BONDS};
+use crate::simulation::{whole_cells, Atom, Boundary, SimulationParameters, Thermostat};
 use bytemuck::{Pod, Zeroable};
+use eyre::{eyre, Result, WrapErr};
+use nalgebra::Vector2;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
 use std::mem::size_of;
-use std::sync::Arc;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
 use wgpu::{
-    include_wgsl, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingType, Buffer, BufferAddress, BufferBindingType, BufferUsages,
-    CommandBuffer, CommandEncoder, CommandEncoderDescriptor, ComputePassDescriptor,
-    ComputePipeline, ComputePipelineDescriptor, Device, PipelineLayoutDescriptor,
-    PushConstantRange, ShaderStages,
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferAddress, BufferBindingType, BufferDescriptor,
+    BufferSize, BufferUsages, CommandEncoder, CommandEncoderDescriptor, ComputePass,
+    ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device,
+    PipelineLayoutDescriptor, PushConstantRange, Queue, ShaderModuleDescriptor, ShaderSource,
+    ShaderStages,
 };
 
 pub const MAX_INDICES: usize = 16;
 
+/// the maximum amount of atoms in a neighbour list, see `Neighbours` in `interact.wgsl`
+pub const MAX_NEIGHBOURS: usize = 64;
+
+/// the workgroup size of the passes with one invocation per atom, probe or cell in
+/// `interact.wgsl`, which are all passes except monte carlo sweeps
+const ATOM_WORKGROUP_SIZE: u32 = 64;
+
+/// the workgroup size along each axis of `main_monte_carlo` in `interact.wgsl`
+const MONTE_CARLO_WORKGROUP_SIZE: u32 = 8;
+
+/// the size of the rounding error of the position of one atom in bytes
+const COMPENSATION_SIZE: BufferAddress = size_of::<Vector2<f32>>() as BufferAddress;
+
+const TOPOLOGY_BUFFER_USAGES: BufferUsages = BufferUsages::STORAGE.union(BufferUsages::COPY_DST);
+
+const ATOM_BUFFER_USAGES: BufferUsages = BufferUsages::STORAGE
+    .union(BufferUsages::COPY_DST)
+    .union(BufferUsages::COPY_SRC)
+    .union(BufferUsages::VERTEX);
+
+/// how a hash grid runs on the gpu, fixed at construction. Checkpoints do not depend on the
+/// options, so a hash grid may be restored with different ones.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpuOptions {
+    /// how the atoms are arranged in the atom buffers, which does not change the results
+    pub layout: AtomLayout,
+    /// how the positions of the atoms are accumulated
+    pub precision: Precision,
+    /// sorts the atoms in every cell by index after binning. Binning fills the cells in
+    /// whatever order the gpu schedules it, and the order of the cells decides the order in
+    /// which forces and energies are summed. Sorted cells make two runs with the same seed
+    /// produce bitwise identical trajectories on the same device, as long as no cell
+    /// overflows, at the cost of one more pass per time step.
+    pub deterministic: bool,
+    /// how the interaction and integration passes without neighbour lists are dispatched
+    pub dispatch: Dispatch,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub(crate) struct PushConstants {
+    cells_per_side: i32,
+    /// the index of the current time step, used to seed the gpu rng
+    step: u32,
+    atom_count: u32,
+    /// the shift of the cell grid, only used by monte carlo sweeps
+    offset: Vector2<f32>,
+    /// the checkerboard color of the cells a monte carlo pass updates
+    color: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+/// the uniform counterpart of [`SimulationParameters`], see `Parameters` in `interact.wgsl`
+struct GpuParameters {
+    grid_side_length: f32,
+    time_step: f32,
+    boundary_x: u32,
+    boundary_y: u32,
+    species_count: u32,
+    thermostat: u32,
+    temperature: f32,
+    damping: f32,
+    minimize: u32,
+    seed: u32,
+    cell_side_length: f32,
+    skin: f32,
+    position_precision: u32,
+    bonded: u32,
+    has_angles: u32,
+    constraint_tolerance: f32,
+    constraint_iterations: u32,
+    electrostatics: u32,
+    ewald_alpha: f32,
+    coulomb_cutoff_sq: f32,
+    mesh_size: u32,
+    mesh_order: u32,
+    gravity: Vector2<f32>,
+    electric_field: Vector2<f32>,
+    trap_count: u32,
+    force_field_width: u32,
+    force_field_height: u32,
+}
+
+impl GpuParameters {
+    fn new(
+        parameters: &SimulationParameters,
+        seed: u64,
+        precision: Precision,
+        minimize: bool,
+        thermostat: bool,
+    ) -> Self {
+        let force_field = parameters.external_forces.force_field.as_ref();
+        let (mesh_size, mesh_order) = match parameters.electrostatics.map(|ewald| ewald.reciprocal)
+        {
+            Some(Reciprocal::Mesh { size, order }) => (size, order),
+            _ => (0, 0),
+        };
+        let (thermostat, temperature, damping) = match parameters.thermostat.filter(|_| thermostat)
+        {
+            Some(Thermostat::Langevin {
+                temperature,
+                damping,
+            }) => (1, temperature, damping),
+            None => (0, 0.0, 0.0),
+        };
+
+        Self {
+            grid_side_length: parameters.grid_side_length,
+            time_step: parameters.time_step,
+            boundary_x: parameters.boundaries[0].gpu_id(),
+            boundary_y: parameters.boundaries[1].gpu_id(),
+            species_count: parameters.species.len() as u32,
+            thermostat,
+            temperature,
+            damping,
+            minimize: minimize as u32,
+            // the gpu rng works on 32 bit words, so both halves of the seed are folded together
+            seed: (seed ^ (seed >> 32)) as u32,
+            cell_side_length: parameters.cell_side_length,
+            skin: parameters.neighbour_skin.unwrap_or(0.0),
+            position_precision: precision.gpu_id(),
+            bonded: !parameters.bond_types.is_empty() as u32,
+            has_angles: !parameters.angle_types.is_empty() as u32,
+            constraint_tolerance: parameters.constraint_solver.tolerance,
+            constraint_iterations: parameters.constraint_solver.max_iterations,
+            electrostatics: parameters.electrostatics.is_some() as u32,
+            ewald_alpha: parameters.electrostatics.map_or(0.0, |ewald| ewald.alpha),
+            coulomb_cutoff_sq: parameters
+                .electrostatics
+                .map_or(0.0, |ewald| ewald.cutoff * ewald.cutoff),
+            mesh_size,
+            mesh_order,
+            gravity: parameters.external_forces.gravity,
+            electric_field: parameters.external_forces.electric_field,
+            trap_count: parameters.external_forces.traps.len() as u32,
+            force_field_width: force_field.map_or(0, |field| field.width),
+            force_field_height: force_field.map_or(0, |field| field.height),
+        }
+    }
+}
+
+/// the settings and acceptance statistics of monte carlo sweeps, see `MonteCarlo` in
+/// `interact.wgsl`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct GpuMonteCarlo {
+    temperature: f32,
+    max_displacement: f32,
+    moves: u32,
+    attempted: u32,
+    accepted: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct GpuPairPotential {
+    epsilon: f32,
+    sigma: f32,
+    cutoff_sq: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct GpuBondType {
+    kind: u32,
+    k: f32,
+    length: f32,
+}
+
+/// the bonds of one atom slot, see `Bonds` in `interact.wgsl`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct GpuBonds {
+    /// the slots of the bonded atoms, [`GpuBonds::NO_PARTNER`] for unused entries
+    partners: [u32; MAX_BONDS],
+    kinds: [u32; MAX_BONDS],
+}
+
+impl GpuBonds {
+    const NO_PARTNER: u32 = u32::MAX;
+
+    const EMPTY: Self = Self {
+        partners: [Self::NO_PARTNER; MAX_BONDS],
+        kinds: [0; MAX_BONDS],
+    };
+
+    fn push(&mut self, partner: u32, kind: u32) {
+        let entry = self
+            .partners
+            .iter()
+            .position(|&partner| partner == Self::NO_PARTNER)
+            .expect("atoms take part in at most `MAX_BONDS` bonds");
+        self.partners[entry] = partner;
+        self.kinds[entry] = kind;
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct GpuAngleType {
+    kind: u32,
+    k: f32,
+    angle: f32,
+}
+
+/// the angles one atom slot takes part in, see `Angles` in `interact.wgsl`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct GpuAngles {
+    /// the slots of both ends, the slot of the center and the kind of every angle, with the
+    /// first slot set to [`GpuBonds::NO_PARTNER`] for unused entries
+    entries: [[u32; 4]; MAX_ANGLES],
+}
+
+impl GpuAngles {
+    const EMPTY: Self = Self {
+        entries: [[GpuBonds::NO_PARTNER, 0, 0, 0]; MAX_ANGLES],
+    };
+
+    fn push(&mut self, entry: [u32; 4]) {
+        let free = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry[0] == GpuBonds::NO_PARTNER)
+            .expect("atoms take part in at most `MAX_ANGLES` angles");
+        *free = entry;
+    }
+}
+
+/// a wave vector of the reciprocal space part of the ewald sum, see `WaveVector` in
+/// `interact.wgsl`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct GpuWaveVector {
+    k: Vector2<f32>,
+    coefficient: f32,
+    _padding: f32,
+}
+
+/// a harmonic trap, see `Trap` in `interact.wgsl`
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct GpuTrap {
+    center: Vector2<f32>,
+    stiffness: f32,
+    species: u32,
+}
+
+/// a constrained bond between two atom slots, see `Constraint` in `interact.wgsl`
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
-struct PushConstants {
-    cells_per_side: u32,
+struct GpuConstraint {
+    a: u32,
+    b: u32,
+    length: f32,
 }
 
 #[repr(C)]
@@ -28,82 +299,726 @@ pub struct HashGridCell {
     /// the indices into the main atom array of the atoms that lie within this cell.
     indices: [i32; MAX_INDICES],
 }
+/// a test atom whose potential energy with the atoms of a hash grid is evaluated, see
+/// [`HashGrid::probe_energies`]
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct Probe {
+    pub position: Vector2<f32>,
+    pub species: u32,
+    /// the index of an atom to ignore, e.g. the probed atom itself, or -1
+    pub exclude: i32,
+}
+
+/// the constrained bonds grouped by molecule, see `main_constraints` in `interact.wgsl`
+struct Constraints {
+    constraint_buffer: Buffer,
+    molecule_buffer: Buffer,
+    /// the amount of constraints the hash grid started with. There cannot be more molecules
+    /// than constraints, and atoms are never bonded once created, so it bounds both.
+    capacity: u32,
+    bind_group: BindGroup,
+}
+
+impl Constraints {
+    /// the constraints of `topology` with the atoms in the slots given by `ids`, or none if
+    /// there are no constrained bonds
+    fn new(
+        device: &Device,
+        layout: &BindGroupLayout,
+        ids: &[u32],
+        topology: &Topology,
+        bond_types: &[BondPotential],
+    ) -> Option<Self> {
+        let capacity = topology
+            .bonds
+            .iter()
+            .filter(|bond| bond_types[bond.kind as usize].is_constraint())
+            .count() as u32;
+        if capacity == 0 {
+            return None;
+        }
+
+        let (constraints, molecules) = constraint_table(ids, topology, bond_types, capacity);
+        let constraint_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Constraint Buffer"),
+            contents: bytemuck::cast_slice(&constraints),
+            usage: TOPOLOGY_BUFFER_USAGES,
+        });
+        let molecule_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Molecule Buffer"),
+            contents: bytemuck::cast_slice(&molecules),
+            usage: TOPOLOGY_BUFFER_USAGES,
+        });
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Constraint Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 6,
+                    resource: constraint_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 7,
+                    resource: molecule_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Some(Self {
+            constraint_buffer,
+            molecule_buffer,
+            capacity,
+            bind_group,
+        })
+    }
+
+    /// writes the constraints of the atoms in the slots given by `ids`, see [`constraint_table`]
+    fn write(&self, queue: &Queue, ids: &[u32], topology: &Topology, bond_types: &[BondPotential]) {
+        let (constraints, molecules) = constraint_table(ids, topology, bond_types, self.capacity);
+        queue.write_buffer(
+            &self.constraint_buffer,
+            0,
+            bytemuck::cast_slice(&constraints),
+        );
+        queue.write_buffer(&self.molecule_buffer, 0, bytemuck::cast_slice(&molecules));
+    }
+}
+
+/// the reciprocal space part of the ewald sum, see `main_structure_factors` in `interact.wgsl`
+struct EwaldSum {
+    // only accessed through the bind group
+    _wave_vector_buffer: Buffer,
+    _structure_factor_buffer: Buffer,
+    wave_vector_count: u32,
+    bind_group: BindGroup,
+}
+
+impl EwaldSum {
+    fn new(
+        device: &Device,
+        layout: &BindGroupLayout,
+        ewald: &Ewald,
+        side_length: f32,
+        k_max: u32,
+    ) -> Self {
+        let wave_vectors = ewald
+            .wave_vectors(side_length, k_max)
+            .iter()
+            .map(|wave_vector| GpuWaveVector {
+                k: wave_vector.k.cast(),
+                coefficient: wave_vector.coefficient as f32,
+                _padding: 0.0,
+            })
+            .collect::<Vec<_>>();
+        let wave_vector_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Wave Vector Buffer"),
+            contents: bytemuck::cast_slice(&wave_vectors),
+            usage: BufferUsages::STORAGE,
+        });
+        let structure_factor_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Structure Factor Buffer"),
+            size: (wave_vectors.len() * size_of::<Vector2<f32>>()) as BufferAddress,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Ewald Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 8,
+                    resource: wave_vector_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 9,
+                    resource: structure_factor_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            _wave_vector_buffer: wave_vector_buffer,
+            _structure_factor_buffer: structure_factor_buffer,
+            wave_vector_count: wave_vectors.len() as u32,
+            bind_group,
+        }
+    }
+}
+
+/// the reciprocal space part of the ewald sum on a mesh, see `main_spread` in `interact.wgsl`
+struct ParticleMesh {
+    // only accessed through the bind group
+    _mesh_buffer: Buffer,
+    _influence_buffer: Buffer,
+    size: u32,
+    bind_group: BindGroup,
+}
+
+impl ParticleMesh {
+    fn new(
+        device: &Device,
+        layout: &BindGroupLayout,
+        ewald: &Ewald,
+        side_length: f32,
+        size: u32,
+        order: u32,
+    ) -> Self {
+        let influence = ewald
+            .influence_function(side_length, size, order)
+            .iter()
+            .map(|&influence| influence as f32)
+            .collect::<Vec<_>>();
+        let influence_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Influence Buffer"),
+            contents: bytemuck::cast_slice(&influence),
+            usage: BufferUsages::STORAGE,
+        });
+        let mesh_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Mesh Buffer"),
+            size: (influence.len() * size_of::<Vector2<f32>>()) as BufferAddress,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Particle Mesh Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 10,
+                    resource: mesh_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 11,
+                    resource: influence_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            _mesh_buffer: mesh_buffer,
+            _influence_buffer: influence_buffer,
+            size,
+            bind_group,
+        }
+    }
+}
+
+/// the traps and the force field of the external forces, see `external_force` in
+/// `interact.wgsl`. Always present, as the integration pass binds them either way, with a
+/// placeholder entry in empty buffers.
+struct ExternalForceBuffers {
+    // only accessed through the bind group
+    _trap_buffer: Buffer,
+    _force_field_buffer: Buffer,
+    bind_group: BindGroup,
+}
+
+impl ExternalForceBuffers {
+    fn new(device: &Device, layout: &BindGroupLayout, external_forces: &ExternalForces) -> Self {
+        let mut traps = external_forces
+            .traps
+            .iter()
+            .map(|trap| GpuTrap {
+                center: trap.center,
+                stiffness: trap.stiffness,
+                species: trap.species.unwrap_or(u32::MAX),
+            })
+            .collect::<Vec<_>>();
+        if traps.is_empty() {
+            traps.push(GpuTrap::zeroed());
+        }
+        let trap_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Trap Buffer"),
+            contents: bytemuck::cast_slice(&traps),
+            usage: BufferUsages::STORAGE,
+        });
+        let samples = external_forces
+            .force_field
+            .as_ref()
+            .map_or(vec![Vector2::zeros()], |field| field.samples.clone());
+        let force_field_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Force Field Buffer"),
+            contents: bytemuck::cast_slice(&samples),
+            usage: BufferUsages::STORAGE,
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("External Force Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 12,
+                    resource: trap_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 13,
+                    resource: force_field_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            _trap_buffer: trap_buffer,
+            _force_field_buffer: force_field_buffer,
+            bind_group,
+        }
+    }
+}
+
+/// how the reciprocal space part of the ewald sum is evaluated, see [`Reciprocal`]
+enum ReciprocalSpace {
+    Direct(EwaldSum),
+    Mesh(ParticleMesh),
+}
+
+impl ReciprocalSpace {
+    fn bind_group(&self) -> &BindGroup {
+        match self {
+            ReciprocalSpace::Direct(ewald) => &ewald.bind_group,
+            ReciprocalSpace::Mesh(mesh) => &mesh.bind_group,
+        }
+    }
+}
+
+/// the verlet neighbour lists of the atoms, see `main_build_neighbours` in `interact.wgsl`
+struct NeighbourLists {
+    neighbour_buffer: Buffer,
+    reference_buffer: Buffer,
+    /// the rebuild flag, cleared after every time step, followed by the overflow count
+    flag_buffer: Buffer,
+    bind_group: BindGroup,
+}
+
+impl NeighbourLists {
+    /// the offset of the overflow count in the flag buffer
+    const OVERFLOW_OFFSET: BufferAddress = size_of::<u32>() as BufferAddress;
+
+    /// lists with room for `capacity` atoms, restored from `state` if given and built in the
+    /// next time step otherwise
+    fn new(
+        device: &Device,
+        layout: &BindGroupLayout,
+        capacity: u32,
+        state: Option<&NeighbourListState>,
+    ) -> Self {
+        let mut neighbours = state.map_or(Vec::new(), |state| state.neighbours.clone());
+        neighbours.resize(capacity as usize * (MAX_NEIGHBOURS + 1), 0);
+        let mut reference_positions =
+            state.map_or(Vec::new(), |state| state.reference_positions.clone());
+        reference_positions.resize(capacity as usize, Vector2::zeros());
+        let flags = [state.is_none_or(|state| state.rebuild) as u32, 0];
+
+        let usage = BufferUsages::STORAGE | BufferUsages::COPY_SRC;
+        let neighbour_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Neighbour Buffer"),
+            contents: bytemuck::cast_slice(&neighbours),
+            usage,
+        });
+        let reference_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Neighbour Reference Buffer"),
+            contents: bytemuck::cast_slice(&reference_positions),
+            usage,
+        });
+        let flag_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Neighbour Flag Buffer"),
+            contents: bytemuck::cast_slice(&flags),
+            usage: usage | BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Neighbour Bind Group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 3,
+                    resource: neighbour_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: reference_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 5,
+                    resource: flag_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            neighbour_buffer,
+            reference_buffer,
+            flag_buffer,
+            bind_group,
+        }
+    }
+
+    /// reads back the lists of the first `atom_count` atom slots
+    async fn read(
+        &self,
+        device: &Arc<Device>,
+        queue: &Queue,
+        atom_count: u32,
+    ) -> Result<NeighbourListState> {
+        let count = atom_count as usize;
+        let neighbour_count = count * (MAX_NEIGHBOURS + 1);
+        let flags = read_values::<u32>(device, queue, &self.flag_buffer, 2).await?;
+        Ok(NeighbourListState {
+            neighbours: read_values(device, queue, &self.neighbour_buffer, neighbour_count).await?,
+            reference_positions: read_values(device, queue, &self.reference_buffer, count).await?,
+            rebuild: flags[0] != 0,
+        })
+    }
+
+    /// the amount of lists cut off at [`MAX_NEIGHBOURS`] since the lists were created
+    async fn overflows(&self, device: &Arc<Device>, queue: &Queue) -> Result<u32> {
+        let flags = read_values::<u32>(device, queue, &self.flag_buffer, 2).await?;
+        Ok(flags[1])
+    }
+
+    /// makes the next time step rebuild the lists, e.g. because atom indices changed
+    fn invalidate(&self, queue: &Queue) {
+        queue.write_buffer(&self.flag_buffer, 0, bytemuck::bytes_of(&1u32));
+    }
+}
+
+/// the atom buffer the renderer draws from and the amount of atoms in it. The buffer is
+/// replaced when the hash grid grows, so it is shared behind a mutex rather than cloned once.
+#[derive(Clone)]
+pub struct Instances {
+    pub buffer: Arc<Buffer>,
+    pub count: u32,
+}
+
 /// represents a hash grid on the gpu. Note that this does not even store
 pub struct HashGrid {
-    /// the side length of the actual grid
-    grid_side_length: f32,
-    /// the side length of each cell
-    cell_side_length: f32,
+    parameters: SimulationParameters,
+    /// the seed of the run, which also seeds the gpu rng
+    seed: u64,
+    /// whether the integrator performs energy minimization instead of dynamics
+    minimize: bool,
+    /// whether the thermostat of the parameters, if any, is applied
+    thermostat: bool,
     /// the amount of cells per side
     cells_per_side: i32,
-    /// the total amount of cells this hash grid has, i.e. `cells_per_side.powi(2)`
-    cell_count: u32,
+    /// the amount of atoms in the atom buffers
+    atom_count: u32,
+    /// the amount of atoms the atom buffers have room for
+    capacity: u32,
+    options: GpuOptions,
+    /// the id the next inserted atom gets
+    next_id: u32,
+    /// the ids of the atoms in each slot, the same as the id property buffer. They never change
+    /// on the gpu, so the slots of the bonded atoms can be found without reading them back.
+    ids: Vec<u32>,
+    topology: Topology,
+    /// the amount of time steps simulated so far
+    step: u64,
 
+    bin_pipeline: ComputePipeline,
+    sort_cells_pipeline: ComputePipeline,
     interact_pipeline: ComputePipeline,
+    bond_pipeline: ComputePipeline,
+    angle_pipeline: ComputePipeline,
     integrate_pipeline: ComputePipeline,
+    constraint_pipeline: ComputePipeline,
+    energy_pipeline: ComputePipeline,
+    structure_factor_pipeline: ComputePipeline,
+    reciprocal_pipeline: ComputePipeline,
+    coulomb_energy_pipeline: ComputePipeline,
+    spread_pipeline: ComputePipeline,
+    fft_rows_pipeline: ComputePipeline,
+    fft_columns_pipeline: ComputePipeline,
+    convolve_pipeline: ComputePipeline,
+    interpolate_pipeline: ComputePipeline,
+    mesh_coulomb_energy_pipeline: ComputePipeline,
+    monte_carlo_pipeline: ComputePipeline,
+    check_neighbours_pipeline: ComputePipeline,
+    build_neighbours_pipeline: ComputePipeline,
+    interact_neighbours_pipeline: ComputePipeline,
 
     atom_buffer_curr: Arc<Buffer>,
-    atom_buffer_last: Arc<Buffer>,
-    atom_buffer_size: BufferAddress,
+    atom_buffer_last: Buffer,
+    /// one buffer per array of [`Properties`]. They are indexed like the atom buffers and move
+    /// along with the atoms when atoms are removed or sorted, but are not ping-ponged as the
+    /// dynamics never change them.
+    property_buffers: [Buffer; Properties::COUNT],
+    /// the rounding error of the last position update of every atom, see
+    /// [`Precision::Compensated`]. Indexed like the atom buffers, moved along with the atoms
+    /// when atoms are removed or sorted, and saved in checkpoints.
+    compensation_buffer: Buffer,
+    /// the bonds of every atom slot, rewritten whenever atoms change their slots
+    bond_buffer: Buffer,
+    bond_type_buffer: Buffer,
+    /// the angles of every atom slot, rewritten along with the bond buffer
+    angle_buffer: Buffer,
+    angle_type_buffer: Buffer,
+    atom_bind_group_layout: BindGroupLayout,
     atom_bind_group_a: BindGroup,
     atom_bind_group_b: BindGroup,
+    probe_bind_group_layout: BindGroupLayout,
+    monte_carlo_buffer: Buffer,
+    monte_carlo_bind_group: BindGroup,
+    neighbour_bind_group_layout: BindGroupLayout,
+    /// only present if the parameters have a neighbour skin
+    neighbour_lists: Option<NeighbourLists>,
+    /// only present if there are constrained bonds
+    constraints: Option<Constraints>,
+    /// only present if the parameters have electrostatics
+    reciprocal_space: Option<ReciprocalSpace>,
+    external_forces: ExternalForceBuffers,
+    coulomb_energy_bind_group_layout: BindGroupLayout,
     cell_buffer: Buffer,
+    parameter_buffer: Buffer,
+    pair_potential_buffer: Buffer,
+    instances: Arc<Mutex<Instances>>,
 }
 
 impl HashGrid {
+    /// creates a hash grid containing the given atoms with the given properties and bonds.
+    /// `seed` is the seed of the run, it seeds the gpu rng that drives stochastic thermostats.
     pub fn from_slice(
         device: &Device,
         atoms: &[Atom],
-        grid_side_length: f32,
-        cell_side_length: f32,
+        properties: &Properties,
+        topology: &Topology,
+        parameters: &SimulationParameters,
+        seed: u64,
+        options: GpuOptions,
     ) -> Self {
-        let atom_buffer_content = bytemuck::cast_slice(atoms);
-        let atom_buffer_size = atom_buffer_content.len() as BufferAddress;
-
-        let atom_buffer_curr = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Atom Buffer Current"),
-            contents: atom_buffer_content,
-            usage: BufferUsages::STORAGE
-                | BufferUsages::COPY_DST
-                | BufferUsages::COPY_SRC
-                | BufferUsages::VERTEX,
-        });
-        let atom_buffer_last = device.create_buffer_init(&BufferInitDescriptor {
-            label: Some("Atom Buffer Last"),
-            contents: atom_buffer_content,
-            usage: BufferUsages::STORAGE
-                | BufferUsages::COPY_DST
-                | BufferUsages::COPY_SRC
-                | BufferUsages::VERTEX,
-        });
-
-        let cells_per_side = (grid_side_length / cell_side_length).ceil() as usize;
-        let mut cells = vec![HashGridCell::default(); cells_per_side * cells_per_side];
-
-        atoms.iter().enumerate().for_each(|(index, atom)| {
-            let cell_id_x = (atom.position.x / cell_side_length).floor() as usize;
-            let cell_id_y = (atom.position.y / cell_side_length).floor() as usize;
-
-            let cell_index = cell_id_y * cells_per_side + cell_id_x;
-            let cell = &mut cells[cell_index];
-            if cell.count < cell.indices.len() as i32 {
-                cell.indices[cell.count as usize] = index as i32;
-            }
-            cell.count += 1;
-        });
+        Self::new(
+            device,
+            &Checkpoint {
+                parameters: parameters.clone(),
+                step: 0,
+                seed,
+                minimize: false,
+                thermostat: false,
+                next_id: properties.ids.iter().max().map_or(0, |id| id + 1),
+                properties: properties.clone(),
+                topology: topology.clone(),
+                atoms_curr: atoms.to_vec(),
+                atoms_last: atoms.to_vec(),
+                position_compensation: vec![Vector2::zeros(); atoms.len()],
+                neighbour_lists: None,
+            },
+            options,
+        )
+    }
 
-        println!(
-            "max atoms per cell: {}",
-            cells
-                .iter()
-                .max_by_key(|cell| cell.count)
-                .map(|a| a.count)
-                .unwrap()
+    /// restores a hash grid saved with [`HashGrid::save`]
+    pub fn load(device: &Device, path: &Path, options: GpuOptions) -> Result<Self> {
+        let file = File::open(path)
+            .wrap_err_with(|| format!("failed to open checkpoint {}", path.display()))?;
+        let checkpoint = Checkpoint::read(&mut BufReader::new(file))
+            .wrap_err_with(|| format!("invalid checkpoint {}", path.display()))?;
+
+        Ok(Self::new(device, &checkpoint, options))
+    }
+
+    /// reads back the complete state of the hash grid and writes it to `path`. The file is
+    /// replaced atomically, so an interrupted save never destroys an earlier checkpoint.
+    pub async fn save(&self, device: &Arc<Device>, queue: &Queue, path: &Path) -> Result<()> {
+        let checkpoint = Checkpoint {
+            parameters: self.parameters.clone(),
+            step: self.step,
+            seed: self.seed,
+            minimize: self.minimize,
+            thermostat: self.thermostat,
+            next_id: self.next_id,
+            properties: self.read_properties(device, queue).await?,
+            topology: self.topology.clone(),
+            atoms_curr: self.read_atoms(device, queue).await?,
+            atoms_last: self
+                .read_atom_buffer(device, queue, &self.atom_buffer_last)
+                .await?,
+            position_compensation: self.read_compensation(device, queue).await?,
+            neighbour_lists: match &self.neighbour_lists {
+                Some(lists) => Some(lists.read(device, queue, self.atom_count).await?),
+                None => None,
+            },
+        };
+
+        let temporary = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&temporary).wrap_err_with(|| {
+                format!("failed to create checkpoint {}", temporary.display())
+            })?);
+            checkpoint.write(&mut writer)?;
+            writer.into_inner()?.sync_all()?;
+        }
+        std::fs::rename(&temporary, path)
+            .wrap_err_with(|| format!("failed to write checkpoint {}", path.display()))?;
+
+        Ok(())
+    }
+
+    /// reads back the current atoms and waits for them. Unlike
+    /// [`crate::simulation::readback::AtomReadback`] this stalls the simulation, it is meant
+    /// for occasional changes to the atoms.
+    pub async fn read_atoms(&self, device: &Arc<Device>, queue: &Queue) -> Result<Vec<Atom>> {
+        self.read_atom_buffer(device, queue, &self.atom_buffer_curr)
+            .await
+    }
+
+    /// reads back the properties of the current atoms, in the same order as
+    /// [`HashGrid::read_atoms`]
+    pub async fn read_properties(&self, device: &Arc<Device>, queue: &Queue) -> Result<Properties> {
+        let mut columns = Vec::with_capacity(Properties::COUNT);
+        for buffer in &self.property_buffers {
+            columns.push(read_buffer(device, queue, buffer).await?);
+        }
+        Ok(Properties::from_columns(
+            self.atom_count as usize,
+            std::array::from_fn(|index| &*columns[index]),
+        ))
+    }
+
+    async fn read_atom_buffer(
+        &self,
+        device: &Arc<Device>,
+        queue: &Queue,
+        buffer: &Buffer,
+    ) -> Result<Vec<Atom>> {
+        let bytes = read_buffer(device, queue, buffer).await?;
+        Ok(self
+            .options
+            .layout
+            .decode(&bytes, self.atom_count as usize, self.capacity))
+    }
+
+    /// reads back the rounding errors of the positions of the current atoms
+    async fn read_compensation(
+        &self,
+        device: &Arc<Device>,
+        queue: &Queue,
+    ) -> Result<Vec<Vector2<f32>>> {
+        let count = self.atom_count as usize;
+        read_values(device, queue, &self.compensation_buffer, count).await
+    }
+
+    /// the amount of cells along each axis. A partial last cell along open or reflective axes
+    /// reaches beyond the box, periodic axes consist of whole cells, see
+    /// [`whole_cells`].
+    fn cells_per_side(parameters: &SimulationParameters) -> usize {
+        let cells = parameters.grid_side_length / parameters.cell_side_length;
+        if whole_cells(cells) {
+            cells.round() as usize
+        } else {
+            cells.ceil() as usize
+        }
+    }
+
+    fn new(device: &Device, state: &Checkpoint, options: GpuOptions) -> Self {
+        let GpuOptions {
+            layout, precision, ..
+        } = options;
+        let parameters = &state.parameters;
+        let cells_per_side = Self::cells_per_side(parameters);
+
+        let atom_count = state.atoms_curr.len() as u32;
+        // buffers must not be empty, even if all atoms were removed
+        let capacity = atom_count.max(1);
+        let atom_buffer_curr = create_atom_buffer(
+            device,
+            "Atom Buffer Current",
+            layout,
+            capacity,
+            &state.atoms_curr,
+        );
+        let atom_buffer_last = create_atom_buffer(
+            device,
+            "Atom Buffer Last",
+            layout,
+            capacity,
+            &state.atoms_last,
         );
+        let property_buffers = create_property_buffers(device, capacity, &state.properties);
+        let compensation_buffer =
+            create_compensation_buffer(device, capacity, &state.position_compensation);
+        let ids = state.properties.ids.clone();
+        let bond_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Bond Buffer"),
+            contents: bytemuck::cast_slice(&bond_table(&ids, &state.topology, capacity)),
+            usage: TOPOLOGY_BUFFER_USAGES,
+        });
+        let angle_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Angle Buffer"),
+            contents: bytemuck::cast_slice(&angle_table(&ids, &state.topology, capacity)),
+            usage: TOPOLOGY_BUFFER_USAGES,
+        });
 
-        let cell_buffer = device.create_buffer_init(&BufferInitDescriptor {
+        // the cells are rebuilt from the atom positions before every time step
+        let cell_buffer = device.create_buffer(&BufferDescriptor {
             label: Some("Cell Buffer"),
-            contents: bytemuck::cast_slice(&cells),
+            size: (cells_per_side * cells_per_side * size_of::<HashGridCell>()) as BufferAddress,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let gpu_parameters = GpuParameters::new(
+            parameters,
+            state.seed,
+            precision,
+            state.minimize,
+            state.thermostat,
+        );
+        let parameter_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Parameter Buffer"),
+            contents: bytemuck::bytes_of(&gpu_parameters),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let pair_potentials = parameters
+            .pair_potentials
+            .iter()
+            .map(|lj| GpuPairPotential {
+                epsilon: lj.epsilon,
+                sigma: lj.sigma,
+                cutoff_sq: lj.cutoff * lj.cutoff,
+            })
+            .collect::<Vec<_>>();
+        let pair_potential_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Pair Potential Buffer"),
+            contents: bytemuck::cast_slice(&pair_potentials),
+            usage: BufferUsages::STORAGE,
+        });
+
+        // buffers must not be empty, so there are placeholders if there are no bond or angle types
+        let mut bond_types = parameters
+            .bond_types
+            .iter()
+            .map(|bond_type| GpuBondType {
+                kind: bond_type.gpu_id(),
+                k: bond_type.k(),
+                length: bond_type.length(),
+            })
+            .collect::<Vec<_>>();
+        if bond_types.is_empty() {
+            bond_types.push(GpuBondType::zeroed());
+        }
+        let bond_type_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Bond Type Buffer"),
+            contents: bytemuck::cast_slice(&bond_types),
+            usage: BufferUsages::STORAGE,
+        });
+        let mut angle_types = parameters
+            .angle_types
+            .iter()
+            .map(|angle_type| GpuAngleType {
+                kind: angle_type.gpu_id(),
+                k: angle_type.k(),
+                angle: angle_type.angle(),
+            })
+            .collect::<Vec<_>>();
+        if angle_types.is_empty() {
+            angle_types.push(GpuAngleType::zeroed());
+        }
+        let angle_type_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Angle Type Buffer"),
+            contents: bytemuck::cast_slice(&angle_types),
             usage: BufferUsages::STORAGE,
         });
 
@@ -121,12 +1036,12 @@ impl HashGrid {
                     },
                     count: None,
                 },
-                // last position buffer
+                // last position buffer, only its forces are written
                 BindGroupLayoutEntry {
                     binding: 1,
                     visibility: ShaderStages::COMPUTE,
                     ty: BindingType::Buffer {
-                        ty: BufferBindingType::Storage { read_only: true },
+                        ty: BufferBindingType::Storage { read_only: false },
                         has_dynamic_offset: false,
                         min_binding_size: None,
                     },
@@ -143,106 +1058,1027 @@ impl HashGrid {
                     },
                     count: None,
                 },
-            ],
-        });
-        let atom_bind_group_a = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Atom Bind Group A"),
-            layout: &atom_bind_group_layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: atom_buffer_curr.as_entire_binding(),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: atom_buffer_last.as_entire_binding(),
+                // simulation parameters
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
-                BindGroupEntry {
-                    binding: 2,
-                    resource: cell_buffer.as_entire_binding(),
+                // mass buffer
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // pair potential buffer
+                BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // compensation buffer
+                BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // bond buffer
+                BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // bond type buffer
+                BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // angle buffer
+                BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // angle type buffer
+                BindGroupLayoutEntry {
+                    binding: 10,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // charge buffer
+                BindGroupLayoutEntry {
+                    binding: 11,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
             ],
         });
-        let atom_bind_group_b = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("Atom Bind Group B"),
-            layout: &atom_bind_group_layout,
+        let shared_buffers = [
+            &cell_buffer,
+            &parameter_buffer,
+            &property_buffers[Properties::MASS_COLUMN],
+            &pair_potential_buffer,
+            &compensation_buffer,
+            &bond_buffer,
+            &bond_type_buffer,
+            &angle_buffer,
+            &angle_type_buffer,
+            &property_buffers[Properties::CHARGE_COLUMN],
+        ];
+        let atom_bind_group_a = create_atom_bind_group(
+            device,
+            &atom_bind_group_layout,
+            "Atom Bind Group A",
+            [&atom_buffer_curr, &atom_buffer_last],
+            shared_buffers,
+        );
+        let atom_bind_group_b = create_atom_bind_group(
+            device,
+            &atom_bind_group_layout,
+            "Atom Bind Group B",
+            [&atom_buffer_last, &atom_buffer_curr],
+            shared_buffers,
+        );
+
+        let probe_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Probe Bind Group Layout"),
             entries: &[
-                BindGroupEntry {
+                // probes
+                BindGroupLayoutEntry {
                     binding: 0,
-                    resource: atom_buffer_last.as_entire_binding(),
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
-                BindGroupEntry {
+                // probe energies
+                BindGroupLayoutEntry {
                     binding: 1,
-                    resource: atom_buffer_curr.as_entire_binding(),
-                },
-                BindGroupEntry {
-                    binding: 2,
-                    resource: cell_buffer.as_entire_binding(),
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
             ],
         });
 
-        let interact_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-            label: Some("Interact Pipeline Layout"),
-            bind_group_layouts: &[&atom_bind_group_layout],
-            push_constant_ranges: &[PushConstantRange {
-                stages: ShaderStages::COMPUTE,
-                range: 0..size_of::<PushConstants>() as u32,
+        let monte_carlo_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Monte Carlo Buffer"),
+            size: size_of::<GpuMonteCarlo>() as BufferAddress,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let monte_carlo_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Monte Carlo Bind Group Layout"),
+                entries: &[
+                    // monte carlo settings and statistics
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let monte_carlo_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Monte Carlo Bind Group"),
+            layout: &monte_carlo_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 2,
+                resource: monte_carlo_buffer.as_entire_binding(),
             }],
         });
-        let interact_shader = device.create_shader_module(include_wgsl!("shaders/interact.wgsl"));
-        let interact_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
-            label: Some("Interaction Compute Pipeline"),
-            layout: Some(&interact_layout),
-            module: &interact_shader,
-            entry_point: "main_interact",
+
+        let neighbour_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Neighbour Bind Group Layout"),
+                entries: &[3, 4, 5].map(|binding| BindGroupLayoutEntry {
+                    binding,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }),
+            });
+        let neighbour_lists = parameters.neighbour_skin.map(|_| {
+            NeighbourLists::new(
+                device,
+                &neighbour_bind_group_layout,
+                capacity,
+                state.neighbour_lists.as_ref(),
+            )
         });
 
-        let integrate_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
-            label: Some("Integrate Pipeline Layout"),
-            bind_group_layouts: &[&atom_bind_group_layout],
-            push_constant_ranges: &[PushConstantRange {
-                stages: ShaderStages::COMPUTE,
-                range: 0..size_of::<PushConstants>() as u32,
-            }],
+        let constraint_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Constraint Bind Group Layout"),
+                entries: &[6, 7].map(|binding| BindGroupLayoutEntry {
+                    binding,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }),
+            });
+        let constraints = Constraints::new(
+            device,
+            &constraint_bind_group_layout,
+            &ids,
+            &state.topology,
+            &parameters.bond_types,
+        );
+
+        let ewald_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Ewald Bind Group Layout"),
+            entries: &[
+                // wave vectors
+                BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // structure factors
+                BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
         });
-        let integrate_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
-            label: Some("Integrate Compute Pipeline"),
-            layout: Some(&integrate_layout),
-            module: &interact_shader,
-            entry_point: "main_integrate",
+        let mesh_bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("Particle Mesh Bind Group Layout"),
+            entries: &[
+                // mesh
+                BindGroupLayoutEntry {
+                    binding: 10,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // influence function
+                BindGroupLayoutEntry {
+                    binding: 11,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
         });
+        let reciprocal_space = parameters
+            .electrostatics
+            .map(|ewald| match ewald.reciprocal {
+                Reciprocal::Direct { k_max } => ReciprocalSpace::Direct(EwaldSum::new(
+                    device,
+                    &ewald_bind_group_layout,
+                    &ewald,
+                    parameters.grid_side_length,
+                    k_max,
+                )),
+                Reciprocal::Mesh { size, order } => ReciprocalSpace::Mesh(ParticleMesh::new(
+                    device,
+                    &mesh_bind_group_layout,
+                    &ewald,
+                    parameters.grid_side_length,
+                    size,
+                    order,
+                )),
+            });
+        let external_force_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("External Force Bind Group Layout"),
+                entries: &[
+                    // traps
+                    BindGroupLayoutEntry {
+                        binding: 12,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                    // force field
+                    BindGroupLayoutEntry {
+                        binding: 13,
+                        visibility: ShaderStages::COMPUTE,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+        let external_forces = ExternalForceBuffers::new(
+            device,
+            &external_force_bind_group_layout,
+            &parameters.external_forces,
+        );
+        let coulomb_energy_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Coulomb Energy Bind Group Layout"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            });
+
+        let interact_shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Interaction Shader"),
+            source: ShaderSource::Wgsl(layout.shader_source().into()),
+        });
+        let pipeline = |label: &str, entry_point: &str, bind_group_layouts: &[&BindGroupLayout]| {
+            let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: Some(&format!("{label} Pipeline Layout")),
+                bind_group_layouts,
+                push_constant_ranges: &[PushConstantRange {
+                    stages: ShaderStages::COMPUTE,
+                    range: 0..size_of::<PushConstants>() as u32,
+                }],
+            });
+            device.create_compute_pipeline(&ComputePipelineDescriptor {
+                label: Some(&format!("{label} Compute Pipeline")),
+                layout: Some(&layout),
+                module: &interact_shader,
+                entry_point,
+            })
+        };
+        let bin_pipeline = pipeline("Bin", "main_bin", &[&atom_bind_group_layout]);
+        let sort_cells_pipeline =
+            pipeline("Sort Cells", "main_sort_cells", &[&atom_bind_group_layout]);
+        let (interact_entry_point, integrate_entry_point) = match options.dispatch {
+            Dispatch::Atoms => ("main_interact", "main_integrate"),
+            Dispatch::Cells => ("main_interact_cells", "main_integrate_cells"),
+        };
+        let interact_pipeline = pipeline(
+            "Interaction",
+            interact_entry_point,
+            &[&atom_bind_group_layout],
+        );
+        let bond_pipeline = pipeline("Bond", "main_bonds", &[&atom_bind_group_layout]);
+        let angle_pipeline = pipeline("Angle", "main_angles", &[&atom_bind_group_layout]);
+        let integrate_pipeline = pipeline(
+            "Integrate",
+            integrate_entry_point,
+            &[&atom_bind_group_layout, &external_force_bind_group_layout],
+        );
+        let constraint_pipeline = pipeline(
+            "Constraint",
+            "main_constraints",
+            &[&atom_bind_group_layout, &constraint_bind_group_layout],
+        );
+        let energy_pipeline = pipeline(
+            "Energy",
+            "main_energy",
+            &[&atom_bind_group_layout, &probe_bind_group_layout],
+        );
+        let structure_factor_pipeline = pipeline(
+            "Structure Factor",
+            "main_structure_factors",
+            &[&atom_bind_group_layout, &ewald_bind_group_layout],
+        );
+        let reciprocal_pipeline = pipeline(
+            "Reciprocal",
+            "main_reciprocal",
+            &[&atom_bind_group_layout, &ewald_bind_group_layout],
+        );
+        let coulomb_energy_pipeline = pipeline(
+            "Coulomb Energy",
+            "main_coulomb_energy",
+            &[
+                &atom_bind_group_layout,
+                &ewald_bind_group_layout,
+                &coulomb_energy_bind_group_layout,
+            ],
+        );
+        let mesh_pipeline = |label: &str, entry_point: &str| {
+            pipeline(
+                label,
+                entry_point,
+                &[&atom_bind_group_layout, &mesh_bind_group_layout],
+            )
+        };
+        let spread_pipeline = mesh_pipeline("Spread", "main_spread");
+        let fft_rows_pipeline = mesh_pipeline("FFT Rows", "main_fft_rows");
+        let fft_columns_pipeline = mesh_pipeline("FFT Columns", "main_fft_columns");
+        let convolve_pipeline = mesh_pipeline("Convolve", "main_convolve");
+        let interpolate_pipeline = mesh_pipeline("Interpolate", "main_interpolate");
+        let mesh_coulomb_energy_pipeline = pipeline(
+            "Mesh Coulomb Energy",
+            "main_mesh_coulomb_energy",
+            &[
+                &atom_bind_group_layout,
+                &mesh_bind_group_layout,
+                &coulomb_energy_bind_group_layout,
+            ],
+        );
+        let monte_carlo_pipeline = pipeline(
+            "Monte Carlo",
+            "main_monte_carlo",
+            &[&atom_bind_group_layout, &monte_carlo_bind_group_layout],
+        );
+        let neighbour_pipeline = |label: &str, entry_point: &str| {
+            pipeline(
+                label,
+                entry_point,
+                &[&atom_bind_group_layout, &neighbour_bind_group_layout],
+            )
+        };
+        let check_neighbours_pipeline =
+            neighbour_pipeline("Check Neighbours", "main_check_neighbours");
+        let build_neighbours_pipeline =
+            neighbour_pipeline("Build Neighbours", "main_build_neighbours");
+        let interact_neighbours_pipeline =
+            neighbour_pipeline("Neighbour Interaction", "main_interact_neighbours");
+
+        let atom_buffer_curr = Arc::new(atom_buffer_curr);
+        let instances = Arc::new(Mutex::new(Instances {
+            buffer: atom_buffer_curr.clone(),
+            count: atom_count,
+        }));
 
         Self {
-            grid_side_length,
-            cell_side_length,
+            parameters: parameters.clone(),
+            seed: state.seed,
+            minimize: state.minimize,
+            thermostat: state.thermostat,
             cells_per_side: cells_per_side as i32,
-            cell_count: (cells_per_side * cells_per_side) as u32,
+            atom_count,
+            capacity,
+            options,
+            next_id: state.next_id,
+            ids,
+            topology: state.topology.clone(),
+            step: state.step,
 
+            bin_pipeline,
+            sort_cells_pipeline,
             interact_pipeline,
+            bond_pipeline,
+            angle_pipeline,
             integrate_pipeline,
+            constraint_pipeline,
+            energy_pipeline,
+            structure_factor_pipeline,
+            reciprocal_pipeline,
+            coulomb_energy_pipeline,
+            spread_pipeline,
+            fft_rows_pipeline,
+            fft_columns_pipeline,
+            convolve_pipeline,
+            interpolate_pipeline,
+            mesh_coulomb_energy_pipeline,
+            monte_carlo_pipeline,
+            check_neighbours_pipeline,
+            build_neighbours_pipeline,
+            interact_neighbours_pipeline,
 
-            atom_buffer_curr: Arc::new(atom_buffer_curr),
-            atom_buffer_last: Arc::new(atom_buffer_last),
-            atom_buffer_size,
+            atom_buffer_curr,
+            atom_buffer_last,
+            property_buffers,
+            compensation_buffer,
+            bond_buffer,
+            bond_type_buffer,
+            angle_buffer,
+            angle_type_buffer,
+            atom_bind_group_layout,
             atom_bind_group_a,
             atom_bind_group_b,
+            probe_bind_group_layout,
+            monte_carlo_buffer,
+            monte_carlo_bind_group,
+            neighbour_bind_group_layout,
+            neighbour_lists,
+            constraints,
+            reciprocal_space,
+            external_forces,
+            coulomb_energy_bind_group_layout,
             cell_buffer,
+            parameter_buffer,
+            pair_potential_buffer,
+            instances,
         }
     }
 
-    pub fn update(&self, command_encoder: &mut CommandEncoder) {
-        for (bg, (ba, bb)) in [
-            (
-                &self.atom_bind_group_a,
-                (&self.atom_buffer_curr, &self.atom_buffer_last),
-            ),
-            (
-                &self.atom_bind_group_b,
-                (&self.atom_buffer_last, &self.atom_buffer_curr),
-            ),
-        ] {
-            {
+    /// switches between plain dynamics and energy minimization and enables or disables the
+    /// thermostat of the simulation parameters, if there is one.
+    pub fn set_mode(&mut self, queue: &Queue, minimize: bool, thermostat: bool) {
+        self.minimize = minimize;
+        self.thermostat = thermostat;
+
+        let gpu_parameters = GpuParameters::new(
+            &self.parameters,
+            self.seed,
+            self.options.precision,
+            minimize,
+            thermostat,
+        );
+        queue.write_buffer(
+            &self.parameter_buffer,
+            0,
+            bytemuck::bytes_of(&gpu_parameters),
+        );
+    }
+
+    /// the potential energy of each probe with the current atoms, evaluated on the gpu using
+    /// the cells
+    pub async fn probe_energies(
+        &self,
+        device: &Arc<Device>,
+        queue: &Queue,
+        probes: &[Probe],
+    ) -> Result<Vec<f32>> {
+        if probes.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let probe_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("Probe Buffer"),
+            contents: bytemuck::cast_slice(probes),
+            usage: BufferUsages::STORAGE,
+        });
+        let energy_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Probe Energy Buffer"),
+            size: (probes.len() * size_of::<f32>()) as BufferAddress,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let probe_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Probe Bind Group"),
+            layout: &self.probe_bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: probe_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: energy_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let push_constants = self.push_constants();
+        let mut command_encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Probe Energies"),
+        });
+        // bind group b reads the current atom buffer as its last one, which is what binning
+        // and the energy pass look at
+        self.bin(
+            &mut command_encoder,
+            &self.atom_bind_group_b,
+            &push_constants,
+        );
+        {
+            let mut compute_pass = command_encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("Probe Energy Pass"),
+            });
+            compute_pass.set_pipeline(&self.energy_pipeline);
+            compute_pass.set_bind_group(0, &self.atom_bind_group_b, &[]);
+            compute_pass.set_bind_group(1, &probe_bind_group, &[]);
+            compute_pass.set_push_constants(0, bytemuck::bytes_of(&push_constants));
+            compute_pass.dispatch_workgroups(
+                (probes.len() as u32).div_ceil(ATOM_WORKGROUP_SIZE),
+                1,
+                1,
+            );
+        }
+        queue.submit(Some(command_encoder.finish()));
+
+        let energies = read_buffer(device, queue, &energy_buffer).await?;
+        Ok(bytemuck::cast_slice(&energies).to_vec())
+    }
+
+    /// the coulomb energy of the current atoms, evaluated on the gpu by the same sums as the
+    /// forces. The energies of the atoms are added up in double precision on the cpu.
+    pub async fn coulomb_energy(&self, device: &Arc<Device>, queue: &Queue) -> Result<f64> {
+        let reciprocal_space = self
+            .reciprocal_space
+            .as_ref()
+            .ok_or_else(|| eyre!("the simulation has no electrostatics"))?;
+
+        let energy_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Coulomb Energy Buffer"),
+            size: self.capacity as BufferAddress * size_of::<f32>() as BufferAddress,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let energy_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("Coulomb Energy Bind Group"),
+            layout: &self.coulomb_energy_bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: energy_buffer.as_entire_binding(),
+            }],
+        });
+
+        let push_constants = self.push_constants();
+        let mut command_encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Coulomb Energy"),
+        });
+        // bind group b reads the current atom buffer as its last one, like for the probes
+        self.bin(
+            &mut command_encoder,
+            &self.atom_bind_group_b,
+            &push_constants,
+        );
+        self.record_passes(
+            &mut command_encoder,
+            &self.atom_bind_group_b,
+            reciprocal_space,
+            &self.reciprocal_potential_passes(reciprocal_space),
+            &push_constants,
+        );
+        {
+            let mut energy_pass = command_encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("Coulomb Energy Pass"),
+            });
+            energy_pass.set_pipeline(match reciprocal_space {
+                ReciprocalSpace::Direct(_) => &self.coulomb_energy_pipeline,
+                ReciprocalSpace::Mesh(_) => &self.mesh_coulomb_energy_pipeline,
+            });
+            energy_pass.set_bind_group(0, &self.atom_bind_group_b, &[]);
+            energy_pass.set_bind_group(1, reciprocal_space.bind_group(), &[]);
+            energy_pass.set_bind_group(2, &energy_bind_group, &[]);
+            energy_pass.set_push_constants(0, bytemuck::bytes_of(&push_constants));
+            energy_pass.dispatch_workgroups(self.atom_count.div_ceil(ATOM_WORKGROUP_SIZE), 1, 1);
+        }
+        queue.submit(Some(command_encoder.finish()));
+
+        let energies = read_buffer(device, queue, &energy_buffer).await?;
+        Ok(
+            bytemuck::cast_slice::<u8, f32>(&energies)[..self.atom_count as usize]
+                .iter()
+                .map(|&energy| energy as f64)
+                .sum(),
+        )
+    }
+
+    /// the amount of neighbour lists that were cut off at [`MAX_NEIGHBOURS`] atoms since the
+    /// hash grid was created, which leaves out interactions. Zero without neighbour lists.
+    pub async fn neighbour_overflows(&self, device: &Arc<Device>, queue: &Queue) -> Result<u32> {
+        match &self.neighbour_lists {
+            Some(lists) => lists.overflows(device, queue).await,
+            None => Ok(0),
+        }
+    }
+
+    /// the amount of current atoms in the fullest cell. Cells hold at most [`MAX_INDICES`]
+    /// atoms, the passes that go through the cells miss the others.
+    pub async fn fullest_cell(&self, device: &Arc<Device>, queue: &Queue) -> Result<u32> {
+        let mut command_encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Fullest Cell"),
+        });
+        // bind group b reads the current atom buffer as its last one, like for the probes
+        self.bin(
+            &mut command_encoder,
+            &self.atom_bind_group_b,
+            &self.push_constants(),
+        );
+        queue.submit(Some(command_encoder.finish()));
+
+        let cell_count = (self.cells_per_side * self.cells_per_side) as usize;
+        let cells =
+            read_values::<HashGridCell>(device, queue, &self.cell_buffer, cell_count).await?;
+        Ok(cells
+            .iter()
+            .map(|cell| cell.count.max(0) as u32)
+            .max()
+            .unwrap_or(0))
+    }
+
+    /// appends the given atoms, growing the atom buffers if they are full. Their forces are
+    /// reset, and they take part in the next time step. They get new ids and the default
+    /// properties of their species.
+    pub fn insert(&mut self, device: &Device, queue: &Queue, atoms: &[Atom]) {
+        let atom_count = self.atom_count + atoms.len() as u32;
+        if atom_count > self.capacity {
+            self.grow(device, queue, atom_count.max(self.capacity * 2));
+        }
+
+        let atoms = atoms
+            .iter()
+            .map(|atom| Atom {
+                force: Vector2::zeros(),
+                ..*atom
+            })
+            .collect::<Vec<_>>();
+        let count = atoms.len() as u32;
+        let encoded = self.options.layout.encode(&atoms, count);
+        for buffer in [&*self.atom_buffer_curr, &self.atom_buffer_last] {
+            for field in 0..self.options.layout.field_count() {
+                let source = self.options.layout.field_range(field, 0..count, count);
+                let destination = self.options.layout.field_range(
+                    field,
+                    self.atom_count..self.atom_count + count,
+                    self.capacity,
+                );
+                queue.write_buffer(
+                    buffer,
+                    destination.start,
+                    &encoded[source.start as usize..source.end as usize],
+                );
+            }
+        }
+        let properties = atoms
+            .iter()
+            .zip(self.next_id..)
+            .map(|(atom, id)| {
+                AtomProperties::of_species(id, &self.parameters.species[atom.species() as usize])
+            })
+            .collect::<Properties>();
+        let offset = self.atom_count as BufferAddress * Properties::SIZE as BufferAddress;
+        for (buffer, column) in self.property_buffers.iter().zip(properties.columns()) {
+            queue.write_buffer(buffer, offset, column);
+        }
+        queue.write_buffer(
+            &self.compensation_buffer,
+            self.atom_count as BufferAddress * COMPENSATION_SIZE,
+            &vec![0; atoms.len() * COMPENSATION_SIZE as usize],
+        );
+        self.ids
+            .extend(self.next_id..self.next_id + atoms.len() as u32);
+        self.next_id += atoms.len() as u32;
+
+        self.atom_count = atom_count;
+        self.write_topology(queue);
+        self.instances.lock().unwrap().count = atom_count;
+        if let Some(neighbour_lists) = &self.neighbour_lists {
+            neighbour_lists.invalidate(queue);
+        }
+    }
+
+    /// removes the atoms at the given indices along with their bonds. The gaps are filled with
+    /// the last atoms, so the indices of other atoms change as well.
+    pub fn remove(&mut self, device: &Device, queue: &Queue, indices: &[u32]) {
+        let mut indices = indices.to_vec();
+        indices.retain(|&index| index < self.atom_count);
+        indices.sort_unstable_by(|a, b| b.cmp(a));
+        indices.dedup();
+        let removed = indices
+            .iter()
+            .map(|&index| self.ids[index as usize])
+            .collect::<HashSet<_>>();
+        self.topology.remove_atoms(&removed);
+
+        // a buffer can not be copied into itself, so atoms are moved through a scratch buffer
+        let scratch_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Atom Scratch Buffer"),
+            size: size_of::<Atom>() as BufferAddress,
+            usage: BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut command_encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Atom Removal"),
+        });
+
+        // in descending order, the last atom is never one that is removed later on
+        for index in indices {
+            let last = self.atom_count - 1;
+            if index != last {
+                // every field of the atoms and every property is moved on its own
+                let mut moves = Vec::new();
+                for buffer in [&*self.atom_buffer_curr, &self.atom_buffer_last] {
+                    for field in 0..self.options.layout.field_count() {
+                        let field_range = |slot| {
+                            self.options
+                                .layout
+                                .field_range(field, slot..slot + 1, self.capacity)
+                        };
+                        moves.push((buffer, field_range(last), field_range(index)));
+                    }
+                }
+                let slot_range =
+                    |slot, size| slot as BufferAddress * size..(slot + 1) as BufferAddress * size;
+                let size = Properties::SIZE as BufferAddress;
+                for buffer in &self.property_buffers {
+                    moves.push((buffer, slot_range(last, size), slot_range(index, size)));
+                }
+                moves.push((
+                    &self.compensation_buffer,
+                    slot_range(last, COMPENSATION_SIZE),
+                    slot_range(index, COMPENSATION_SIZE),
+                ));
+
+                for (buffer, source, destination) in moves {
+                    let size = source.end - source.start;
+                    command_encoder.copy_buffer_to_buffer(
+                        buffer,
+                        source.start,
+                        &scratch_buffer,
+                        0,
+                        size,
+                    );
+                    command_encoder.copy_buffer_to_buffer(
+                        &scratch_buffer,
+                        0,
+                        buffer,
+                        destination.start,
+                        size,
+                    );
+                }
+            }
+            self.ids.swap_remove(index as usize);
+            self.atom_count = last;
+        }
+        queue.submit(Some(command_encoder.finish()));
+        self.write_topology(queue);
+
+        self.instances.lock().unwrap().count = self.atom_count;
+        if let Some(neighbour_lists) = &self.neighbour_lists {
+            neighbour_lists.invalidate(queue);
+        }
+    }
+
+    /// moves the atoms into new buffers with room for `capacity` atoms
+    fn grow(&mut self, device: &Device, queue: &Queue, capacity: u32) {
+        let atom_buffer_curr = create_atom_buffer(
+            device,
+            "Atom Buffer Current",
+            self.options.layout,
+            capacity,
+            &[],
+        );
+        let atom_buffer_last = create_atom_buffer(
+            device,
+            "Atom Buffer Last",
+            self.options.layout,
+            capacity,
+            &[],
+        );
+        let property_buffers = create_property_buffers(device, capacity, &Properties::default());
+        let compensation_buffer = create_compensation_buffer(device, capacity, &[]);
+        // written by the caller, once the new atoms are in place
+        let bond_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Bond Buffer"),
+            size: capacity as BufferAddress * size_of::<GpuBonds>() as BufferAddress,
+            usage: TOPOLOGY_BUFFER_USAGES,
+            mapped_at_creation: false,
+        });
+        let angle_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Angle Buffer"),
+            size: capacity as BufferAddress * size_of::<GpuAngles>() as BufferAddress,
+            usage: TOPOLOGY_BUFFER_USAGES,
+            mapped_at_creation: false,
+        });
+
+        let mut command_encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("Atom Buffer Growth"),
+        });
+        self.copy_fields(
+            &mut command_encoder,
+            &self.atom_buffer_curr,
+            &atom_buffer_curr,
+            capacity,
+        );
+        self.copy_fields(
+            &mut command_encoder,
+            &self.atom_buffer_last,
+            &atom_buffer_last,
+            capacity,
+        );
+        for (source, destination) in self.property_buffers.iter().zip(&property_buffers) {
+            command_encoder.copy_buffer_to_buffer(
+                source,
+                0,
+                destination,
+                0,
+                self.property_buffer_size(),
+            );
+        }
+        command_encoder.copy_buffer_to_buffer(
+            &self.compensation_buffer,
+            0,
+            &compensation_buffer,
+            0,
+            self.atom_count as BufferAddress * COMPENSATION_SIZE,
+        );
+        // the new lists are built in the next time step, but keep the overflows of the old ones
+        let neighbour_lists = self.neighbour_lists.as_ref().map(|old| {
+            let new =
+                NeighbourLists::new(device, &self.neighbour_bind_group_layout, capacity, None);
+            command_encoder.copy_buffer_to_buffer(
+                &old.flag_buffer,
+                NeighbourLists::OVERFLOW_OFFSET,
+                &new.flag_buffer,
+                NeighbourLists::OVERFLOW_OFFSET,
+                size_of::<u32>() as BufferAddress,
+            );
+            new
+        });
+        queue.submit(Some(command_encoder.finish()));
+
+        let shared_buffers = [
+            &self.cell_buffer,
+            &self.parameter_buffer,
+            &property_buffers[Properties::MASS_COLUMN],
+            &self.pair_potential_buffer,
+            &compensation_buffer,
+            &bond_buffer,
+            &self.bond_type_buffer,
+            &angle_buffer,
+            &self.angle_type_buffer,
+            &property_buffers[Properties::CHARGE_COLUMN],
+        ];
+        self.atom_bind_group_a = create_atom_bind_group(
+            device,
+            &self.atom_bind_group_layout,
+            "Atom Bind Group A",
+            [&atom_buffer_curr, &atom_buffer_last],
+            shared_buffers,
+        );
+        self.atom_bind_group_b = create_atom_bind_group(
+            device,
+            &self.atom_bind_group_layout,
+            "Atom Bind Group B",
+            [&atom_buffer_last, &atom_buffer_curr],
+            shared_buffers,
+        );
+
+        self.atom_buffer_curr = Arc::new(atom_buffer_curr);
+        self.atom_buffer_last = atom_buffer_last;
+        self.property_buffers = property_buffers;
+        self.compensation_buffer = compensation_buffer;
+        self.bond_buffer = bond_buffer;
+        self.angle_buffer = angle_buffer;
+        self.capacity = capacity;
+        self.instances.lock().unwrap().buffer = self.atom_buffer_curr.clone();
+        self.neighbour_lists = neighbour_lists;
+    }
+
+    /// records two time steps, one for each direction of the ping-pong buffers. Bind group b
+    /// writes into the last atom buffer, so it goes first and the current atom buffer holds the
+    /// newest state, including the forces of that step, afterwards.
+    ///
+    /// Every time step first rebuilds the cells from the atom positions it starts from. With
+    /// neighbour lists, it then checks whether the lists are still valid, rebuilds them from
+    /// the cells if not, and evaluates the forces from the lists instead of the cells. The
+    /// forces of the reciprocal space part of the ewald sum, of the bonds and of the angles are
+    /// added in passes of their own before the integration. All passes run one invocation per
+    /// atom, except for the structure factors, which run one workgroup per wave vector, the
+    /// passes of the mesh, which run one invocation per mesh point or one workgroup per row or
+    /// column of it, and the constraint pass after the integration, which runs one invocation
+    /// per molecule. [`Dispatch::Cells`] runs the interaction without neighbour lists and the
+    /// integration with one invocation per cell instead.
+    pub fn update(&mut self, command_encoder: &mut CommandEncoder) {
+        let atom_workgroups = self.atom_count.div_ceil(ATOM_WORKGROUP_SIZE);
+        for bg in [&self.atom_bind_group_b, &self.atom_bind_group_a] {
+            let push_constants = self.push_constants();
+            self.step += 1;
+
+            self.bin(command_encoder, bg, &push_constants);
+
+            if let Some(neighbour_lists) = &self.neighbour_lists {
+                // separate passes, so the build sees the flag of the whole check and the
+                // interaction sees all rebuilt lists
+                for (label, pipeline) in [
+                    ("Check Neighbours Pass", &self.check_neighbours_pipeline),
+                    ("Build Neighbours Pass", &self.build_neighbours_pipeline),
+                    ("Interact Pass", &self.interact_neighbours_pipeline),
+                ] {
+                    let mut pass = command_encoder
+                        .begin_compute_pass(&ComputePassDescriptor { label: Some(label) });
+                    pass.set_pipeline(pipeline);
+                    pass.set_bind_group(0, bg, &[]);
+                    pass.set_bind_group(1, &neighbour_lists.bind_group, &[]);
+                    pass.set_push_constants(0, bytemuck::bytes_of(&push_constants));
+                    pass.dispatch_workgroups(atom_workgroups, 1, 1);
+                }
+                command_encoder.clear_buffer(
+                    &neighbour_lists.flag_buffer,
+                    0,
+                    BufferSize::new(NeighbourLists::OVERFLOW_OFFSET),
+                );
+            } else {
                 let mut interact_pass =
                     command_encoder.begin_compute_pass(&ComputePassDescriptor {
                         label: Some("Interact Pass"),
@@ -250,12 +2086,34 @@ impl HashGrid {
 
                 interact_pass.set_pipeline(&self.interact_pipeline);
                 interact_pass.set_bind_group(0, bg, &[]);
-                interact_pass.set_push_constants(0, bytemuck::bytes_of(&self.cells_per_side));
-                interact_pass.dispatch_workgroups(
-                    self.cells_per_side as u32,
-                    self.cells_per_side as u32,
-                    1,
-                );
+                interact_pass.set_push_constants(0, bytemuck::bytes_of(&push_constants));
+                self.dispatch_atoms(&mut interact_pass, atom_workgroups);
+            }
+
+            if let Some(reciprocal_space) = &self.reciprocal_space {
+                self.reciprocal(command_encoder, bg, reciprocal_space, &push_constants);
+            }
+
+            if !self.topology.bonds.is_empty() {
+                let mut bond_pass = command_encoder.begin_compute_pass(&ComputePassDescriptor {
+                    label: Some("Bond Pass"),
+                });
+
+                bond_pass.set_pipeline(&self.bond_pipeline);
+                bond_pass.set_bind_group(0, bg, &[]);
+                bond_pass.set_push_constants(0, bytemuck::bytes_of(&push_constants));
+                bond_pass.dispatch_workgroups(atom_workgroups, 1, 1);
+            }
+
+            if !self.topology.angles.is_empty() {
+                let mut angle_pass = command_encoder.begin_compute_pass(&ComputePassDescriptor {
+                    label: Some("Angle Pass"),
+                });
+
+                angle_pass.set_pipeline(&self.angle_pipeline);
+                angle_pass.set_bind_group(0, bg, &[]);
+                angle_pass.set_push_constants(0, bytemuck::bytes_of(&push_constants));
+                angle_pass.dispatch_workgroups(atom_workgroups, 1, 1);
             }
 
             {
@@ -266,19 +2124,746 @@ impl HashGrid {
 
                 integrate_pass.set_pipeline(&self.integrate_pipeline);
                 integrate_pass.set_bind_group(0, bg, &[]);
-                integrate_pass.set_push_constants(0, bytemuck::bytes_of(&self.cells_per_side));
-                integrate_pass.dispatch_workgroups(
-                    self.cells_per_side as u32,
-                    self.cells_per_side as u32,
+                integrate_pass.set_bind_group(1, &self.external_forces.bind_group, &[]);
+                integrate_pass.set_push_constants(0, bytemuck::bytes_of(&push_constants));
+                self.dispatch_atoms(&mut integrate_pass, atom_workgroups);
+            }
+
+            if let Some(constraints) = &self.constraints {
+                let mut constraint_pass =
+                    command_encoder.begin_compute_pass(&ComputePassDescriptor {
+                        label: Some("Constraint Pass"),
+                    });
+
+                constraint_pass.set_pipeline(&self.constraint_pipeline);
+                constraint_pass.set_bind_group(0, bg, &[]);
+                constraint_pass.set_bind_group(1, &constraints.bind_group, &[]);
+                constraint_pass.set_push_constants(0, bytemuck::bytes_of(&push_constants));
+                constraint_pass.dispatch_workgroups(
+                    constraints.capacity.div_ceil(ATOM_WORKGROUP_SIZE),
+                    1,
                     1,
                 );
             }
+        }
+    }
+
+    /// sets the temperature, the maximum displacement and the trial moves per atom of the
+    /// following monte carlo sweeps, and resets their acceptance statistics
+    pub fn set_monte_carlo(
+        &self,
+        queue: &Queue,
+        temperature: f32,
+        max_displacement: f32,
+        moves: u32,
+    ) {
+        let monte_carlo = GpuMonteCarlo {
+            temperature,
+            max_displacement,
+            moves,
+            attempted: 0,
+            accepted: 0,
+        };
+        queue.write_buffer(
+            &self.monte_carlo_buffer,
+            0,
+            bytemuck::bytes_of(&monte_carlo),
+        );
+    }
+
+    /// records a monte carlo sweep over the current atoms, which counts as one time step.
+    ///
+    /// The atoms are binned into cells shifted by `offset`, which should be drawn uniformly
+    /// from a cell for every sweep so that atoms can cross every cell boundary eventually. The
+    /// cells are then updated in four passes, one per color of a two by two checkerboard, so
+    /// concurrently updated cells never interact. This requires an even amount of cells along
+    /// periodic axes.
+    pub fn monte_carlo_sweep(
+        &mut self,
+        command_encoder: &mut CommandEncoder,
+        offset: Vector2<f32>,
+    ) {
+        let push_constants = PushConstants {
+            offset,
+            ..self.push_constants()
+        };
+        self.step += 1;
+
+        // bind group b reads and writes the current atom buffer as its last one, which is the
+        // one the next time step starts from
+        self.bin(command_encoder, &self.atom_bind_group_b, &push_constants);
 
-            //command_encoder.copy_buffer_to_buffer(ba, 0, bb, 0, self.atom_buffer_size);
+        let cells_per_color = (self.cells_per_side as u32).div_ceil(2);
+        let workgroups = cells_per_color.div_ceil(MONTE_CARLO_WORKGROUP_SIZE);
+        for color in 0..4 {
+            let push_constants = PushConstants {
+                color,
+                ..push_constants
+            };
+            // separate passes, so every color sees the moves of the previous ones
+            let mut monte_carlo_pass = command_encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("Monte Carlo Pass"),
+            });
+            monte_carlo_pass.set_pipeline(&self.monte_carlo_pipeline);
+            monte_carlo_pass.set_bind_group(0, &self.atom_bind_group_b, &[]);
+            monte_carlo_pass.set_bind_group(1, &self.monte_carlo_bind_group, &[]);
+            monte_carlo_pass.set_push_constants(0, bytemuck::bytes_of(&push_constants));
+            monte_carlo_pass.dispatch_workgroups(workgroups, workgroups, 1);
         }
     }
 
-    pub fn instance_buffer(&self) -> &Arc<Buffer> {
-        &self.atom_buffer_curr
+    /// the amount of attempted and accepted monte carlo moves since the last call to
+    /// [`HashGrid::set_monte_carlo`]
+    pub async fn monte_carlo_acceptance(
+        &self,
+        device: &Arc<Device>,
+        queue: &Queue,
+    ) -> Result<(u32, u32)> {
+        let bytes = read_buffer(device, queue, &self.monte_carlo_buffer).await?;
+        let monte_carlo = bytemuck::pod_read_unaligned::<GpuMonteCarlo>(&bytes);
+        Ok((monte_carlo.attempted, monte_carlo.accepted))
+    }
+
+    /// writes the bonds, angles and constraints of every slot to their buffers. Has to be called
+    /// whenever atoms change their slots, as the buffers refer to the atoms by their slots.
+    fn write_topology(&self, queue: &Queue) {
+        if !self.parameters.bond_types.is_empty() {
+            let table = bond_table(&self.ids, &self.topology, self.capacity);
+            queue.write_buffer(&self.bond_buffer, 0, bytemuck::cast_slice(&table));
+        }
+        if !self.parameters.angle_types.is_empty() {
+            let table = angle_table(&self.ids, &self.topology, self.capacity);
+            queue.write_buffer(&self.angle_buffer, 0, bytemuck::cast_slice(&table));
+        }
+        if let Some(constraints) = &self.constraints {
+            constraints.write(
+                queue,
+                &self.ids,
+                &self.topology,
+                &self.parameters.bond_types,
+            );
+        }
+    }
+
+    /// records clearing the cells and binning the atoms of the last atom buffer of `bind_group`
+    /// into them. In deterministic mode, the cells are sorted afterwards, in a pass of its own
+    /// so the sort sees every atom of the cell.
+    fn bin(
+        &self,
+        command_encoder: &mut CommandEncoder,
+        bind_group: &BindGroup,
+        push_constants: &PushConstants,
+    ) {
+        command_encoder.clear_buffer(&self.cell_buffer, 0, None);
+        {
+            let mut bin_pass = command_encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("Bin Pass"),
+            });
+            bin_pass.set_pipeline(&self.bin_pipeline);
+            bin_pass.set_bind_group(0, bind_group, &[]);
+            bin_pass.set_push_constants(0, bytemuck::bytes_of(push_constants));
+            bin_pass.dispatch_workgroups(self.atom_count.div_ceil(ATOM_WORKGROUP_SIZE), 1, 1);
+        }
+
+        if self.options.deterministic {
+            let cells = (self.cells_per_side * self.cells_per_side) as u32;
+            let mut sort_pass = command_encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("Sort Cells Pass"),
+            });
+            sort_pass.set_pipeline(&self.sort_cells_pipeline);
+            sort_pass.set_bind_group(0, bind_group, &[]);
+            sort_pass.set_push_constants(0, bytemuck::bytes_of(push_constants));
+            sort_pass.dispatch_workgroups(cells.div_ceil(ATOM_WORKGROUP_SIZE), 1, 1);
+        }
+    }
+
+    /// dispatches the interaction or integration pipeline of `compute_pass`, with one
+    /// invocation per atom or per cell depending on [`GpuOptions::dispatch`]
+    fn dispatch_atoms(&self, compute_pass: &mut ComputePass, atom_workgroups: u32) {
+        match self.options.dispatch {
+            Dispatch::Atoms => compute_pass.dispatch_workgroups(atom_workgroups, 1, 1),
+            Dispatch::Cells => compute_pass.dispatch_workgroups(
+                self.cells_per_side as u32,
+                self.cells_per_side as u32,
+                1,
+            ),
+        }
+    }
+
+    /// records the reciprocal space part of the ewald sum for the last atom buffer of
+    /// `bind_group`: the passes of [`HashGrid::reciprocal_potential_passes`], and the forces in
+    /// another one
+    fn reciprocal(
+        &self,
+        command_encoder: &mut CommandEncoder,
+        bind_group: &BindGroup,
+        reciprocal_space: &ReciprocalSpace,
+        push_constants: &PushConstants,
+    ) {
+        let mut passes = self.reciprocal_potential_passes(reciprocal_space);
+        passes.push((
+            "Reciprocal Pass",
+            match reciprocal_space {
+                ReciprocalSpace::Direct(_) => &self.reciprocal_pipeline,
+                ReciprocalSpace::Mesh(_) => &self.interpolate_pipeline,
+            },
+            self.atom_count.div_ceil(ATOM_WORKGROUP_SIZE),
+        ));
+        self.record_passes(
+            command_encoder,
+            bind_group,
+            reciprocal_space,
+            &passes,
+            push_constants,
+        );
+    }
+
+    /// the passes that evaluate the structure factors, or the potential of the mesh, of the
+    /// last atom buffer, with their labels and amounts of workgroups. The mesh takes a pass per
+    /// stage of the convolution, so that every stage is complete before the next one begins.
+    fn reciprocal_potential_passes(
+        &self,
+        reciprocal_space: &ReciprocalSpace,
+    ) -> Vec<(&'static str, &ComputePipeline, u32)> {
+        match reciprocal_space {
+            ReciprocalSpace::Direct(ewald) => vec![(
+                "Structure Factor Pass",
+                &self.structure_factor_pipeline,
+                ewald.wave_vector_count,
+            )],
+            ReciprocalSpace::Mesh(mesh) => {
+                let points = (mesh.size * mesh.size).div_ceil(ATOM_WORKGROUP_SIZE);
+                vec![
+                    ("Spread Pass", &self.spread_pipeline, points),
+                    ("FFT Rows Pass", &self.fft_rows_pipeline, mesh.size),
+                    ("FFT Columns Pass", &self.fft_columns_pipeline, mesh.size),
+                    ("Convolve Pass", &self.convolve_pipeline, points),
+                    ("FFT Rows Pass", &self.fft_rows_pipeline, mesh.size),
+                    ("FFT Columns Pass", &self.fft_columns_pipeline, mesh.size),
+                ]
+            }
+        }
+    }
+
+    /// records the given passes, one after another, with the given atom bind group and the bind
+    /// group of the reciprocal space sum
+    fn record_passes(
+        &self,
+        command_encoder: &mut CommandEncoder,
+        bind_group: &BindGroup,
+        reciprocal_space: &ReciprocalSpace,
+        passes: &[(&str, &ComputePipeline, u32)],
+        push_constants: &PushConstants,
+    ) {
+        for &(label, pipeline, workgroups) in passes {
+            let mut pass =
+                command_encoder.begin_compute_pass(&ComputePassDescriptor { label: Some(label) });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.set_bind_group(1, reciprocal_space.bind_group(), &[]);
+            pass.set_push_constants(0, bytemuck::bytes_of(push_constants));
+            pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+    }
+
+    fn push_constants(&self) -> PushConstants {
+        PushConstants {
+            cells_per_side: self.cells_per_side,
+            step: self.step as u32,
+            atom_count: self.atom_count,
+            offset: Vector2::zeros(),
+            color: 0,
+        }
+    }
+
+    /// copies the current atoms followed by each of their property arrays into `destination`,
+    /// which must be at least [`HashGrid::atom_buffer_size`] plus [`Properties::COUNT`] times
+    /// [`HashGrid::property_buffer_size`] bytes large.
+    ///
+    /// The atoms are copied in the layout of the hash grid, as if the destination had room for
+    /// exactly [`HashGrid::atom_count`] atoms.
+    pub fn copy_atoms(&self, command_encoder: &mut CommandEncoder, destination: &Buffer) {
+        self.copy_fields(
+            command_encoder,
+            &self.atom_buffer_curr,
+            destination,
+            self.atom_count,
+        );
+        for (index, buffer) in self.property_buffers.iter().enumerate() {
+            command_encoder.copy_buffer_to_buffer(
+                buffer,
+                0,
+                destination,
+                self.atom_buffer_size() + index as BufferAddress * self.property_buffer_size(),
+                self.property_buffer_size(),
+            );
+        }
+    }
+
+    /// records a copy of every field of the atoms in `source`, an atom buffer of the hash grid,
+    /// to the start of `destination`, which is laid out for `capacity` atoms
+    fn copy_fields(
+        &self,
+        command_encoder: &mut CommandEncoder,
+        source: &Buffer,
+        destination: &Buffer,
+        capacity: u32,
+    ) {
+        for field in 0..self.options.layout.field_count() {
+            let range = self
+                .options
+                .layout
+                .field_range(field, 0..self.atom_count, self.capacity);
+            command_encoder.copy_buffer_to_buffer(
+                source,
+                range.start,
+                destination,
+                self.options.layout.field_range(field, 0..0, capacity).start,
+                range.end - range.start,
+            );
+        }
+    }
+
+    /// reorders the atoms by the position of their cell along `curve`, so that atoms that are
+    /// close in space are also close in memory. Atoms within a cell keep their relative order,
+    /// and their properties and the rounding errors carried for [`Precision::Compensated`] move
+    /// along with them.
+    pub async fn sort(&mut self, device: &Arc<Device>, queue: &Queue, curve: Curve) -> Result<()> {
+        let properties = self.read_properties(device, queue).await?;
+        let atoms_curr = self.read_atoms(device, queue).await?;
+        let atoms_last = self
+            .read_atom_buffer(device, queue, &self.atom_buffer_last)
+            .await?;
+        let compensation = self.read_compensation(device, queue).await?;
+
+        let n = self.cells_per_side;
+        let cell_coordinate = |position: f32, boundary: Boundary| {
+            let cell = (position / self.parameters.cell_side_length).floor() as i32;
+            match boundary {
+                Boundary::Periodic => cell.rem_euclid(n),
+                Boundary::Open | Boundary::Reflective => cell.clamp(0, n - 1),
+            }
+        };
+        let mut order = (0..atoms_curr.len()).collect::<Vec<_>>();
+        order.sort_by_cached_key(|&index| {
+            let position = atoms_curr[index].position;
+            let x = cell_coordinate(position.x, self.parameters.boundaries[0]);
+            let y = cell_coordinate(position.y, self.parameters.boundaries[1]);
+            curve.key(x as u32, y as u32, n as u32)
+        });
+
+        self.ids = order.iter().map(|&index| self.ids[index]).collect();
+        self.write_topology(queue);
+        let properties = properties.permuted(&order);
+        for (buffer, column) in self.property_buffers.iter().zip(properties.columns()) {
+            queue.write_buffer(buffer, 0, column);
+        }
+        for (buffer, atoms) in [
+            (&*self.atom_buffer_curr, &atoms_curr),
+            (&self.atom_buffer_last, &atoms_last),
+        ] {
+            let atoms = order.iter().map(|&index| atoms[index]).collect::<Vec<_>>();
+            queue.write_buffer(
+                buffer,
+                0,
+                &self.options.layout.encode(&atoms, self.capacity),
+            );
+        }
+        let compensation = order
+            .iter()
+            .map(|&index| compensation[index])
+            .collect::<Vec<_>>();
+        queue.write_buffer(
+            &self.compensation_buffer,
+            0,
+            bytemuck::cast_slice(&compensation),
+        );
+
+        if let Some(neighbour_lists) = &self.neighbour_lists {
+            neighbour_lists.invalidate(queue);
+        }
+        Ok(())
+    }
+
+    /// the atom buffer and atom count to render, kept up to date as atoms are inserted and
+    /// removed
+    pub fn instances(&self) -> Arc<Mutex<Instances>> {
+        self.instances.clone()
+    }
+
+    /// the size of the atoms in the atom buffers in bytes, the buffers may be larger
+    pub fn atom_buffer_size(&self) -> BufferAddress {
+        self.atom_count as BufferAddress * size_of::<Atom>() as BufferAddress
+    }
+
+    /// the size of the properties of the atoms in each property buffer in bytes
+    pub fn property_buffer_size(&self) -> BufferAddress {
+        self.atom_count as BufferAddress * Properties::SIZE as BufferAddress
+    }
+
+    pub fn options(&self) -> GpuOptions {
+        self.options
+    }
+
+    pub fn atom_count(&self) -> u32 {
+        self.atom_count
+    }
+
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn parameters(&self) -> &SimulationParameters {
+        &self.parameters
+    }
+
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+}
+
+/// an atom buffer in the given layout with room for `capacity` atoms that starts with the given
+/// atoms
+fn create_atom_buffer(
+    device: &Device,
+    label: &str,
+    layout: AtomLayout,
+    capacity: u32,
+    atoms: &[Atom],
+) -> Buffer {
+    let buffer = device.create_buffer(&BufferDescriptor {
+        label: Some(label),
+        size: capacity as BufferAddress * size_of::<Atom>() as BufferAddress,
+        usage: ATOM_BUFFER_USAGES,
+        mapped_at_creation: true,
+    });
+    let contents = layout.encode(atoms, capacity);
+    buffer
+        .slice(..)
+        .get_mapped_range_mut()
+        .copy_from_slice(&contents);
+    buffer.unmap();
+    buffer
+}
+
+/// one buffer per property array with room for `capacity` atoms, starting with the given
+/// properties
+fn create_property_buffers(
+    device: &Device,
+    capacity: u32,
+    properties: &Properties,
+) -> [Buffer; Properties::COUNT] {
+    properties.columns().map(|contents| {
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Atom Property Buffer"),
+            size: capacity as BufferAddress * Properties::SIZE as BufferAddress,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+            mapped_at_creation: true,
+        });
+        buffer.slice(..).get_mapped_range_mut()[..contents.len()].copy_from_slice(contents);
+        buffer.unmap();
+        buffer
+    })
+}
+
+/// a buffer with room for the rounding errors of `capacity` atoms, starting with
+/// `compensation` and zeroed after it
+fn create_compensation_buffer(
+    device: &Device,
+    capacity: u32,
+    compensation: &[Vector2<f32>],
+) -> Buffer {
+    let mut contents = compensation.to_vec();
+    contents.resize(capacity as usize, Vector2::zeros());
+    device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("Compensation Buffer"),
+        contents: bytemuck::cast_slice(&contents),
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+    })
+}
+
+/// the slot of every atom id
+fn slots(ids: &[u32]) -> HashMap<u32, u32> {
+    ids.iter()
+        .enumerate()
+        .map(|(slot, &id)| (id, slot as u32))
+        .collect()
+}
+
+/// the bonds of the atoms with the given ids in their slots, with the bonded atoms given by their
+/// slots as well, followed by empty slots up to `capacity`
+fn bond_table(ids: &[u32], topology: &Topology, capacity: u32) -> Vec<GpuBonds> {
+    let slots = slots(ids);
+    let mut table = vec![GpuBonds::EMPTY; capacity as usize];
+    for bond in &topology.bonds {
+        if let (Some(&a), Some(&b)) = (slots.get(&bond.a), slots.get(&bond.b)) {
+            table[a as usize].push(b, bond.kind);
+            table[b as usize].push(a, bond.kind);
+        }
+    }
+    table
+}
+
+/// the angles of the atoms with the given ids in their slots, listed at all three atoms of every
+/// angle, followed by empty slots up to `capacity`
+fn angle_table(ids: &[u32], topology: &Topology, capacity: u32) -> Vec<GpuAngles> {
+    let slots = slots(ids);
+    let mut table = vec![GpuAngles::EMPTY; capacity as usize];
+    for angle in &topology.angles {
+        if let (Some(&a), Some(&b), Some(&c)) = (
+            slots.get(&angle.a),
+            slots.get(&angle.b),
+            slots.get(&angle.c),
+        ) {
+            for slot in [a, b, c] {
+                table[slot as usize].push([a, b, c, angle.kind]);
+            }
+        }
+    }
+    table
+}
+
+/// the constrained bonds of the atoms with the given ids in their slots, grouped by molecule,
+/// and the range of constraints of every molecule, both padded to `capacity`. Removed atoms
+/// leave fewer constraints behind, so the remaining molecules get empty ranges.
+fn constraint_table(
+    ids: &[u32],
+    topology: &Topology,
+    bond_types: &[BondPotential],
+    capacity: u32,
+) -> (Vec<GpuConstraint>, Vec<[u32; 2]>) {
+    let slots = slots(ids);
+    let constraints = topology
+        .bonds
+        .iter()
+        .filter(|bond| bond_types[bond.kind as usize].is_constraint())
+        .filter_map(|bond| {
+            Some(GpuConstraint {
+                a: *slots.get(&bond.a)?,
+                b: *slots.get(&bond.b)?,
+                length: bond_types[bond.kind as usize].length(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut by_slot = HashMap::<u32, Vec<usize>>::new();
+    for (index, constraint) in constraints.iter().enumerate() {
+        by_slot.entry(constraint.a).or_default().push(index);
+        by_slot.entry(constraint.b).or_default().push(index);
+    }
+
+    // every molecule is collected by walking its constraints from the first one
+    let mut visited = vec![false; constraints.len()];
+    let mut table = Vec::with_capacity(constraints.len());
+    let mut molecules = Vec::new();
+    for first in 0..constraints.len() {
+        if visited[first] {
+            continue;
+        }
+        let start = table.len() as u32;
+        visited[first] = true;
+        let mut pending = vec![first];
+        while let Some(index) = pending.pop() {
+            let constraint = constraints[index];
+            table.push(constraint);
+            for slot in [constraint.a, constraint.b] {
+                for &next in &by_slot[&slot] {
+                    if !visited[next] {
+                        visited[next] = true;
+                        pending.push(next);
+                    }
+                }
+            }
+        }
+        molecules.push([start, table.len() as u32]);
+    }
+    table.resize(capacity as usize, GpuConstraint::zeroed());
+    molecules.resize(capacity as usize, [0, 0]);
+    (table, molecules)
+}
+
+/// binds the two atom buffers in the given order, followed by the cell, parameter, mass, pair
+/// potential, compensation, bond, bond type, angle, angle type and charge buffers
+fn create_atom_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    label: &str,
+    atom_buffers: [&Buffer; 2],
+    shared_buffers: [&Buffer; 10],
+) -> BindGroup {
+    let entries = atom_buffers
+        .into_iter()
+        .chain(shared_buffers)
+        .enumerate()
+        .map(|(binding, buffer)| BindGroupEntry {
+            binding: binding as u32,
+            resource: buffer.as_entire_binding(),
+        })
+        .collect::<Vec<_>>();
+
+    device.create_bind_group(&BindGroupDescriptor {
+        label: Some(label),
+        layout,
+        entries: &entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario::{Overrides, Setup};
+    use crate::simulation::readback::AtomReadback;
+
+    /// a gas of 200 atoms in a periodic box, with compensated positions
+    const GAS: &str = r#"
+        seed = 1
+        [box]
+        side_length = 20.0
+        cell_size = 2.5
+        [[species]]
+        name = "Ar"
+        mass = 1.0
+        [[generators]]
+        kind = "random_gas"
+        species = "Ar"
+        count = 200
+        min_separation = 1.0
+        [[potentials]]
+        kind = "lennard_jones"
+        species = ["Ar", "Ar"]
+        epsilon = 1.0
+        sigma = 1.0
+        [gpu]
+        precision = "compensated"
+        [[stages]]
+        kind = "produce"
+        steps = 10
+    "#;
+
+    fn hash_grid(device: &Device, setup: &Setup) -> HashGrid {
+        HashGrid::from_slice(
+            device,
+            &setup.atoms,
+            &setup.properties,
+            &setup.topology,
+            &setup.parameters,
+            setup.seed,
+            setup.gpu,
+        )
+    }
+
+    #[tokio::test]
+    async fn sorting_keeps_compensation() {
+        let setup = Setup::parse(GAS, Path::new("."), Overrides::default()).unwrap();
+        let Some((device, queue)) = crate::test_device().await else {
+            return;
+        };
+        let mut hash_grid = hash_grid(&device, &setup);
+        let compensation_of = |id: u32| Vector2::new(id as f32 * 1e-7, -(id as f32) * 1e-7);
+        let compensation = hash_grid
+            .ids
+            .iter()
+            .map(|&id| compensation_of(id))
+            .collect::<Vec<_>>();
+        queue.write_buffer(
+            &hash_grid.compensation_buffer,
+            0,
+            bytemuck::cast_slice(&compensation),
+        );
+
+        let ids = hash_grid.ids.clone();
+        hash_grid
+            .sort(&device, &queue, Curve::Hilbert)
+            .await
+            .unwrap();
+        assert_ne!(hash_grid.ids, ids);
+        let sorted = hash_grid.read_compensation(&device, &queue).await.unwrap();
+        for (&id, compensation) in hash_grid.ids.iter().zip(&sorted) {
+            assert_eq!(*compensation, compensation_of(id));
+        }
+    }
+
+    #[tokio::test]
+    async fn checkpoints_round_trip() {
+        let scenario = GAS
+            .replace("cell_size = 2.5", "cell_size = 4.0")
+            .replace("[gpu]", "[neighbour_list]\nskin = 0.3\n[gpu]");
+        let setup = Setup::parse(&scenario, Path::new("."), Overrides::default()).unwrap();
+        let Some((device, queue)) = crate::test_device().await else {
+            return;
+        };
+        let mut hash_grid = hash_grid(&device, &setup);
+        let mut command_encoder =
+            device.create_command_encoder(&CommandEncoderDescriptor::default());
+        for _ in 0..5 {
+            hash_grid.update(&mut command_encoder);
+        }
+        queue.submit(Some(command_encoder.finish()));
+
+        // the rounding errors and the lists come back from the gpu as they were saved
+        let path = std::env::temp_dir().join(format!("jones-gpu-{}.chk", std::process::id()));
+        hash_grid.save(&device, &queue, &path).await.unwrap();
+        let saved = std::fs::read(&path).unwrap();
+        let loaded = HashGrid::load(&device, &path, setup.gpu).unwrap();
+        loaded.save(&device, &queue, &path).await.unwrap();
+        let resaved = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            saved == resaved,
+            "the checkpoint changed when it was loaded"
+        );
+
+        let checkpoint = Checkpoint::read(&mut saved.as_slice()).unwrap();
+        assert!(checkpoint
+            .position_compensation
+            .iter()
+            .any(|compensation| *compensation != Vector2::zeros()));
+        assert!(checkpoint.neighbour_lists.is_some());
+    }
+
+    /// the hashes of the frames after each of `steps` time steps of a thermostatted setup
+    async fn frame_hashes(
+        device: &Arc<Device>,
+        queue: &Queue,
+        setup: &Setup,
+        steps: u32,
+    ) -> Vec<u64> {
+        let mut hash_grid = hash_grid(device, setup);
+        hash_grid.set_mode(queue, false, true);
+        let mut readback = AtomReadback::new(device, &hash_grid);
+        let mut hashes = Vec::new();
+        for _ in 0..steps {
+            let mut command_encoder =
+                device.create_command_encoder(&CommandEncoderDescriptor::default());
+            hash_grid.update(&mut command_encoder);
+            readback.copy(device, &mut command_encoder, &hash_grid);
+            queue.submit(Some(command_encoder.finish()));
+            readback.map();
+            hashes.push(readback.take(device).await.unwrap().hash());
+        }
+        hashes
+    }
+
+    #[tokio::test]
+    async fn deterministic_runs_are_identical() {
+        let scenario = GAS.replace(
+            "[gpu]",
+            "[velocities]\ntemperature = 1.0\n\
+             [thermostat]\nkind = \"langevin\"\ntemperature = 1.0\ndamping = 1.0\n\
+             [gpu]\ndeterministic = true",
+        );
+        let setup = Setup::parse(&scenario, Path::new("."), Overrides::default()).unwrap();
+        assert!(setup.gpu.deterministic);
+        let Some((device, queue)) = crate::test_device().await else {
+            return;
+        };
+        let first = frame_hashes(&device, &queue, &setup, 50).await;
+        let second = frame_hashes(&device, &queue, &setup, 50).await;
+        for (step, (a, b)) in first.iter().zip(&second).enumerate() {
+            assert_eq!(a, b, "the runs differ after {} steps", step + 1);
+        }
     }
 }
@@ -0,0 +1,104 @@
+use crate::simulation::Atom;
+use bytemuck::Zeroable;
+use serde::Deserialize;
+use std::mem::size_of;
+use std::ops::Range;
+use wgpu::BufferAddress;
+
+/// how the atoms are arranged in the atom buffers of a [`super::hashgrid::HashGrid`]. The
+/// layout is invisible outside of the hash grid, atoms are always read back as [`Atom`]s.
+///
+/// An [`Atom`] consists of four fields of 8 bytes each: the position, the velocity, the force
+/// and the visual together with the species. A buffer with room for `capacity` atoms stores
+/// each field in a region of its own, so field `k` of the atom in slot `i` is found at byte
+/// `k * field_size * capacity + i * field_size`. Array of structs is the special case of a
+/// single field that spans the whole atom.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AtomLayout {
+    /// whole atoms one after the other
+    #[default]
+    ArrayOfStructs,
+    /// all positions, then all velocities, then all forces and finally the visuals and species
+    /// of all atoms, so passes that only need the positions read contiguous memory
+    StructOfArrays,
+}
+
+impl AtomLayout {
+    /// the source of the simulation shader for this layout. `interact.wgsl` reads and writes
+    /// the atoms only through the accessors that the layout prepends.
+    pub fn shader_source(self) -> &'static str {
+        match self {
+            AtomLayout::ArrayOfStructs => concat!(
+                include_str!("shaders/atoms_aos.wgsl"),
+                include_str!("shaders/interact.wgsl")
+            ),
+            AtomLayout::StructOfArrays => concat!(
+                include_str!("shaders/atoms_soa.wgsl"),
+                include_str!("shaders/interact.wgsl")
+            ),
+        }
+    }
+
+    /// the size of a single field of an atom in bytes
+    pub fn field_size(self) -> usize {
+        match self {
+            AtomLayout::ArrayOfStructs => size_of::<Atom>(),
+            AtomLayout::StructOfArrays => 2 * size_of::<f32>(),
+        }
+    }
+
+    pub fn field_count(self) -> usize {
+        size_of::<Atom>() / self.field_size()
+    }
+
+    /// the byte range of field `field` of the atoms in `slots` in a buffer with room for
+    /// `capacity` atoms
+    pub fn field_range(
+        self,
+        field: usize,
+        slots: Range<u32>,
+        capacity: u32,
+    ) -> Range<BufferAddress> {
+        let field_size = self.field_size() as BufferAddress;
+        let start = field as BufferAddress * field_size * capacity as BufferAddress;
+        start + slots.start as BufferAddress * field_size
+            ..start + slots.end as BufferAddress * field_size
+    }
+
+    /// the contents of a buffer with room for `capacity` atoms that starts with `atoms`
+    pub fn encode(self, atoms: &[Atom], capacity: u32) -> Vec<u8> {
+        let bytes = bytemuck::cast_slice::<_, u8>(atoms);
+        let field_size = self.field_size();
+        let mut buffer = vec![0; capacity as usize * size_of::<Atom>()];
+        for field in 0..self.field_count() {
+            let range = self.field_range(field, 0..atoms.len() as u32, capacity);
+            let region = &mut buffer[range.start as usize..range.end as usize];
+            for (destination, atom) in region
+                .chunks_exact_mut(field_size)
+                .zip(bytes.chunks_exact(size_of::<Atom>()))
+            {
+                destination.copy_from_slice(&atom[field * field_size..][..field_size]);
+            }
+        }
+        buffer
+    }
+
+    /// the first `count` atoms of the contents of a buffer with room for `capacity` atoms
+    pub fn decode(self, buffer: &[u8], count: usize, capacity: u32) -> Vec<Atom> {
+        let field_size = self.field_size();
+        let mut atoms = vec![Atom::zeroed(); count];
+        let bytes = bytemuck::cast_slice_mut::<_, u8>(&mut atoms);
+        for field in 0..self.field_count() {
+            let range = self.field_range(field, 0..count as u32, capacity);
+            let region = &buffer[range.start as usize..range.end as usize];
+            for (atom, source) in bytes
+                .chunks_exact_mut(size_of::<Atom>())
+                .zip(region.chunks_exact(field_size))
+            {
+                atom[field * field_size..][..field_size].copy_from_slice(source);
+            }
+        }
+        atoms
+    }
+}
@@ -1,13 +1,27 @@
+pub mod defects;
+pub mod dispatch;
+pub mod ewald;
+pub mod external;
+pub mod gcmc;
+pub mod generators;
 pub mod hashgrid;
+pub mod layout;
+pub mod monte_carlo;
+pub mod ordering;
+pub mod precision;
+pub mod properties;
+pub mod readback;
+pub mod topology;
 
 use bytemuck::{Pod, Zeroable};
+use ewald::Ewald;
+use external::ExternalForces;
 use nalgebra::Vector2;
-use std::mem::size_of;
-use wgpu::{vertex_attr_array, BufferAddress, VertexAttribute};
+use topology::{AnglePotential, BondPotential, ConstraintSolver};
 
 /// the conversion factors from constants to real-world data are not trivial, though
 /// the simulation result should correspond to reality at least by proportionality.
-pub const DELTA_T: f32 = 1e-6;
+pub const DELTA_T: f32 = 1e-3;
 
 #[repr(C)]
 #[derive(Copy, Clone, Pod, Zeroable)]
@@ -16,11 +30,8 @@ pub struct Atom {
     velocity: Vector2<f32>,
     force: Vector2<f32>,
     visual: f32,
-}
-
-impl Atom {
-    pub const INSTANCE_ATTRIBS: &'_ [VertexAttribute] =
-        &vertex_attr_array![2 => Float32x2, 3 => Float32x2, 4 => Float32x2, 5 => Float32];
+    /// index into the species table of the simulation parameters
+    species: u32,
 }
 
 impl Atom {
@@ -30,6 +41,165 @@ impl Atom {
             velocity,
             force,
             visual: 0.0,
+            species: 0,
+        }
+    }
+
+    pub fn with_species(mut self, species: u32) -> Self {
+        self.species = species;
+        self
+    }
+
+    pub fn position(&self) -> Vector2<f32> {
+        self.position
+    }
+
+    pub fn velocity(&self) -> Vector2<f32> {
+        self.velocity
+    }
+
+    pub fn force(&self) -> Vector2<f32> {
+        self.force
+    }
+
+    pub fn species(&self) -> u32 {
+        self.species
+    }
+}
+
+/// how atoms are treated when they reach the edge of the grid along one axis
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Boundary {
+    /// atoms may leave the grid, they are hashed into the outermost cells
+    Open,
+    /// atoms leaving on one side re-enter on the other, interactions use the minimum image
+    Periodic,
+    /// atoms are mirrored back into the grid and their velocity is flipped
+    Reflective,
+}
+
+impl Boundary {
+    pub(crate) fn gpu_id(self) -> u32 {
+        match self {
+            Boundary::Open => 0,
+            Boundary::Periodic => 1,
+            Boundary::Reflective => 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Species {
+    pub name: String,
+    /// the default mass of atoms of this species, see [`properties::Properties`]
+    pub mass: f32,
+    /// the default charge of atoms of this species
+    pub charge: f32,
+}
+
+/// parameters of the (truncated) lennard-jones potential between two species
+#[derive(Copy, Clone, Debug)]
+pub struct LennardJones {
+    pub epsilon: f32,
+    pub sigma: f32,
+    pub cutoff: f32,
+}
+
+impl LennardJones {
+    /// lorentz-berthelot mixing rule, used for species pairs that have no explicit parameters
+    pub fn mix(&self, other: &Self) -> Self {
+        Self {
+            epsilon: (self.epsilon * other.epsilon).sqrt(),
+            sigma: (self.sigma + other.sigma) * 0.5,
+            cutoff: self.cutoff.max(other.cutoff),
+        }
+    }
+
+    /// the truncated potential at the given squared distance, matching `lennard_jones_energy`
+    /// in `interact.wgsl`
+    pub fn energy(&self, dist_sq: f32) -> f32 {
+        if dist_sq > self.cutoff * self.cutoff {
+            return 0.0;
         }
+        let s6 = (self.sigma * self.sigma / dist_sq).powi(3);
+        4.0 * self.epsilon * (s6 * s6 - s6)
     }
 }
+
+#[derive(Copy, Clone, Debug)]
+pub enum Thermostat {
+    /// langevin dynamics, i.e. a friction term plus a random force driven by the gpu rng
+    Langevin { temperature: f32, damping: f32 },
+}
+
+/// whether a side of the given amount of cells is made of whole cells, up to rounding errors
+pub fn whole_cells(cells: f32) -> bool {
+    (cells - cells.round()).abs() < 1e-3
+}
+
+/// everything that determines how the atoms of a [`hashgrid::HashGrid`] evolve
+#[derive(Clone, Debug)]
+pub struct SimulationParameters {
+    /// the side length of the actual grid
+    pub grid_side_length: f32,
+    /// the side length of each cell. Must not be smaller than the largest cutoff.
+    pub cell_side_length: f32,
+    pub time_step: f32,
+    /// boundary conditions along x and y
+    pub boundaries: [Boundary; 2],
+    pub species: Vec<Species>,
+    /// pair potentials in row-major order, i.e. `species.len().pow(2)` entries
+    pub pair_potentials: Vec<LennardJones>,
+    /// the potentials of the bond types, see [`topology::Topology`]
+    pub bond_types: Vec<BondPotential>,
+    /// the potentials of the angle types, see [`topology::Topology`]
+    pub angle_types: Vec<AnglePotential>,
+    pub constraint_solver: ConstraintSolver,
+    pub thermostat: Option<Thermostat>,
+    /// the skin of the verlet neighbour lists. Without one, the interaction pass scans the
+    /// neighbouring cells in every time step.
+    pub neighbour_skin: Option<f32>,
+    /// the ewald summation of the coulomb interactions of the charges, if they interact at all
+    pub electrostatics: Option<Ewald>,
+    /// the forces of fields outside the system, see [`ExternalForces`]
+    pub external_forces: ExternalForces,
+}
+
+impl SimulationParameters {
+    pub fn pair_potential(&self, a: u32, b: u32) -> &LennardJones {
+        &self.pair_potentials[a as usize * self.species.len() + b as usize]
+    }
+
+    /// the vector from `a` to `b`, using the nearest periodic image along periodic axes
+    pub fn separation(&self, a: Vector2<f32>, b: Vector2<f32>) -> Vector2<f32> {
+        let l = self.grid_side_length;
+        let mut d = b - a;
+        for (axis, boundary) in self.boundaries.iter().enumerate() {
+            if *boundary == Boundary::Periodic {
+                d[axis] -= l * (d[axis] / l).round();
+            }
+        }
+        d
+    }
+}
+
+/// the kinetic energy and the temperature of the given atoms with the given masses, in units
+/// where `k_B = 1`, of which `constraints` bonds are held at fixed lengths. The sum is
+/// accumulated in double precision, so it does not drift with the amount of atoms.
+pub fn kinetic_energy_and_temperature(
+    atoms: &[Atom],
+    masses: &[f32],
+    constraints: usize,
+) -> (f32, f32) {
+    let kinetic_energy = atoms
+        .iter()
+        .zip(masses)
+        .map(|(atom, &mass)| 0.5 * mass as f64 * atom.velocity.cast::<f64>().norm_squared())
+        .sum::<f64>() as f32;
+
+    // two degrees of freedom per atom, less the two of the centre of mass momentum and one per
+    // constrained bond
+    let degrees_of_freedom = (2 * atoms.len()).saturating_sub(2 + constraints).max(1);
+    let temperature = 2.0 * kinetic_energy / degrees_of_freedom as f32;
+    (kinetic_energy, temperature)
+}
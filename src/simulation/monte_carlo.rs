@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+/// the largest maximum displacement tuning may pick, as a fraction of the cell size. Moves
+/// that leave their cell are rejected, so larger displacements would mostly be wasted.
+const MAX_DISPLACEMENT_LIMIT: f32 = 0.5;
+
+/// metropolis monte carlo sampling of the atom positions at a fixed temperature, see
+/// `main_monte_carlo` in `interact.wgsl`. Every sweep attempts `moves` displacements per atom.
+#[derive(Clone, Debug)]
+pub struct MonteCarlo {
+    pub temperature: f32,
+    /// the initial maximum displacement along each axis
+    pub max_displacement: f32,
+    pub moves: u32,
+    /// the amount of sweeps between reports of the acceptance ratio
+    pub every: u64,
+    /// the acceptance ratio the maximum displacement is tuned towards at every report
+    pub target_acceptance: Option<f32>,
+    /// the file the reports are written to, stdout if not given
+    pub path: Option<PathBuf>,
+}
+
+impl MonteCarlo {
+    /// the maximum displacement for the next interval, given the one of the last interval and
+    /// its acceptance ratio. The displacement changes by at most a factor of two at once.
+    pub fn tune(&self, max_displacement: f32, acceptance: f32, cell_side_length: f32) -> f32 {
+        match self.target_acceptance {
+            Some(target) => {
+                let factor = (acceptance / target).clamp(0.5, 2.0);
+                let limit = MAX_DISPLACEMENT_LIMIT * cell_side_length;
+                (max_displacement * factor).clamp(limit * 1e-4, limit)
+            }
+            None => max_displacement,
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use serde::Deserialize;
+
+/// a space filling curve through the cells of the hash grid. Atoms stored in the order of
+/// their cells along the curve are close in memory when they are close in space.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Curve {
+    /// z-order, i.e. the interleaved bits of the cell coordinates
+    Morton,
+    /// slightly more local than z-order, as consecutive cells are always adjacent
+    Hilbert,
+}
+
+impl Curve {
+    /// the position of the cell `(x, y)` along the curve through a grid of `cells_per_side`
+    /// cells per side
+    pub fn key(self, x: u32, y: u32, cells_per_side: u32) -> u64 {
+        match self {
+            Curve::Morton => spread(x) | spread(y) << 1,
+            Curve::Hilbert => hilbert(x, y, cells_per_side.next_power_of_two()),
+        }
+    }
+}
+
+/// the bits of `value` moved to the even bits of the result
+fn spread(value: u32) -> u64 {
+    let mut value = value as u64;
+    value = (value | value << 16) & 0x0000_ffff_0000_ffff;
+    value = (value | value << 8) & 0x00ff_00ff_00ff_00ff;
+    value = (value | value << 4) & 0x0f0f_0f0f_0f0f_0f0f;
+    value = (value | value << 2) & 0x3333_3333_3333_3333;
+    (value | value << 1) & 0x5555_5555_5555_5555
+}
+
+/// the distance of `(x, y)` along the hilbert curve through a square of side `n`, which must be
+/// a power of two
+fn hilbert(mut x: u32, mut y: u32, n: u32) -> u64 {
+    let mut distance = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = (x & s > 0) as u32;
+        let ry = (y & s > 0) as u32;
+        distance += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+
+        // rotate the quadrant so the curve inside it has the standard orientation
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - (x & (s - 1));
+                y = s - 1 - (y & (s - 1));
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    distance
+}
@@ -0,0 +1,85 @@
+use serde::Deserialize;
+
+/// how the integrator accumulates the positions of the atoms. Forces are always evaluated in
+/// single precision, the modes only differ in how the small displacements of every time step
+/// are added to positions that may be much larger.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Precision {
+    /// plain `f32` positions, every time step rounds to the precision of the position
+    #[default]
+    Single,
+    /// kahan summation: every atom carries the rounding error of its last position update,
+    /// which is added back in the next one. Positions then behave as if they had about twice
+    /// the precision, at the cost of one more vector per atom and time step.
+    ///
+    /// The carried error is kept by checkpoints and moves along with the atoms when they are
+    /// sorted. Inserted atoms start without one.
+    Compensated,
+}
+
+impl Precision {
+    pub(crate) fn gpu_id(self) -> u32 {
+        match self {
+            Precision::Single => 0,
+            Precision::Compensated => 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// one position update of `main_integrate` in `interact.wgsl` along one axis, returning
+    /// the new position and rounding error
+    fn advance(precision: Precision, position: f32, step: f32, compensation: f32) -> (f32, f32) {
+        match precision {
+            Precision::Single => (position + step, 0.0),
+            Precision::Compensated => {
+                let step = step - compensation;
+                let next = position + step;
+                (next, (next - position) - step)
+            }
+        }
+    }
+
+    /// the largest distance between a unit harmonic oscillator around `centre`, started at rest
+    /// one length unit away from it and integrated in f32 with symplectic euler like
+    /// `main_integrate`, and the same oscillator integrated in f64
+    fn deviation(precision: Precision, centre: f32, steps: usize) -> f64 {
+        let time_step = 1e-3;
+        let (mut position, mut velocity, mut compensation) = (centre + 1.0, 0.0f32, 0.0);
+        let (mut exact_position, mut exact_velocity) = (1.0f64, 0.0f64);
+        let mut deviation = 0.0f64;
+        for _ in 0..steps {
+            velocity -= (position - centre) * time_step;
+            (position, compensation) =
+                advance(precision, position, velocity * time_step, compensation);
+            exact_velocity -= exact_position * time_step as f64;
+            exact_position += exact_velocity * time_step as f64;
+            deviation = deviation.max((position as f64 - centre as f64 - exact_position).abs());
+        }
+        deviation
+    }
+
+    #[test]
+    fn compensated_positions_do_not_drift() {
+        // an ulp of 1000 is 6.1e-5, which the displacements of a time step are not much larger
+        // than, so every single precision step rounds noticeably and the errors add up
+        assert!(deviation(Precision::Single, 1000.0, 100_000) > 0.1);
+        assert!(deviation(Precision::Compensated, 1000.0, 100_000) < 1e-4);
+    }
+
+    #[test]
+    fn compensated_steps_below_half_an_ulp_add_up() {
+        let (mut single, mut compensated, mut compensation) = (1000.0f32, 1000.0f32, 0.0);
+        for _ in 0..10_000 {
+            single = advance(Precision::Single, single, 1e-5, 0.0).0;
+            (compensated, compensation) =
+                advance(Precision::Compensated, compensated, 1e-5, compensation);
+        }
+        assert_eq!(single, 1000.0);
+        assert!((compensated as f64 - 1000.1).abs() < 1e-4);
+    }
+}
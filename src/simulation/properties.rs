@@ -0,0 +1,141 @@
+use crate::simulation::{Atom, Species};
+use std::mem::size_of;
+
+/// the per-atom properties besides the dynamic state in [`Atom`], as one array per property so
+/// that a pass only reads the properties it needs. Entry `i` of every array belongs to the atom
+/// in slot `i` of the atom buffers, so the arrays are permuted along with the atoms.
+///
+/// The species stays part of [`Atom`], as every force evaluation reads it together with the
+/// position.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Properties {
+    /// the stable id of each atom, assigned in order of creation and never reused
+    pub ids: Vec<u32>,
+    pub charges: Vec<f32>,
+    /// the mass the integrator uses, which defaults to the mass of the species
+    pub masses: Vec<f32>,
+    /// a bit mask of the groups each atom belongs to
+    pub groups: Vec<u32>,
+    /// bits the simulation never interprets, they are only carried along to the outputs
+    pub flags: Vec<u32>,
+}
+
+/// the properties of a single atom, see [`Properties`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AtomProperties {
+    pub id: u32,
+    pub charge: f32,
+    pub mass: f32,
+    pub groups: u32,
+    pub flags: u32,
+}
+
+impl AtomProperties {
+    /// the default properties of an atom of `species`, which belongs to no group
+    pub fn of_species(id: u32, species: &Species) -> Self {
+        Self {
+            id,
+            charge: species.charge,
+            mass: species.mass,
+            groups: 0,
+            flags: 0,
+        }
+    }
+}
+
+impl Properties {
+    /// the amount of property arrays
+    pub const COUNT: usize = 5;
+
+    /// the size of every property in bytes, so that each array can be stored in a buffer of
+    /// its own with the same layout
+    pub const SIZE: usize = 4;
+
+    /// the index of the charges among [`Properties::columns`]
+    pub const CHARGE_COLUMN: usize = 1;
+
+    /// the index of the masses among [`Properties::columns`]
+    pub const MASS_COLUMN: usize = 2;
+
+    /// the default properties of the given atoms, with ids counting up from `first_id`
+    pub fn new(atoms: &[Atom], species: &[Species], first_id: u32) -> Self {
+        atoms
+            .iter()
+            .zip(first_id..)
+            .map(|(atom, id)| AtomProperties::of_species(id, &species[atom.species() as usize]))
+            .collect()
+    }
+
+    /// the properties of the atom in slot `index`
+    pub fn get(&self, index: usize) -> AtomProperties {
+        AtomProperties {
+            id: self.ids[index],
+            charge: self.charges[index],
+            mass: self.masses[index],
+            groups: self.groups[index],
+            flags: self.flags[index],
+        }
+    }
+
+    pub fn set(&mut self, index: usize, properties: AtomProperties) {
+        self.ids[index] = properties.id;
+        self.charges[index] = properties.charge;
+        self.masses[index] = properties.mass;
+        self.groups[index] = properties.groups;
+        self.flags[index] = properties.flags;
+    }
+
+    pub fn push(&mut self, properties: AtomProperties) {
+        self.ids.push(properties.id);
+        self.charges.push(properties.charge);
+        self.masses.push(properties.mass);
+        self.groups.push(properties.groups);
+        self.flags.push(properties.flags);
+    }
+
+    /// the properties of the atoms in the given order of their slots
+    pub fn permuted(&self, order: &[usize]) -> Self {
+        order.iter().map(|&index| self.get(index)).collect()
+    }
+
+    /// the raw contents of every property array, in the order of the fields
+    pub fn columns(&self) -> [&[u8]; Self::COUNT] {
+        [
+            bytemuck::cast_slice(&self.ids),
+            bytemuck::cast_slice(&self.charges),
+            bytemuck::cast_slice(&self.masses),
+            bytemuck::cast_slice(&self.groups),
+            bytemuck::cast_slice(&self.flags),
+        ]
+    }
+
+    /// the properties of `count` atoms from the raw contents of the property arrays, which may
+    /// be longer than needed
+    pub fn from_columns(count: usize, columns: [&[u8]; Self::COUNT]) -> Self {
+        // the columns are not necessarily aligned, so they are copied instead of cast
+        fn column<T: bytemuck::Pod>(count: usize, bytes: &[u8]) -> Vec<T> {
+            let mut values = vec![T::zeroed(); count];
+            bytemuck::cast_slice_mut(&mut values).copy_from_slice(&bytes[..count * size_of::<T>()]);
+            values
+        }
+
+        let [ids, charges, masses, groups, flags] = columns;
+        Self {
+            ids: column(count, ids),
+            charges: column(count, charges),
+            masses: column(count, masses),
+            groups: column(count, groups),
+            flags: column(count, flags),
+        }
+    }
+}
+
+impl FromIterator<AtomProperties> for Properties {
+    fn from_iter<I: IntoIterator<Item = AtomProperties>>(iter: I) -> Self {
+        let mut properties = Self::default();
+        for atom in iter {
+            properties.push(atom);
+        }
+        properties
+    }
+}
@@ -0,0 +1,222 @@
+use crate::simulation::hashgrid::HashGrid;
+use crate::simulation::layout::AtomLayout;
+use crate::simulation::properties::{AtomProperties, Properties};
+use crate::simulation::Atom;
+use eyre::{eyre, Result};
+use std::mem::size_of;
+use std::sync::Arc;
+use tokio::sync::oneshot::{self, error::TryRecvError};
+use wgpu::{
+    Buffer, BufferAddress, BufferAsyncError, BufferDescriptor, BufferUsages, CommandEncoder,
+    CommandEncoderDescriptor, Device, Maintain, MapMode, Queue,
+};
+
+/// the atoms of a hash grid and their properties at one step, ordered by their ids
+pub struct Frame {
+    pub step: u64,
+    pub atoms: Vec<Atom>,
+    pub properties: Properties,
+}
+
+impl Frame {
+    /// the atom with the given id and its properties, if it exists at this step
+    pub fn get(&self, id: u32) -> Option<(Atom, AtomProperties)> {
+        let index = self.properties.ids.binary_search(&id).ok()?;
+        Some((self.atoms[index], self.properties.get(index)))
+    }
+
+    /// the 64 bit fnv-1a hash of the raw bytes of the atoms and their properties, which tells
+    /// whether two runs produced bitwise identical frames
+    pub fn hash(&self) -> u64 {
+        std::iter::once(bytemuck::cast_slice::<_, u8>(&self.atoms))
+            .chain(self.properties.columns())
+            .flatten()
+            .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+            })
+    }
+}
+
+/// a staging buffer the current atom buffer of a [`HashGrid`] can be copied into and mapped
+/// from, so that the atoms can be inspected on the cpu while the simulation keeps running.
+///
+/// A readback is started by recording [`AtomReadback::copy`] into a command encoder and calling
+/// [`AtomReadback::map`] once that encoder was submitted. The atoms can then be collected with
+/// [`AtomReadback::try_take`] whenever the device has been polled, or awaited with
+/// [`AtomReadback::take`].
+pub struct AtomReadback {
+    staging_buffer: Buffer,
+    /// the step, the amount and the layout of the copied atoms, set between `copy` and `take`
+    copied: Option<(u64, usize, AtomLayout)>,
+    mapped: Option<oneshot::Receiver<Result<(), BufferAsyncError>>>,
+}
+
+impl AtomReadback {
+    pub fn new(device: &Device, hash_grid: &HashGrid) -> Self {
+        Self {
+            staging_buffer: create_staging_buffer(device, readback_size(hash_grid)),
+            copied: None,
+            mapped: None,
+        }
+    }
+
+    /// whether a copy was started that has not been taken yet
+    pub fn is_pending(&self) -> bool {
+        self.copied.is_some()
+    }
+
+    /// records a copy of the current atoms of the hash grid into the staging buffer, which is
+    /// replaced if the hash grid has grown past it. Must not be called while a readback is
+    /// pending.
+    pub fn copy(
+        &mut self,
+        device: &Device,
+        command_encoder: &mut CommandEncoder,
+        hash_grid: &HashGrid,
+    ) {
+        assert!(!self.is_pending(), "atom readback is already in use");
+
+        if self.staging_buffer.size() < readback_size(hash_grid) {
+            self.staging_buffer = create_staging_buffer(device, readback_size(hash_grid));
+        }
+        hash_grid.copy_atoms(command_encoder, &self.staging_buffer);
+        self.copied = Some((
+            hash_grid.step(),
+            hash_grid.atom_count() as usize,
+            hash_grid.options().layout,
+        ));
+    }
+
+    /// starts mapping the staging buffer, the command encoder the copy was recorded into must
+    /// have been submitted already.
+    pub fn map(&mut self) {
+        let (sender, receiver) = oneshot::channel();
+        self.staging_buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        self.mapped = Some(receiver);
+    }
+
+    /// the copied atoms if mapping has finished. Mapping only progresses while the device is
+    /// polled.
+    pub fn try_take(&mut self) -> Result<Option<Frame>> {
+        let Some(receiver) = &mut self.mapped else {
+            return Ok(None);
+        };
+
+        match receiver.try_recv() {
+            Ok(result) => {
+                result?;
+                Ok(Some(self.collect()))
+            }
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Closed) => Err(eyre!("atom readback was dropped")),
+        }
+    }
+
+    /// waits until mapping has finished. The device is polled on a blocking thread so the
+    /// calling task is free in the meantime.
+    pub async fn take(&mut self, device: &Arc<Device>) -> Result<Frame> {
+        let receiver = self
+            .mapped
+            .as_mut()
+            .ok_or_else(|| eyre!("atom readback was not mapped"))?;
+
+        tokio::task::spawn_blocking({
+            let device = device.clone();
+            move || device.poll(Maintain::Wait)
+        })
+        .await?;
+        receiver.await??;
+
+        Ok(self.collect())
+    }
+
+    fn collect(&mut self) -> Frame {
+        let (step, count, layout) = self.copied.take().unwrap();
+        let (atoms, properties) = {
+            let bytes = self.staging_buffer.slice(..).get_mapped_range();
+            let (atoms, properties) = bytes.split_at(count * size_of::<Atom>());
+            // the atoms were copied as if the staging buffer had room for exactly `count` atoms
+            let atoms = layout.decode(atoms, count, count as u32);
+            let column_size = count * Properties::SIZE;
+            let columns = std::array::from_fn(|index| &properties[index * column_size..]);
+            (atoms, Properties::from_columns(count, columns))
+        };
+        self.staging_buffer.unmap();
+        self.mapped = None;
+
+        let mut order = (0..count).collect::<Vec<_>>();
+        order.sort_unstable_by_key(|&index| properties.ids[index]);
+        Frame {
+            step,
+            atoms: order.iter().map(|&index| atoms[index]).collect(),
+            properties: properties.permuted(&order),
+        }
+    }
+}
+
+/// the size of the atoms and properties [`HashGrid::copy_atoms`] copies
+fn readback_size(hash_grid: &HashGrid) -> BufferAddress {
+    hash_grid.atom_buffer_size()
+        + Properties::COUNT as BufferAddress * hash_grid.property_buffer_size()
+}
+
+fn create_staging_buffer(device: &Device, size: BufferAddress) -> Buffer {
+    device.create_buffer(&BufferDescriptor {
+        label: Some("Atom Staging Buffer"),
+        // mapping an empty buffer is not allowed
+        size: size.max(size_of::<Atom>() as BufferAddress),
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// copies the entire `source` buffer, which needs `COPY_SRC` usage, to the cpu
+pub async fn read_buffer(device: &Arc<Device>, queue: &Queue, source: &Buffer) -> Result<Vec<u8>> {
+    let staging_buffer = device.create_buffer(&BufferDescriptor {
+        label: Some("Readback Staging Buffer"),
+        size: source.size(),
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut command_encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("Buffer Readback"),
+    });
+    command_encoder.copy_buffer_to_buffer(source, 0, &staging_buffer, 0, source.size());
+    queue.submit(Some(command_encoder.finish()));
+
+    let (sender, receiver) = oneshot::channel();
+    staging_buffer
+        .slice(..)
+        .map_async(MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+    tokio::task::spawn_blocking({
+        let device = device.clone();
+        move || device.poll(Maintain::Wait)
+    })
+    .await?;
+    receiver.await??;
+
+    let bytes = staging_buffer.slice(..).get_mapped_range().to_vec();
+    staging_buffer.unmap();
+    Ok(bytes)
+}
+
+/// the first `count` values of the `source` buffer, see [`read_buffer`]
+pub async fn read_values<T: bytemuck::Pod>(
+    device: &Arc<Device>,
+    queue: &Queue,
+    source: &Buffer,
+    count: usize,
+) -> Result<Vec<T>> {
+    let bytes = read_buffer(device, queue, source).await?;
+    // the bytes are not necessarily aligned, so they are copied instead of cast
+    let mut values = vec![T::zeroed(); count];
+    bytemuck::cast_slice_mut(&mut values).copy_from_slice(&bytes[..count * size_of::<T>()]);
+    Ok(values)
+}
@@ -0,0 +1,74 @@
+// the atom buffers as an array of structs, see `AtomLayout` in `layout.rs`. The rest of the
+// shader only accesses the atoms through the functions below.
+
+struct Atom {
+    pos_x: f32,
+    pos_y: f32,
+    vel_x: f32,
+    vel_y: f32,
+    force_x: f32,
+    force_y: f32,
+    visual: f32,
+    species: u32,
+}
+
+@group(0) @binding(0) var<storage, read_write> atoms_curr: array<Atom>;
+@group(0) @binding(1) var<storage, read_write> atoms_last: array<Atom>;
+
+fn position_last(index: u32) -> vec2<f32> {
+    return vec2<f32>(atoms_last[index].pos_x, atoms_last[index].pos_y);
+}
+
+fn set_position_last(index: u32, pos: vec2<f32>) {
+    atoms_last[index].pos_x = pos.x;
+    atoms_last[index].pos_y = pos.y;
+}
+
+fn velocity_last(index: u32) -> vec2<f32> {
+    return vec2<f32>(atoms_last[index].vel_x, atoms_last[index].vel_y);
+}
+
+fn species_last(index: u32) -> u32 {
+    return atoms_last[index].species;
+}
+
+fn clear_force_last(index: u32) {
+    atoms_last[index].force_x = 0.0;
+    atoms_last[index].force_y = 0.0;
+}
+
+fn position_curr(index: u32) -> vec2<f32> {
+    return vec2<f32>(atoms_curr[index].pos_x, atoms_curr[index].pos_y);
+}
+
+fn set_position_curr(index: u32, pos: vec2<f32>) {
+    atoms_curr[index].pos_x = pos.x;
+    atoms_curr[index].pos_y = pos.y;
+}
+
+fn velocity_curr(index: u32) -> vec2<f32> {
+    return vec2<f32>(atoms_curr[index].vel_x, atoms_curr[index].vel_y);
+}
+
+fn set_velocity_curr(index: u32, vel: vec2<f32>) {
+    atoms_curr[index].vel_x = vel.x;
+    atoms_curr[index].vel_y = vel.y;
+}
+
+fn force_curr(index: u32) -> vec2<f32> {
+    return vec2<f32>(atoms_curr[index].force_x, atoms_curr[index].force_y);
+}
+
+fn add_force_curr(index: u32, force: vec2<f32>) {
+    atoms_curr[index].force_x += force.x;
+    atoms_curr[index].force_y += force.y;
+}
+
+fn visual_curr(index: u32) -> f32 {
+    return atoms_curr[index].visual;
+}
+
+fn set_visual_curr(index: u32, visual: f32) {
+    atoms_curr[index].visual = visual;
+}
+
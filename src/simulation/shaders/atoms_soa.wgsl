@@ -0,0 +1,75 @@
+// the atom buffers as a struct of arrays, see `AtomLayout` in `layout.rs`. Each buffer holds
+// the positions, the velocities, the forces and the visuals together with the species of all
+// atoms, in regions of `capacity()` elements each. The rest of the shader only accesses the
+// atoms through the functions below.
+
+@group(0) @binding(0) var<storage, read_write> atoms_curr: array<vec2<f32>>;
+@group(0) @binding(1) var<storage, read_write> atoms_last: array<vec2<f32>>;
+
+let FIELD_POSITION = 0u;
+let FIELD_VELOCITY = 1u;
+let FIELD_FORCE = 2u;
+// the visual in x and the bits of the species in y
+let FIELD_VISUAL_SPECIES = 3u;
+
+// the amount of atoms the buffers have room for, both buffers have the same size
+fn capacity() -> u32 {
+    return arrayLength(&atoms_curr) / 4u;
+}
+
+fn field(field: u32, index: u32) -> u32 {
+    return field * capacity() + index;
+}
+
+fn position_last(index: u32) -> vec2<f32> {
+    return atoms_last[field(FIELD_POSITION, index)];
+}
+
+fn set_position_last(index: u32, pos: vec2<f32>) {
+    atoms_last[field(FIELD_POSITION, index)] = pos;
+}
+
+fn velocity_last(index: u32) -> vec2<f32> {
+    return atoms_last[field(FIELD_VELOCITY, index)];
+}
+
+fn species_last(index: u32) -> u32 {
+    return bitcast<u32>(atoms_last[field(FIELD_VISUAL_SPECIES, index)].y);
+}
+
+fn clear_force_last(index: u32) {
+    atoms_last[field(FIELD_FORCE, index)] = vec2<f32>(0.0, 0.0);
+}
+
+fn position_curr(index: u32) -> vec2<f32> {
+    return atoms_curr[field(FIELD_POSITION, index)];
+}
+
+fn set_position_curr(index: u32, pos: vec2<f32>) {
+    atoms_curr[field(FIELD_POSITION, index)] = pos;
+}
+
+fn velocity_curr(index: u32) -> vec2<f32> {
+    return atoms_curr[field(FIELD_VELOCITY, index)];
+}
+
+fn set_velocity_curr(index: u32, vel: vec2<f32>) {
+    atoms_curr[field(FIELD_VELOCITY, index)] = vel;
+}
+
+fn force_curr(index: u32) -> vec2<f32> {
+    return atoms_curr[field(FIELD_FORCE, index)];
+}
+
+fn add_force_curr(index: u32, force: vec2<f32>) {
+    atoms_curr[field(FIELD_FORCE, index)] += force;
+}
+
+fn visual_curr(index: u32) -> f32 {
+    return atoms_curr[field(FIELD_VISUAL_SPECIES, index)].x;
+}
+
+fn set_visual_curr(index: u32, visual: f32) {
+    atoms_curr[field(FIELD_VISUAL_SPECIES, index)].x = visual;
+}
+
@@ -1,109 +1,1386 @@
-struct Atom {
-    pos_x: f32,
-    pos_y: f32,
-    vel_x: f32,
-    vel_y: f32,
-    force_x: f32,
-    force_y: f32,
-    visual: f32,
-}
-
 struct Cell {
-    count: i32,
+    // incremented concurrently while binning, may exceed the length of `indices`
+    count: atomic<i32>,
     indices: array<i32, 16>
 }
 
 struct PushConstants {
-    cells_per_side: i32
+    cells_per_side: i32,
+    step: u32,
+    atom_count: u32,
+    // shift of the cell grid, only used by monte carlo sweeps
+    offset_x: f32,
+    offset_y: f32,
+    // the checkerboard color of the cells a monte carlo pass updates
+    color: u32,
+}
+
+struct Parameters {
+    grid_side_length: f32,
+    time_step: f32,
+    boundary_x: u32,
+    boundary_y: u32,
+    species_count: u32,
+    thermostat: u32,
+    temperature: f32,
+    damping: f32,
+    minimize: u32,
+    seed: u32,
+    cell_side_length: f32,
+    // the skin of the neighbour lists, 0 if the cells are scanned instead
+    skin: f32,
+    position_precision: u32,
+    // whether there are bond types, otherwise the bond buffer is not used
+    bonded: u32,
+    // whether there are angle types, otherwise the angle buffer is not used
+    has_angles: u32,
+    constraint_tolerance: f32,
+    constraint_iterations: u32,
+    // whether the charges interact, see `coulomb`
+    electrostatics: u32,
+    ewald_alpha: f32,
+    coulomb_cutoff_sq: f32,
+    // the side of the particle mesh and the order of its b-splines, 0 without a mesh
+    mesh_size: u32,
+    mesh_order: u32,
+    // the external forces, see `external_force`
+    gravity_x: f32,
+    gravity_y: f32,
+    electric_field_x: f32,
+    electric_field_y: f32,
+    trap_count: u32,
+    // the amount of samples of the force field along x and y, 0 without a force field
+    force_field_width: u32,
+    force_field_height: u32,
+}
+
+struct PairPotential {
+    epsilon: f32,
+    sigma: f32,
+    cutoff_sq: f32,
+}
+
+struct BondType {
+    kind: u32,
+    k: f32,
+    // the rest length of harmonic bonds or the maximum length of fene bonds
+    length: f32,
+}
+
+// the bonds of one atom, unused entries have the partner NO_PARTNER
+struct Bonds {
+    partners: array<u32, 4>,
+    kinds: array<u32, 4>,
 }
 
-@group(0) @binding(0) var<storage, read_write> atoms_curr: array<Atom>;
-@group(0) @binding(1) var<storage, read> atoms_last: array<Atom>;
+struct AngleType {
+    kind: u32,
+    k: f32,
+    // the rest angle in radians
+    angle: f32,
+}
+
+// the angles one atom takes part in, each given by the indices of its two ends in x and z, the
+// index of its center in y and its type in w. Unused entries have x set to NO_PARTNER.
+struct Angles {
+    entries: array<vec4<u32>, 8>,
+}
+
+// the atom buffers at bindings 0 and 1 are declared by the layout specific part of the shader
 @group(0) @binding(2) var<storage, read_write> cells: array<Cell>;
+@group(0) @binding(3) var<uniform> parameters: Parameters;
+// the mass of each atom, indexed like the atom buffers
+@group(0) @binding(4) var<storage, read> masses: array<f32>;
+@group(0) @binding(5) var<storage, read> pair_potentials: array<PairPotential>;
+// the rounding error of the last position update of every atom, only used with compensated
+// precision. It is indexed like the atoms but not ping-ponged, as only the integration pass of
+// the atom itself reads and writes it.
+@group(0) @binding(6) var<storage, read_write> position_compensation: array<vec2<f32>>;
+// indexed like the atoms, the partners are given by their indices as well
+@group(0) @binding(7) var<storage, read> bonds: array<Bonds>;
+@group(0) @binding(8) var<storage, read> bond_types: array<BondType>;
+@group(0) @binding(9) var<storage, read> angles: array<Angles>;
+@group(0) @binding(10) var<storage, read> angle_types: array<AngleType>;
+// the charge of each atom, indexed like the atom buffers
+@group(0) @binding(11) var<storage, read> charges: array<f32>;
+
+// an atom of `species` at the given position whose potential energy is evaluated, ignoring the
+// atom at index `exclude` if it is not negative
+struct Probe {
+    pos_x: f32,
+    pos_y: f32,
+    species: u32,
+    exclude: i32,
+}
+
+@group(1) @binding(0) var<storage, read> probes: array<Probe>;
+@group(1) @binding(1) var<storage, read_write> probe_energies: array<f32>;
+
+// the settings and acceptance statistics of monte carlo sweeps
+struct MonteCarlo {
+    temperature: f32,
+    max_displacement: f32,
+    // trial moves per atom and sweep
+    moves: u32,
+    attempted: atomic<u32>,
+    accepted: atomic<u32>,
+}
+
+@group(1) @binding(2) var<storage, read_write> monte_carlo: MonteCarlo;
+
+// the atoms within the cutoff plus the skin of an atom when its list was last built
+struct Neighbours {
+    count: u32,
+    indices: array<u32, 64>,
+}
+
+@group(1) @binding(3) var<storage, read_write> neighbours: array<Neighbours>;
+// the positions of the atoms when the neighbour lists were last built
+@group(1) @binding(4) var<storage, read_write> reference_positions: array<vec2<f32>>;
+struct NeighbourFlags {
+    // set if any atom moved far enough for the lists to be rebuilt in this time step
+    rebuild: atomic<u32>,
+    // the amount of lists that were cut off at `MAX_NEIGHBOURS`, which is never reset
+    overflows: atomic<u32>,
+}
+
+@group(1) @binding(5) var<storage, read_write> neighbour_flags: NeighbourFlags;
+
+// a bond held at a fixed length, between the atoms at indices a and b
+struct Constraint {
+    a: u32,
+    b: u32,
+    length: f32,
+}
+
+// the constraints grouped by molecule, i.e. by the sets of atoms connected through
+// constraints, and the range of constraints of every molecule. Empty ranges are unused.
+@group(1) @binding(6) var<storage, read> constraints: array<Constraint>;
+@group(1) @binding(7) var<storage, read> molecules: array<vec2<u32>>;
+
+// a wave vector of the reciprocal space part of the ewald sum, whose energy is `coefficient`
+// times the squared magnitude of its structure factor
+struct WaveVector {
+    k: vec2<f32>,
+    coefficient: f32,
+}
+
+@group(1) @binding(8) var<storage, read> wave_vectors: array<WaveVector>;
+// the structure factor of every wave vector, i.e. the sum of `q exp(i k r)` over all atoms
+@group(1) @binding(9) var<storage, read_write> structure_factors: array<vec2<f32>>;
+
+// the periodic particle mesh in row-major order. It holds the charges spread onto it, then
+// their fourier transform, and finally the potential of the reciprocal space sum.
+@group(1) @binding(10) var<storage, read_write> mesh: array<vec2<f32>>;
+// the energy of every wave vector of the mesh per squared fourier coefficient, indexed like it
+@group(1) @binding(11) var<storage, read> influence: array<f32>;
+
+struct Trap {
+    center: vec2<f32>,
+    stiffness: f32,
+    // the species the trap holds, or ALL_SPECIES
+    species: u32,
+}
+
+// the first `trap_count` entries are the harmonic traps
+@group(1) @binding(12) var<storage, read> traps: array<Trap>;
+// the samples of the force field in row-major order, from the bottom row to the top one
+@group(1) @binding(13) var<storage, read> force_field: array<vec2<f32>>;
+
+// the coulomb energy of every atom, whose sum is the coulomb energy of the system
+@group(2) @binding(0) var<storage, read_write> coulomb_energies: array<f32>;
 
 var<push_constant> push_constants: PushConstants;
 
-fn lennard_jones(dist_sq: f32) -> f32 {
-    let desired_radius = 1.0;
-    let sigma_fac = 1.122462048309373; // 6th root of 2, the factor of the root relative to sigma
-    let sigma = desired_radius / sigma_fac;
-    let sigma_6 = sigma * sigma * sigma * sigma * sigma * sigma;
-    let epsilon = 0.25 * 3.0;
+let BOUNDARY_OPEN = 0u;
+let BOUNDARY_PERIODIC = 1u;
+let BOUNDARY_REFLECTIVE = 2u;
+
+let THERMOSTAT_LANGEVIN = 1u;
+
+let PRECISION_COMPENSATED = 1u;
+
+let MAX_NEIGHBOURS = 64u;
+
+let BOND_FENE = 1u;
+let BOND_CONSTRAINT = 2u;
+let MAX_BONDS = 4u;
+let NO_PARTNER = 0xffffffffu;
+
+let ANGLE_COSINE = 1u;
+let MAX_ANGLES = 8u;
+
+let FRAC_2_SQRT_PI = 1.1283791670955126;
+let PI = 3.141592653589793;
 
+let MAX_MESH = 512u;
+let MAX_MESH_ORDER = 8u;
+
+let ALL_SPECIES = 0xffffffffu;
+
+fn lennard_jones(dist_sq: f32, pair: PairPotential) -> f32 {
+    if (dist_sq > pair.cutoff_sq) {
+        return 0.0;
+    }
+
+    let sigma_6 = pair.sigma * pair.sigma * pair.sigma * pair.sigma * pair.sigma * pair.sigma;
+    let epsilon = pair.epsilon;
 
     return max(-1e7, (24.0 * epsilon * sigma_6 * (dist_sq * dist_sq * dist_sq - 2.0 * sigma_6)) / (dist_sq * dist_sq * dist_sq * dist_sq * dist_sq * dist_sq * dist_sq));
 }
 
+fn lennard_jones_energy(dist_sq: f32, pair: PairPotential) -> f32 {
+    if (dist_sq > pair.cutoff_sq) {
+        return 0.0;
+    }
+
+    let s2 = pair.sigma * pair.sigma / dist_sq;
+    let s6 = s2 * s2 * s2;
+    return 4.0 * pair.epsilon * (s6 * s6 - s6);
+}
+
+// the complementary error function for non-negative arguments, with an absolute error below
+// 1.5e-7 (Abramowitz and Stegun 7.1.26)
+fn erfc(x: f32) -> f32 {
+    let t = 1.0 / (1.0 + 0.3275911 * x);
+    let polynomial = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    return polynomial * exp(-x * x);
+}
+
+// like `lennard_jones`, the real space part of the ewald sum of two atoms whose charges have
+// the given product, as the derivative divided by the distance
+fn coulomb(dist_sq: f32, charge_product: f32) -> f32 {
+    if (dist_sq > parameters.coulomb_cutoff_sq) {
+        return 0.0;
+    }
+
+    let alpha = parameters.ewald_alpha;
+    let dist = sqrt(dist_sq);
+    return -charge_product * (erfc(alpha * dist) / dist + FRAC_2_SQRT_PI * alpha * exp(-alpha * alpha * dist_sq)) / dist_sq;
+}
+
+fn coulomb_energy(dist_sq: f32, charge_product: f32) -> f32 {
+    if (dist_sq > parameters.coulomb_cutoff_sq) {
+        return 0.0;
+    }
+
+    let dist = sqrt(dist_sq);
+    return charge_product * erfc(parameters.ewald_alpha * dist) / dist;
+}
+
+// whether the atoms at the given indices are bonded, bonded atoms do not interact through the
+// pair potentials
+fn bonded(index: u32, other_index: u32) -> bool {
+    if (parameters.bonded == 0u) {
+        return false;
+    }
+    for (var i = 0u; i < MAX_BONDS; i++) {
+        if (bonds[index].partners[i] == other_index) {
+            return true;
+        }
+    }
+    return false;
+}
+
+// like `lennard_jones`, the derivative of the bond potential divided by the distance
+fn bond_force(dist_sq: f32, bond: BondType) -> f32 {
+    if (bond.kind == BOND_CONSTRAINT) {
+        return 0.0;
+    }
+    if (bond.kind == BOND_FENE) {
+        // stretching beyond the maximum length is only possible with too large time steps
+        let stretch = min(0.99, dist_sq / (bond.length * bond.length));
+        return bond.k / (1.0 - stretch);
+    }
+    let dist = max(1e-6, sqrt(dist_sq));
+    return bond.k * (dist - bond.length) / dist;
+}
+
+fn bond_energy(dist_sq: f32, bond: BondType) -> f32 {
+    if (bond.kind == BOND_CONSTRAINT) {
+        return 0.0;
+    }
+    if (bond.kind == BOND_FENE) {
+        let stretch = min(0.99, dist_sq / (bond.length * bond.length));
+        return -0.5 * bond.k * bond.length * bond.length * log(1.0 - stretch);
+    }
+    let extension = sqrt(dist_sq) - bond.length;
+    return 0.5 * bond.k * extension * extension;
+}
+
+// the derivative of the angle potential with respect to the angle
+fn angle_derivative(theta: f32, angle: AngleType) -> f32 {
+    if (angle.kind == ANGLE_COSINE) {
+        return angle.k * sin(theta - angle.angle);
+    }
+    return angle.k * (theta - angle.angle);
+}
+
+fn angle_energy(theta: f32, angle: AngleType) -> f32 {
+    if (angle.kind == ANGLE_COSINE) {
+        return angle.k * (1.0 - cos(theta - angle.angle));
+    }
+    let deviation = theta - angle.angle;
+    return 0.5 * angle.k * deviation * deviation;
+}
+
+fn wrap_cell(id: i32, boundary: u32) -> i32 {
+    let n = push_constants.cells_per_side;
+    if (boundary == BOUNDARY_PERIODIC) {
+        return (id + n) % n;
+    }
+    return clamp(id, 0, n - 1);
+}
+
 fn hash(id: vec2<i32>) -> i32 {
-    return clamp(id.y, 0, push_constants.cells_per_side - 1) * push_constants.cells_per_side + clamp(id.x, 0, push_constants.cells_per_side - 1);
+    return wrap_cell(id.y, parameters.boundary_y) * push_constants.cells_per_side + wrap_cell(id.x, parameters.boundary_x);
+}
+
+// first neighbour cell to visit. Periodic axes always visit all three neighbours and wrap
+// them in `hash`, the others stop at the edge of the grid so no cell is visited twice.
+fn stencil_start(id: vec2<i32>) -> vec2<i32> {
+    return vec2<i32>(
+        select(max(0, id.x - 1), id.x - 1, parameters.boundary_x == BOUNDARY_PERIODIC),
+        select(max(0, id.y - 1), id.y - 1, parameters.boundary_y == BOUNDARY_PERIODIC),
+    );
 }
 
+fn stencil_end(id: vec2<i32>) -> vec2<i32> {
+    let n = push_constants.cells_per_side;
+    return vec2<i32>(
+        select(min(n - 1, id.x + 1), id.x + 1, parameters.boundary_x == BOUNDARY_PERIODIC),
+        select(min(n - 1, id.y + 1), id.y + 1, parameters.boundary_y == BOUNDARY_PERIODIC),
+    );
+}
+
+fn minimum_image(d: f32, boundary: u32) -> f32 {
+    if (boundary == BOUNDARY_PERIODIC) {
+        return d - parameters.grid_side_length * round(d / parameters.grid_side_length);
+    }
+    return d;
+}
+
+// the vector from `origin` to `end`, using the nearest periodic image along periodic axes
+fn separation(origin: vec2<f32>, end: vec2<f32>) -> vec2<f32> {
+    let raw_diff = end - origin;
+    return vec2<f32>(
+        minimum_image(raw_diff.x, parameters.boundary_x),
+        minimum_image(raw_diff.y, parameters.boundary_y),
+    );
+}
+
+// the angle between the two bonds of a center atom, given the vectors from the center to both
+// ends. atan2 stays accurate for nearly straight angles, unlike acos.
+fn angle_between(r1: vec2<f32>, r2: vec2<f32>) -> f32 {
+    return atan2(abs(r1.x * r2.y - r1.y * r2.x), dot(r1, r2));
+}
+
+// pcg hash, see "Hash Functions for GPU Rendering" (Jarzynski, Olano)
+fn pcg(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// a pair of independent standard normal numbers for the given atom in the current step
+fn gaussian2(index: u32) -> vec2<f32> {
+    let a = pcg(parameters.seed ^ pcg(push_constants.step ^ pcg(index)));
+    let b = pcg(a);
+    let u1 = (f32(a >> 8u) + 1.0) / 16777216.0;
+    let u2 = f32(b >> 8u) / 16777216.0;
+    let r = sqrt(-2.0 * log(u1));
+    let theta = 6.283185307179586 * u2;
+    return r * vec2<f32>(cos(theta), sin(theta));
+}
+
+fn apply_boundary(pos: f32, vel: f32, boundary: u32) -> vec2<f32> {
+    let l = parameters.grid_side_length;
+    if (boundary == BOUNDARY_PERIODIC) {
+        return vec2<f32>(pos - l * floor(pos / l), vel);
+    }
+    if (boundary == BOUNDARY_REFLECTIVE) {
+        if (pos < 0.0) {
+            return vec2<f32>(-pos, -vel);
+        }
+        if (pos >= l) {
+            return vec2<f32>(2.0 * l - pos, -vel);
+        }
+    }
+    return vec2<f32>(pos, vel);
+}
+
+fn cell_coordinate(pos: f32, offset: f32, boundary: u32) -> i32 {
+    let id = i32(floor((pos + offset) / parameters.cell_side_length));
+    if (boundary == BOUNDARY_PERIODIC) {
+        let n = push_constants.cells_per_side;
+        return (id % n + n) % n;
+    }
+    return clamp(id, 0, push_constants.cells_per_side - 1);
+}
+
+// the cell containing the given position, taking the grid offset into account
+fn cell_of(pos: vec2<f32>) -> vec2<i32> {
+    return vec2<i32>(
+        cell_coordinate(pos.x, push_constants.offset_x, parameters.boundary_x),
+        cell_coordinate(pos.y, push_constants.offset_y, parameters.boundary_y),
+    );
+}
+
+// the potential energy of an atom of `species` at the given position with the atoms of the
+// last atom buffer. If `exclude` is not negative, it is the index of the atom in question,
+// which is ignored along with its bonded partners, and the energy of its bonds and angles is
+// added.
+fn potential_energy(pos: vec2<f32>, atom_species: u32, exclude: i32) -> f32 {
+    let cell = cell_of(pos);
+
+    var energy = 0.0;
+    let lo = stencil_start(cell);
+    let hi = stencil_end(cell);
+    for (var y_pos = lo.y; y_pos <= hi.y; y_pos++) {
+        for (var x_pos = lo.x; x_pos <= hi.x; x_pos++) {
+            let other_cell = &cells[hash(vec2<i32>(x_pos, y_pos))];
+            let other_count = min(16, atomicLoad(&(*other_cell).count));
+
+            for (var i = 0; i < other_count; i++) {
+                let other_index = (*other_cell).indices[i];
+                if (other_index != exclude && !(exclude >= 0 && bonded(u32(exclude), u32(other_index)))) {
+                    let raw_diff = position_last(u32(other_index)) - pos;
+                    let diff = vec2<f32>(
+                        minimum_image(raw_diff.x, parameters.boundary_x),
+                        minimum_image(raw_diff.y, parameters.boundary_y),
+                    );
+
+                    let pair = pair_potentials[atom_species * parameters.species_count + species_last(u32(other_index))];
+                    energy += lennard_jones_energy(dot(diff, diff), pair);
+                }
+            }
+        }
+    }
+
+    // the bonds of an existing atom, with their partners where they are
+    if (exclude >= 0 && parameters.bonded != 0u) {
+        for (var i = 0u; i < MAX_BONDS; i++) {
+            let partner = bonds[exclude].partners[i];
+            if (partner != NO_PARTNER) {
+                let raw_diff = position_last(partner) - pos;
+                let diff = vec2<f32>(
+                    minimum_image(raw_diff.x, parameters.boundary_x),
+                    minimum_image(raw_diff.y, parameters.boundary_y),
+                );
+                energy += bond_energy(dot(diff, diff), bond_types[bonds[exclude].kinds[i]]);
+            }
+        }
+    }
+
+    // the angles of an existing atom, with the other atoms where they are
+    if (exclude >= 0 && parameters.has_angles != 0u) {
+        for (var i = 0u; i < MAX_ANGLES; i++) {
+            let entry = angles[exclude].entries[i];
+            if (entry.x != NO_PARTNER) {
+                let a = select(position_last(entry.x), pos, entry.x == u32(exclude));
+                let b = select(position_last(entry.y), pos, entry.y == u32(exclude));
+                let c = select(position_last(entry.z), pos, entry.z == u32(exclude));
+                let theta = angle_between(separation(b, a), separation(b, c));
+                energy += angle_energy(theta, angle_types[entry.w]);
+            }
+        }
+    }
+    return energy;
+}
+
+// sorts the atoms into the cells by the positions the interaction pass reads. The cell buffer
+// is cleared before, so cells only ever contain atoms that currently exist.
 @compute
-@workgroup_size(1)
-fn main_interact(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
-    let global_id = vec3<i32>(invocation_id);
+@workgroup_size(64)
+fn main_bin(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= push_constants.atom_count) {
+        return;
+    }
+
+    let cell = cell_of(position_last(index));
+    let cell_index = cell.y * push_constants.cells_per_side + cell.x;
+    let slot = atomicAdd(&cells[cell_index].count, 1);
+    if (slot < 16) {
+        cells[cell_index].indices[slot] = i32(index);
+    }
+}
 
-    let self_index = hash(global_id.xy);
-    let self_cell = &cells[self_index];
+// sorts the atoms in every cell by index, one invocation per cell, so that the order in which
+// forces and energies are summed does not depend on the order in which binning filled the cells
+@compute
+@workgroup_size(64)
+fn main_sort_cells(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let n = u32(push_constants.cells_per_side);
+    if (global_id.x >= n * n) {
+        return;
+    }
+
+    // insertion sort, the cells are short
+    let cell = &cells[global_id.x];
+    let count = min(16, atomicLoad(&(*cell).count));
+    for (var i = 1; i < count; i++) {
+        let index = (*cell).indices[i];
+        var j = i;
+        while (j > 0 && (*cell).indices[j - 1] > index) {
+            (*cell).indices[j] = (*cell).indices[j - 1];
+            j--;
+        }
+        (*cell).indices[j] = index;
+    }
+}
 
+// the potential energy of every probe with the atoms of the last atom buffer, which must have
+// been binned into the cells before
+@compute
+@workgroup_size(64)
+fn main_energy(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= arrayLength(&probes)) {
+        return;
+    }
+
+    let probe = probes[index];
+    let pos = vec2<f32>(probe.pos_x, probe.pos_y);
+    probe_energies[index] = potential_energy(pos, probe.species, probe.exclude);
+}
+
+// the next uniform random number in [0, 1) of a per-thread rng state
+fn random(state: ptr<function, u32>) -> f32 {
+    *state = pcg(*state);
+    return f32(*state >> 8u) / 16777216.0;
+}
+
+// whether `partner`, if it is a partner of the atom at `index` other than itself, lies in
+// another cell of the same checkerboard color as `cell`
+fn in_other_active_cell(partner: u32, index: u32, cell: vec2<i32>) -> bool {
+    if (partner == NO_PARTNER || partner == index) {
+        return false;
+    }
+    let partner_cell = cell_of(position_last(partner));
+    return all(partner_cell % 2 == cell % 2) && any(partner_cell != cell);
+}
+
+// whether a bond or angle partner of the atom at the given index lies in another cell of the
+// same checkerboard color as `cell`, whose atoms a monte carlo pass moves at the same time.
+// Atoms never leave their cells during a pass, so the answer does not change while it runs,
+// and it is the same before and after a move of the atom.
+fn partner_in_active_cell(index: u32, cell: vec2<i32>) -> bool {
+    if (parameters.bonded != 0u) {
+        for (var i = 0u; i < MAX_BONDS; i++) {
+            if (in_other_active_cell(bonds[index].partners[i], index, cell)) {
+                return true;
+            }
+        }
+    }
+    if (parameters.has_angles != 0u) {
+        for (var i = 0u; i < MAX_ANGLES; i++) {
+            let entry = angles[index].entries[i];
+            if (in_other_active_cell(entry.x, index, cell)
+                || in_other_active_cell(entry.y, index, cell)
+                || in_other_active_cell(entry.z, index, cell)) {
+                return true;
+            }
+        }
+    }
+    return false;
+}
+
+// metropolis displacement moves of the atoms in the cells of one checkerboard color, one
+// thread per cell. Moves that would leave the cell are rejected, so the cells of a color are
+// at least one cell apart at all times and never interact, as the cutoffs do not exceed the
+// cell size. Atoms with a bond or angle partner in another cell of the color are not moved,
+// as the partner may move at the same time. The cells must have been binned with the same
+// offset before.
+@compute
+@workgroup_size(8, 8)
+fn main_monte_carlo(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let color = vec2<i32>(i32(push_constants.color & 1u), i32(push_constants.color >> 1u));
+    let cell = vec2<i32>(global_id.xy) * 2 + color;
     let n = push_constants.cells_per_side;
-    for (var y_pos = max(0, global_id.y - 1); y_pos <= min(global_id.y + 1, n); y_pos++) {
-        for(var x_pos = max(0, global_id.x - 1); x_pos <= min(global_id.x + 1, n); x_pos++) {
-            let other_index = hash(vec2<i32>(x_pos, y_pos));
-            let other_cell = &cells[other_index];
+    if (cell.x >= n || cell.y >= n) {
+        return;
+    }
+
+    let cell_index = cell.y * n + cell.x;
+    let self_cell = &cells[cell_index];
+    let count = min(16, atomicLoad(&(*self_cell).count));
+    if (count == 0) {
+        return;
+    }
+
+    var state = pcg(parameters.seed ^ pcg(push_constants.step ^ pcg(u32(cell_index))));
+    let moves = u32(count) * monte_carlo.moves;
+    var accepted = 0u;
+    for (var i = 0u; i < moves; i++) {
+        let index = (*self_cell).indices[min(count - 1, i32(random(&state) * f32(count)))];
+        let pos = position_last(u32(index));
+        let atom_species = species_last(u32(index));
+
+        let step = (vec2<f32>(random(&state), random(&state)) * 2.0 - 1.0) * monte_carlo.max_displacement;
+        let x = apply_boundary(pos.x + step.x, 0.0, parameters.boundary_x).x;
+        let y = apply_boundary(pos.y + step.y, 0.0, parameters.boundary_y).x;
+        let new_pos = vec2<f32>(x, y);
+        let inside = all(cell_of(new_pos) == cell)
+            && (parameters.boundary_x != BOUNDARY_REFLECTIVE || new_pos.x == pos.x + step.x)
+            && (parameters.boundary_y != BOUNDARY_REFLECTIVE || new_pos.y == pos.y + step.y);
+
+        if (inside && !partner_in_active_cell(u32(index), cell)) {
+            let delta = potential_energy(new_pos, atom_species, index) - potential_energy(pos, atom_species, index);
+            if (delta <= 0.0 || random(&state) < exp(-delta / monte_carlo.temperature)) {
+                set_position_last(u32(index), new_pos);
+                accepted++;
+            }
+        }
+    }
+
+    atomicAdd(&monte_carlo.attempted, moves);
+    atomicAdd(&monte_carlo.accepted, accepted);
+}
+
+// sets `neighbour_flags.rebuild` if any atom moved more than half the skin since the lists were
+// built, as two such atoms could then have come closer than the cutoff without being listed
+@compute
+@workgroup_size(64)
+fn main_check_neighbours(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= push_constants.atom_count) {
+        return;
+    }
+
+    let raw_moved = position_last(index) - reference_positions[index];
+    let moved = vec2<f32>(
+        minimum_image(raw_moved.x, parameters.boundary_x),
+        minimum_image(raw_moved.y, parameters.boundary_y),
+    );
+    let half_skin = 0.5 * parameters.skin;
+    if (dot(moved, moved) > half_skin * half_skin) {
+        atomicStore(&neighbour_flags.rebuild, 1u);
+    }
+}
+
+// rebuilds the neighbour list of every atom from the cells if `neighbour_flags.rebuild` is set.
+// Lists with more than `MAX_NEIGHBOURS` atoms in range are cut off and counted as overflows.
+@compute
+@workgroup_size(64)
+fn main_build_neighbours(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= push_constants.atom_count || atomicLoad(&neighbour_flags.rebuild) == 0u) {
+        return;
+    }
 
-            for (var j = 0; j < min(16, (*self_cell).count); j++) {
-                let self_index = (*self_cell).indices[j];
+    let pos = position_last(index);
+    let atom_species = species_last(index);
+    let cell = cell_of(pos);
 
-                for (var i = 0; i < min(16, (*other_cell).count); i++) {
-                    let other_index = (*other_cell).indices[i];
+    var count = 0u;
+    var overflow = false;
+    let lo = stencil_start(cell);
+    let hi = stencil_end(cell);
+    for (var y_pos = lo.y; y_pos <= hi.y; y_pos++) {
+        for (var x_pos = lo.x; x_pos <= hi.x; x_pos++) {
+            let other_cell = &cells[hash(vec2<i32>(x_pos, y_pos))];
+            let other_count = min(16, atomicLoad(&(*other_cell).count));
 
-                    if (self_index != other_index) {
-                        let self_atom = atoms_last[self_index];
-                        let self_pos = vec2<f32>(self_atom.pos_x, self_atom.pos_y);
-                        let other_atom = atoms_last[other_index];
-                        let other_pos = vec2<f32>(other_atom.pos_x, other_atom.pos_y);
+            for (var i = 0; i < other_count; i++) {
+                let other_index = (*other_cell).indices[i];
+                let raw_diff = position_last(u32(other_index)) - pos;
+                let diff = vec2<f32>(
+                    minimum_image(raw_diff.x, parameters.boundary_x),
+                    minimum_image(raw_diff.y, parameters.boundary_y),
+                );
 
-                        let diff = other_pos - self_pos;
-                        let dist_sq = dot(diff, diff);
+                // bonded atoms only interact through their charges
+                let pair = pair_potentials[atom_species * parameters.species_count + species_last(u32(other_index))];
+                let range = sqrt(max(pair.cutoff_sq, parameters.coulomb_cutoff_sq)) + parameters.skin;
+                let listed = parameters.electrostatics != 0u || !bonded(index, u32(other_index));
+                if (u32(other_index) != index && dot(diff, diff) < range * range && listed) {
+                    if (count < MAX_NEIGHBOURS) {
+                        neighbours[index].indices[count] = u32(other_index);
+                        count++;
+                    } else {
+                        overflow = true;
+                    }
+                }
+            }
+        }
+    }
+
+    if (overflow) {
+        atomicAdd(&neighbour_flags.overflows, 1u);
+    }
+    neighbours[index].count = count;
+    reference_positions[index] = pos;
+}
+
+// the forces from the neighbour lists, one invocation per atom. The lists hold both atoms of
+// every pair, so each invocation only writes the force of its own atom.
+@compute
+@workgroup_size(64)
+fn main_interact_neighbours(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= push_constants.atom_count) {
+        return;
+    }
+
+    let pos = position_last(index);
+    let atom_species = species_last(index);
+    let list = &neighbours[index];
 
-                        let force = diff * lennard_jones(dist_sq);
+    var force = vec2<f32>(0.0, 0.0);
+    for (var i = 0u; i < (*list).count; i++) {
+        let other_index = (*list).indices[i];
+        let raw_diff = position_last(other_index) - pos;
+        let diff = vec2<f32>(
+            minimum_image(raw_diff.x, parameters.boundary_x),
+            minimum_image(raw_diff.y, parameters.boundary_y),
+        );
 
-                        // interact
-                        atoms_curr[self_index].force_x += force.x;
-                        atoms_curr[self_index].force_y += force.y;
-                        atoms_curr[other_index].force_x -= force.x;
-                        atoms_curr[other_index].force_y -= force.y;
+        // with electrostatics, the lists contain the bonded atoms as well
+        if (parameters.electrostatics == 0u || !bonded(index, other_index)) {
+            let pair = pair_potentials[atom_species * parameters.species_count + species_last(other_index)];
+            force += diff * lennard_jones(dot(diff, diff), pair);
+        }
+        if (parameters.electrostatics != 0u) {
+            force += diff * coulomb(dot(diff, diff), charges[index] * charges[other_index]);
+        }
+    }
+
+    add_force_curr(index, force);
+}
+
+// the forces on an atom from the atoms in the neighbouring cells. Every pair is evaluated by
+// both of its atoms, so only the force of this atom is written and no synchronization is needed.
+fn interact_atom(index: u32) {
+    let pos = position_last(index);
+    let atom_species = species_last(index);
+    let cell = cell_of(pos);
+
+    var force = vec2<f32>(0.0, 0.0);
+    let lo = stencil_start(cell);
+    let hi = stencil_end(cell);
+    for (var y_pos = lo.y; y_pos <= hi.y; y_pos++) {
+        for (var x_pos = lo.x; x_pos <= hi.x; x_pos++) {
+            let other_cell = &cells[hash(vec2<i32>(x_pos, y_pos))];
+            let other_count = min(16, atomicLoad(&(*other_cell).count));
+
+            for (var i = 0; i < other_count; i++) {
+                let other_index = (*other_cell).indices[i];
+                if (u32(other_index) != index) {
+                    let raw_diff = position_last(u32(other_index)) - pos;
+                    let diff = vec2<f32>(
+                        minimum_image(raw_diff.x, parameters.boundary_x),
+                        minimum_image(raw_diff.y, parameters.boundary_y),
+                    );
+
+                    if (!bonded(index, u32(other_index))) {
+                        let pair = pair_potentials[atom_species * parameters.species_count + species_last(u32(other_index))];
+                        force += diff * lennard_jones(dot(diff, diff), pair);
+                    }
+                    if (parameters.electrostatics != 0u) {
+                        force += diff * coulomb(dot(diff, diff), charges[index] * charges[u32(other_index)]);
                     }
                 }
             }
         }
     }
+
+    add_force_curr(index, force);
+}
+
+// the forces on every atom from the atoms in the neighbouring cells, one invocation per atom
+@compute
+@workgroup_size(64)
+fn main_interact(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index < push_constants.atom_count) {
+        interact_atom(index);
+    }
 }
 
+// like `main_interact`, but one invocation per cell, which loops over the atoms of its cell.
+// Only kept to compare both dispatches, atoms beyond the 16th of a cell are skipped.
 @compute
 @workgroup_size(1)
+fn main_interact_cells(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let cell = &cells[hash(vec2<i32>(global_id.xy))];
+    let count = min(16, atomicLoad(&(*cell).count));
+    for (var i = 0; i < count; i++) {
+        interact_atom(u32((*cell).indices[i]));
+    }
+}
+
+var<workgroup> partial_structure_factors: array<vec2<f32>, 64>;
+
+// the structure factor of every wave vector, one workgroup per wave vector. The invocations
+// sum over every 64th atom, and the partial sums are then added up in the workgroup.
+@compute
+@workgroup_size(64)
+fn main_structure_factors(
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    let k = wave_vectors[workgroup_id.x].k;
+    var sum = vec2<f32>(0.0, 0.0);
+    for (var index = local_index; index < push_constants.atom_count; index += 64u) {
+        let phase = dot(k, position_last(index));
+        sum += charges[index] * vec2<f32>(cos(phase), sin(phase));
+    }
+    partial_structure_factors[local_index] = sum;
+    workgroupBarrier();
+
+    for (var stride = 32u; stride > 0u; stride = stride / 2u) {
+        if (local_index < stride) {
+            partial_structure_factors[local_index] += partial_structure_factors[local_index + stride];
+        }
+        workgroupBarrier();
+    }
+    if (local_index == 0u) {
+        structure_factors[workgroup_id.x] = partial_structure_factors[0];
+    }
+}
+
+// the forces of the reciprocal space part of the ewald sum, one invocation per atom, from the
+// structure factors of the same positions
+@compute
+@workgroup_size(64)
+fn main_reciprocal(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= push_constants.atom_count) {
+        return;
+    }
+
+    let pos = position_last(index);
+    var force = vec2<f32>(0.0, 0.0);
+    for (var i = 0u; i < arrayLength(&wave_vectors); i++) {
+        let wave_vector = wave_vectors[i];
+        let phase = dot(wave_vector.k, pos);
+        let factor = structure_factors[i];
+        force += 2.0 * wave_vector.coefficient * (sin(phase) * factor.x - cos(phase) * factor.y) * wave_vector.k;
+    }
+
+    add_force_curr(index, charges[index] * force);
+}
+
+// the real space part of the coulomb energy of an atom with the atoms of the last atom buffer,
+// which must have been binned into the cells, and of the charge with its own screening charge.
+// The energy of every pair is split evenly between its atoms.
+fn short_range_coulomb_energy(index: u32) -> f32 {
+    let pos = position_last(index);
+    let charge = charges[index];
+    let cell = cell_of(pos);
+
+    var energy = 0.0;
+    let lo = stencil_start(cell);
+    let hi = stencil_end(cell);
+    for (var y_pos = lo.y; y_pos <= hi.y; y_pos++) {
+        for (var x_pos = lo.x; x_pos <= hi.x; x_pos++) {
+            let other_cell = &cells[hash(vec2<i32>(x_pos, y_pos))];
+            let other_count = min(16, atomicLoad(&(*other_cell).count));
+
+            for (var i = 0; i < other_count; i++) {
+                let other_index = u32((*other_cell).indices[i]);
+                if (other_index != index) {
+                    let diff = separation(pos, position_last(other_index));
+                    energy += 0.5 * coulomb_energy(dot(diff, diff), charge * charges[other_index]);
+                }
+            }
+        }
+    }
+
+    return energy - 0.5 * FRAC_2_SQRT_PI * parameters.ewald_alpha * charge * charge;
+}
+
+// the coulomb energy of every atom of the last atom buffer, which must have been binned into
+// the cells, with the structure factors of the same positions
+@compute
+@workgroup_size(64)
+fn main_coulomb_energy(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= push_constants.atom_count) {
+        return;
+    }
+
+    let pos = position_last(index);
+    var energy = 0.0;
+    for (var i = 0u; i < arrayLength(&wave_vectors); i++) {
+        let wave_vector = wave_vectors[i];
+        let phase = dot(wave_vector.k, pos);
+        let factor = structure_factors[i];
+        energy += wave_vector.coefficient * (cos(phase) * factor.x + sin(phase) * factor.y);
+    }
+
+    coulomb_energies[index] = short_range_coulomb_energy(index) + charges[index] * energy;
+}
+
+// like `bspline_weights` in `ewald.rs`, the cardinal b-spline of the given order at `t + j` for
+// every `j` below the order, with the remaining entries left at 0
+fn bspline_weights(t: f32, order: u32) -> array<f32, 8> {
+    var weights: array<f32, 8>;
+    weights[0] = t;
+    weights[1] = 1.0 - t;
+    for (var n = 3u; n <= order; n++) {
+        for (var j = n - 1u; j < n; j--) {
+            var lower = 0.0;
+            if (j > 0u) {
+                lower = (f32(n) - t - f32(j)) * weights[j - 1u];
+            }
+            weights[j] = ((t + f32(j)) * weights[j] + lower) / f32(n - 1u);
+        }
+    }
+    return weights;
+}
+
+// the position of a charge in units of the mesh spacing
+fn mesh_coordinates(pos: vec2<f32>) -> vec2<f32> {
+    return pos * f32(parameters.mesh_size) / parameters.grid_side_length;
+}
+
+// the charge spread onto every point of the mesh, one invocation per mesh point. A charge is
+// spread onto the `mesh_order` points below it along both axes, so every point gathers the
+// charges within that many mesh spacings above it, from the cells around the center of that
+// range.
+@compute
+@workgroup_size(64)
+fn main_spread(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let size = parameters.mesh_size;
+    let index = global_id.x;
+    if (index >= size * size) {
+        return;
+    }
+
+    let order = parameters.mesh_order;
+    let mesh_point = vec2<i32>(vec2<u32>(index % size, index / size));
+    let center = (vec2<f32>(mesh_point) + 0.5 * f32(order)) * parameters.grid_side_length / f32(size);
+    let cell = cell_of(center);
+
+    var charge = 0.0;
+    let lo = stencil_start(cell);
+    let hi = stencil_end(cell);
+    for (var y_pos = lo.y; y_pos <= hi.y; y_pos++) {
+        for (var x_pos = lo.x; x_pos <= hi.x; x_pos++) {
+            let other_cell = &cells[hash(vec2<i32>(x_pos, y_pos))];
+            let other_count = min(16, atomicLoad(&(*other_cell).count));
+
+            for (var i = 0; i < other_count; i++) {
+                let other_index = u32((*other_cell).indices[i]);
+                let u = mesh_coordinates(position_last(other_index));
+                // how far this point lies below the mesh point below the charge
+                let n = i32(size);
+                let below = vec2<u32>(((vec2<i32>(floor(u)) - mesh_point) % n + n) % n);
+                if (below.x < order && below.y < order) {
+                    let t = u - floor(u);
+                    var weights_x = bspline_weights(t.x, order);
+                    var weights_y = bspline_weights(t.y, order);
+                    charge += charges[other_index] * weights_x[below.x] * weights_y[below.y];
+                }
+            }
+        }
+    }
+
+    mesh[index] = vec2<f32>(charge, 0.0);
+}
+
+var<workgroup> fft_line: array<vec2<f32>, 512>;
+
+// the discrete fourier transform with positive exponent of the `mesh_size` points of the mesh
+// from `start` on with the given stride, in place. The points are loaded into workgroup memory
+// in bit-reversed order and combined by radix-2 butterflies, with a barrier after every stage.
+fn transform_line(start: u32, stride: u32, local_index: u32) {
+    let size = parameters.mesh_size;
+    let bits = countOneBits(size - 1u);
+    for (var i = local_index; i < size; i += 64u) {
+        fft_line[reverseBits(i) >> (32u - bits)] = mesh[start + i * stride];
+    }
+    workgroupBarrier();
+
+    for (var half = 1u; half < size; half = half * 2u) {
+        for (var i = local_index; i < size / 2u; i += 64u) {
+            let j = i % half;
+            let even_index = (i / half) * 2u * half + j;
+            let angle = PI * f32(j) / f32(half);
+            let twiddle = vec2<f32>(cos(angle), sin(angle));
+            let even = fft_line[even_index];
+            let odd = fft_line[even_index + half];
+            let product = vec2<f32>(twiddle.x * odd.x - twiddle.y * odd.y, twiddle.x * odd.y + twiddle.y * odd.x);
+            fft_line[even_index] = even + product;
+            fft_line[even_index + half] = even - product;
+        }
+        workgroupBarrier();
+    }
+
+    for (var i = local_index; i < size; i += 64u) {
+        mesh[start + i * stride] = fft_line[i];
+    }
+}
+
+// the fourier transform of every row of the mesh, one workgroup per row
+@compute
+@workgroup_size(64)
+fn main_fft_rows(
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    transform_line(workgroup_id.x * parameters.mesh_size, 1u, local_index);
+}
+
+// the fourier transform of every column of the mesh, one workgroup per column
+@compute
+@workgroup_size(64)
+fn main_fft_columns(
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    transform_line(workgroup_id.x, parameters.mesh_size, local_index);
+}
+
+// multiplies the conjugated fourier transform of the spread charges with the influence
+// function, one invocation per mesh point. The influence function is real and even, so
+// transforming the product once more with the same sign gives the potential at the mesh points.
+@compute
+@workgroup_size(64)
+fn main_convolve(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= parameters.mesh_size * parameters.mesh_size) {
+        return;
+    }
+
+    let coefficient = mesh[index];
+    mesh[index] = influence[index] * vec2<f32>(coefficient.x, -coefficient.y);
+}
+
+// the potential of the mesh interpolated to the given position in z, and its gradient in x
+// and y
+fn mesh_potential(pos: vec2<f32>) -> vec3<f32> {
+    let size = i32(parameters.mesh_size);
+    let order = parameters.mesh_order;
+    let u = mesh_coordinates(pos);
+    let base = vec2<i32>(floor(u));
+    let t = u - floor(u);
+    var weights_x = bspline_weights(t.x, order);
+    var weights_y = bspline_weights(t.y, order);
+    // the derivative of a b-spline is the difference of two of the next lower order
+    var lower_x = bspline_weights(t.x, order - 1u);
+    var lower_y = bspline_weights(t.y, order - 1u);
+
+    var potential = vec3<f32>(0.0, 0.0, 0.0);
+    var previous_y = 0.0;
+    for (var j_y = 0u; j_y < order; j_y++) {
+        let y = ((base.y - i32(j_y)) % size + size) % size;
+        let slope_y = lower_y[j_y] - previous_y;
+        previous_y = lower_y[j_y];
+
+        var previous_x = 0.0;
+        for (var j_x = 0u; j_x < order; j_x++) {
+            let x = ((base.x - i32(j_x)) % size + size) % size;
+            let slope_x = lower_x[j_x] - previous_x;
+            previous_x = lower_x[j_x];
+
+            let value = mesh[y * size + x].x;
+            potential += value * vec3<f32>(slope_x * weights_y[j_y], weights_x[j_x] * slope_y, weights_x[j_x] * weights_y[j_y]);
+        }
+    }
+    let spacing = parameters.grid_side_length / f32(size);
+    return vec3<f32>(potential.xy / spacing, potential.z);
+}
+
+// the forces of the reciprocal space part of the ewald sum from the potential of the mesh, one
+// invocation per atom. The energy is quadratic in the spread charges, hence the factor 2.
+@compute
+@workgroup_size(64)
+fn main_interpolate(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= push_constants.atom_count) {
+        return;
+    }
+
+    let gradient = mesh_potential(position_last(index)).xy;
+    add_force_curr(index, -2.0 * charges[index] * gradient);
+}
+
+// like `main_coulomb_energy`, with the potential of the mesh instead of the structure factors
+@compute
+@workgroup_size(64)
+fn main_mesh_coulomb_energy(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= push_constants.atom_count) {
+        return;
+    }
+
+    let potential = mesh_potential(position_last(index)).z;
+    coulomb_energies[index] = short_range_coulomb_energy(index) + charges[index] * potential;
+}
+
+// the forces of the bonds of every atom, one invocation per atom. Both atoms of a bond list
+// each other, so each invocation only writes the force of its own atom.
+@compute
+@workgroup_size(64)
+fn main_bonds(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= push_constants.atom_count) {
+        return;
+    }
+
+    let pos = position_last(index);
+    var force = vec2<f32>(0.0, 0.0);
+    for (var i = 0u; i < MAX_BONDS; i++) {
+        let partner = bonds[index].partners[i];
+        if (partner != NO_PARTNER) {
+            let raw_diff = position_last(partner) - pos;
+            let diff = vec2<f32>(
+                minimum_image(raw_diff.x, parameters.boundary_x),
+                minimum_image(raw_diff.y, parameters.boundary_y),
+            );
+            force += diff * bond_force(dot(diff, diff), bond_types[bonds[index].kinds[i]]);
+        }
+    }
+
+    add_force_curr(index, force);
+}
+
+// the forces of the angles of every atom, one invocation per atom. Every atom of an angle
+// lists it, so each invocation evaluates the whole angle and only writes the force on its own
+// atom.
+@compute
+@workgroup_size(64)
+fn main_angles(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= push_constants.atom_count) {
+        return;
+    }
+
+    var force = vec2<f32>(0.0, 0.0);
+    for (var i = 0u; i < MAX_ANGLES; i++) {
+        let entry = angles[index].entries[i];
+        if (entry.x != NO_PARTNER) {
+            let center = position_last(entry.y);
+            let r1 = separation(center, position_last(entry.x));
+            let r2 = separation(center, position_last(entry.z));
+            let theta = angle_between(r1, r2);
+            let derivative = angle_derivative(theta, angle_types[entry.w]);
+
+            // in 2d, the angle of each bond only changes perpendicular to it. The sign of the
+            // cross product tells which way opens the angle.
+            let turn = sign(r1.x * r2.y - r1.y * r2.x);
+            let force_a = derivative * turn * vec2<f32>(-r1.y, r1.x) / dot(r1, r1);
+            let force_c = -derivative * turn * vec2<f32>(-r2.y, r2.x) / dot(r2, r2);
+            if (index == entry.x) {
+                force += force_a;
+            } else if (index == entry.z) {
+                force += force_c;
+            } else {
+                force -= force_a + force_c;
+            }
+        }
+    }
+
+    add_force_curr(index, force);
+}
+
+// the sample of the force field at the given column and row. Indices beyond the outermost
+// samples wrap around along periodic axes and are clamped to them along the others.
+fn force_field_sample(column: i32, row: i32) -> vec2<f32> {
+    let width = i32(parameters.force_field_width);
+    let height = i32(parameters.force_field_height);
+    var x = clamp(column, 0, width - 1);
+    if (parameters.boundary_x == BOUNDARY_PERIODIC) {
+        x = (column % width + width) % width;
+    }
+    var y = clamp(row, 0, height - 1);
+    if (parameters.boundary_y == BOUNDARY_PERIODIC) {
+        y = (row % height + height) % height;
+    }
+    return force_field[y * width + x];
+}
+
+// the force of the fields outside the system on the atom with the given index at the given
+// position: gravity on its mass, the electric field on its charge, the harmonic traps holding
+// its species and the force field, interpolated bilinearly between the samples at the centers
+// of the texels it divides the box into
+fn external_force(index: u32, pos: vec2<f32>) -> vec2<f32> {
+    var force = masses[index] * vec2<f32>(parameters.gravity_x, parameters.gravity_y)
+        + charges[index] * vec2<f32>(parameters.electric_field_x, parameters.electric_field_y);
+
+    let atom_species = species_last(index);
+    for (var i = 0u; i < parameters.trap_count; i++) {
+        let trap = traps[i];
+        if (trap.species == ALL_SPECIES || trap.species == atom_species) {
+            force += trap.stiffness * separation(pos, trap.center);
+        }
+    }
+
+    if (parameters.force_field_width > 0u) {
+        let size = vec2<f32>(f32(parameters.force_field_width), f32(parameters.force_field_height));
+        let texel = pos * size / parameters.grid_side_length - 0.5;
+        let lower = floor(texel);
+        let t = texel - lower;
+        let column = i32(lower.x);
+        let row = i32(lower.y);
+        let bottom = mix(force_field_sample(column, row), force_field_sample(column + 1, row), t.x);
+        let top = mix(force_field_sample(column, row + 1), force_field_sample(column + 1, row + 1), t.x);
+        force += mix(bottom, top, t.y);
+    }
+    return force;
+}
+
+// advances an atom by one time step. The external forces are added to the forces of the
+// interactions here, so they are not part of the stored forces.
+fn integrate_atom(index: u32) {
+    let time_step = parameters.time_step;
+    let mass = masses[index];
+
+    let force = force_curr(index) + external_force(index, position_last(index));
+    var vel = velocity_last(index);
+
+    if (parameters.minimize != 0u) {
+        // quick-min: only keep the velocity component along the force, and only while going downhill
+        let force_sq = dot(force, force);
+        if (dot(vel, force) > 0.0 && force_sq > 0.0) {
+            vel = force * dot(vel, force) / force_sq;
+        } else {
+            vel = vec2<f32>(0.0, 0.0);
+        }
+    }
+
+    vel += force / mass * time_step;
+
+    if (parameters.thermostat == THERMOSTAT_LANGEVIN) {
+        let gamma = parameters.damping;
+        let noise = sqrt(2.0 * gamma * parameters.temperature * time_step / mass);
+        vel = vel * (1.0 - gamma * time_step) + noise * gaussian2(index);
+    }
+
+    let last_pos = position_last(index);
+    var pos = last_pos + vel * time_step;
+    var compensation = vec2<f32>(0.0, 0.0);
+    if (parameters.position_precision == PRECISION_COMPENSATED) {
+        // kahan summation: the rounding error of the last update is taken back before adding,
+        // and the rounding error of this update is recovered from the result
+        let step = vel * time_step - position_compensation[index];
+        pos = last_pos + step;
+        compensation = (pos - last_pos) - step;
+    }
+    let x = apply_boundary(pos.x, vel.x, parameters.boundary_x);
+    let y = apply_boundary(pos.y, vel.y, parameters.boundary_y);
+
+    set_position_curr(index, vec2<f32>(x.x, y.x));
+    set_velocity_curr(index, vec2<f32>(x.y, y.y));
+    if (parameters.position_precision == PRECISION_COMPENSATED) {
+        // a reflection mirrors the position, and with it its rounding error
+        let mirrored = vec2<bool>(x.y != vel.x, y.y != vel.y);
+        position_compensation[index] = select(compensation, -compensation, mirrored);
+    }
+
+    let vis = log2(length(force) + 1.0) * 0.07;
+    let k = 0.01;
+    set_visual_curr(index, mix(visual_curr(index), vis, k));
+
+    // the forces of the current step stay in place so they can be read back, the other
+    // buffer is the one the next interaction pass accumulates into.
+    clear_force_last(index);
+}
+
+// advances every atom by one time step, one invocation per atom
+@compute
+@workgroup_size(64)
 fn main_integrate(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    let self_id = hash(vec2<i32>(global_id.xy));
-    let self_cell = &cells[self_id];
+    let index = global_id.x;
+    if (index < push_constants.atom_count) {
+        integrate_atom(index);
+    }
+}
 
-    let time_step = 1e-5;
-    let mass = 1.0;
+// like `main_integrate`, but one invocation per cell, see `main_interact_cells`
+@compute
+@workgroup_size(1)
+fn main_integrate_cells(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let cell = &cells[hash(vec2<i32>(global_id.xy))];
+    let count = min(16, atomicLoad(&(*cell).count));
+    for (var i = 0; i < count; i++) {
+        integrate_atom(u32((*cell).indices[i]));
+    }
+}
 
-    for (var i = 0; i < min(16, (*self_cell).count); i++) {
-        let index = (*self_cell).indices[i];
+// holds the constrained bonds at their lengths after the integration, one invocation per
+// molecule. SHAKE sweeps the constraints one after the other and moves both atoms of a bond
+// along the bond as it was at the start of the step, changing their velocities by the same
+// displacement. RATTLE then removes the relative velocities along the bonds. Both sweep until
+// every constraint is within the tolerance or the maximum amount of sweeps is reached.
+// `integrate_atom` is semi-implicit euler rather than velocity verlet, so there are no half
+// step velocities: the velocity a step ends with is the one that moved the atom, which is why
+// SHAKE changes it by the displacement over the time step, and RATTLE corrects it once.
+@compute
+@workgroup_size(64)
+fn main_constraints(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= arrayLength(&molecules)) {
+        return;
+    }
 
-        atoms_curr[index].vel_x = atoms_last[index].vel_x + atoms_curr[index].force_x / mass;
-        atoms_curr[index].vel_y = atoms_last[index].vel_y + atoms_curr[index].force_y / mass;
+    let range = molecules[index];
+    let time_step = parameters.time_step;
+    let tolerance = parameters.constraint_tolerance;
 
-        atoms_curr[index].pos_x = atoms_last[index].pos_x + atoms_curr[index].vel_x * time_step;
-        atoms_curr[index].pos_y = atoms_last[index].pos_y + atoms_curr[index].vel_y * time_step;
+    for (var sweep = 0u; sweep < parameters.constraint_iterations; sweep++) {
+        var converged = true;
+        for (var i = range.x; i < range.y; i++) {
+            let constraint = constraints[i];
+            let length_sq = constraint.length * constraint.length;
+            let bond = separation(position_curr(constraint.b), position_curr(constraint.a));
+            let deviation = dot(bond, bond) - length_sq;
+            // the relative deviation of the squared length is twice that of the length
+            if (abs(deviation) > 2.0 * tolerance * length_sq) {
+                converged = false;
+                let inverse_a = 1.0 / masses[constraint.a];
+                let inverse_b = 1.0 / masses[constraint.b];
+                let reference = separation(position_last(constraint.b), position_last(constraint.a));
+                let g = deviation / (2.0 * (inverse_a + inverse_b) * dot(bond, reference));
+                let shift_a = -g * inverse_a * reference;
+                let shift_b = g * inverse_b * reference;
+                set_position_curr(constraint.a, position_curr(constraint.a) + shift_a);
+                set_position_curr(constraint.b, position_curr(constraint.b) + shift_b);
+                set_velocity_curr(constraint.a, velocity_curr(constraint.a) + shift_a / time_step);
+                set_velocity_curr(constraint.b, velocity_curr(constraint.b) + shift_b / time_step);
+            }
+        }
+        if (converged) {
+            break;
+        }
+    }
 
-        let vis = log2(length(vec2<f32>(atoms_curr[index].force_x, atoms_curr[index].force_y)) + 1.0) * 0.07;
-        let k = 0.01;
-        atoms_curr[index].visual = mix(atoms_curr[index].visual, vis, k);
+    for (var sweep = 0u; sweep < parameters.constraint_iterations; sweep++) {
+        var converged = true;
+        for (var i = range.x; i < range.y; i++) {
+            let constraint = constraints[i];
+            let length_sq = constraint.length * constraint.length;
+            let bond = separation(position_curr(constraint.b), position_curr(constraint.a));
+            let relative = velocity_curr(constraint.a) - velocity_curr(constraint.b);
+            // how much the bond would change its length within a time step
+            let rate = dot(bond, relative);
+            if (abs(rate) * time_step > tolerance * length_sq) {
+                converged = false;
+                let inverse_a = 1.0 / masses[constraint.a];
+                let inverse_b = 1.0 / masses[constraint.b];
+                let k = rate / ((inverse_a + inverse_b) * dot(bond, bond));
+                set_velocity_curr(constraint.a, velocity_curr(constraint.a) - k * inverse_a * bond);
+                set_velocity_curr(constraint.b, velocity_curr(constraint.b) + k * inverse_b * bond);
+            }
+        }
+        if (converged) {
+            break;
+        }
+    }
 
-        atoms_curr[index].force_x = 0.0;
-        atoms_curr[index].force_y = 0.0;
+    // the corrections may have moved atoms across the boundaries
+    for (var i = range.x; i < range.y; i++) {
+        for (var end = 0u; end < 2u; end++) {
+            let atom = select(constraints[i].a, constraints[i].b, end == 1u);
+            let pos = position_curr(atom);
+            let vel = velocity_curr(atom);
+            let x = apply_boundary(pos.x, vel.x, parameters.boundary_x);
+            let y = apply_boundary(pos.y, vel.y, parameters.boundary_y);
+            set_position_curr(atom, vec2<f32>(x.x, y.x));
+            set_velocity_curr(atom, vec2<f32>(x.y, y.y));
+        }
     }
-}
\ No newline at end of file
+}
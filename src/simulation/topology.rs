@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+
+/// the most bonds a single atom can take part in
+pub const MAX_BONDS: usize = 4;
+
+/// the most angles a single atom can take part in, as either end or as center
+pub const MAX_ANGLES: usize = 8;
+
+/// the potential of a bond type as a function of the distance `r` of the bonded atoms
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BondPotential {
+    /// `k / 2 * (r - length)^2`
+    Harmonic { k: f32, length: f32 },
+    /// the finitely extensible nonlinear elastic potential
+    /// `-k / 2 * max_length^2 * ln(1 - (r / max_length)^2)`, which keeps bonds shorter than
+    /// `max_length`. Bonds that are stretched further anyway, which only happens if the time
+    /// step is too large, are pulled back as if they had 99% of the maximum length.
+    Fene { k: f32, max_length: f32 },
+    /// not a potential: the bond is held at `length` by the constraint solver instead, see
+    /// [`ConstraintSolver`]. Constrained bonds exert no force of their own.
+    Constraint { length: f32 },
+}
+
+impl BondPotential {
+    pub(crate) fn gpu_id(self) -> u32 {
+        match self {
+            BondPotential::Harmonic { .. } => 0,
+            BondPotential::Fene { .. } => 1,
+            BondPotential::Constraint { .. } => 2,
+        }
+    }
+
+    pub fn k(self) -> f32 {
+        match self {
+            BondPotential::Harmonic { k, .. } | BondPotential::Fene { k, .. } => k,
+            BondPotential::Constraint { .. } => 0.0,
+        }
+    }
+
+    /// the rest length of harmonic bonds, the maximum length of fene bonds or the length of
+    /// constrained bonds
+    pub fn length(self) -> f32 {
+        match self {
+            BondPotential::Harmonic { length, .. } | BondPotential::Constraint { length } => length,
+            BondPotential::Fene { max_length, .. } => max_length,
+        }
+    }
+
+    pub fn is_constraint(self) -> bool {
+        matches!(self, BondPotential::Constraint { .. })
+    }
+}
+
+/// the settings of the shake and rattle constraint solver, which holds the bonds of the
+/// [`BondPotential::Constraint`] types at their lengths after every time step. It corrects the
+/// positions and velocities of the semi-implicit euler integrator, not velocity verlet.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ConstraintSolver {
+    /// the largest accepted relative deviation of a constrained bond from its length, and of
+    /// the relative velocity of its atoms along the bond within a time step
+    pub tolerance: f32,
+    /// the most sweeps over the constraints of a molecule per time step, once for the
+    /// positions and once for the velocities
+    pub max_iterations: u32,
+}
+
+impl Default for ConstraintSolver {
+    fn default() -> Self {
+        Self {
+            tolerance: 1e-5,
+            max_iterations: 100,
+        }
+    }
+}
+
+/// the potential of an angle type as a function of the angle `theta` between the two bonds of
+/// its center atom, in radians
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AnglePotential {
+    /// `k / 2 * (theta - angle)^2`
+    Harmonic { k: f32, angle: f32 },
+    /// `k * (1 - cos(theta - angle))`, which is softer than the harmonic potential for large
+    /// deviations and is the usual bending potential of semi-flexible chains
+    Cosine { k: f32, angle: f32 },
+}
+
+impl AnglePotential {
+    pub(crate) fn gpu_id(self) -> u32 {
+        match self {
+            AnglePotential::Harmonic { .. } => 0,
+            AnglePotential::Cosine { .. } => 1,
+        }
+    }
+
+    pub fn k(self) -> f32 {
+        match self {
+            AnglePotential::Harmonic { k, .. } | AnglePotential::Cosine { k, .. } => k,
+        }
+    }
+
+    /// the rest angle in radians
+    pub fn angle(self) -> f32 {
+        match self {
+            AnglePotential::Harmonic { angle, .. } | AnglePotential::Cosine { angle, .. } => angle,
+        }
+    }
+}
+
+/// a bond between the atoms with the stable ids `a` and `b`. `kind` is an index into
+/// [`super::SimulationParameters::bond_types`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Bond {
+    pub a: u32,
+    pub b: u32,
+    pub kind: u32,
+}
+
+/// the angle at the atom with the stable id `b` between the atoms with the ids `a` and `c`.
+/// `kind` is an index into [`super::SimulationParameters::angle_types`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Angle {
+    pub a: u32,
+    pub b: u32,
+    pub c: u32,
+    pub kind: u32,
+}
+
+/// which atoms are bonded and which angles between them are restrained. Atoms are identified by
+/// their stable ids, so the topology does not change when atoms move between slots. Bonded
+/// atoms do not interact through the pair potentials, the ends of an angle still do.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Topology {
+    pub bonds: Vec<Bond>,
+    pub angles: Vec<Angle>,
+}
+
+impl Topology {
+    /// the amount of bonds of the [`BondPotential::Constraint`] types, each of which takes a
+    /// degree of freedom from the atoms
+    pub fn constraint_count(&self, bond_types: &[BondPotential]) -> usize {
+        self.bonds
+            .iter()
+            .filter(|bond| bond_types[bond.kind as usize].is_constraint())
+            .count()
+    }
+
+    /// drops the bonds and angles of the atoms with the given ids
+    pub fn remove_atoms(&mut self, ids: &HashSet<u32>) {
+        self.bonds
+            .retain(|bond| !ids.contains(&bond.a) && !ids.contains(&bond.b));
+        self.angles.retain(|angle| {
+            [angle.a, angle.b, angle.c]
+                .iter()
+                .all(|id| !ids.contains(id))
+        });
+    }
+}
@@ -0,0 +1,61 @@
+use crate::scenario::Setup;
+use crate::simulation::ewald::Reciprocal;
+use crate::simulation::hashgrid::HashGrid;
+use eyre::{bail, eyre, Result};
+use std::sync::Arc;
+use wgpu::{Device, Queue};
+
+/// the largest accepted relative difference between the energies of the gpu and the cpu. The
+/// gpu sums in single precision and approximates `erfc`, the cpu does neither.
+const ENERGY_TOLERANCE: f64 = 1e-4;
+
+/// like [`ENERGY_TOLERANCE`], for particle mesh ewald, whose error additionally depends on the
+/// size and order of the mesh, as the cpu always sums the reciprocal space directly
+const MESH_ENERGY_TOLERANCE: f64 = 1e-3;
+
+/// evaluates the coulomb energy of the setup's atoms on the gpu and with the cpu reference of
+/// [`crate::simulation::ewald::Ewald::energy`], prints both and fails if they differ by more
+/// than [`ENERGY_TOLERANCE`] or [`MESH_ENERGY_TOLERANCE`]. The energies only agree if no cell
+/// overflows.
+pub async fn electrostatics(device: &Arc<Device>, queue: &Queue, setup: &Setup) -> Result<()> {
+    let ewald = setup
+        .parameters
+        .electrostatics
+        .ok_or_else(|| eyre!("the scenario has no `[electrostatics]` section"))?;
+    let hash_grid = HashGrid::from_slice(
+        device,
+        &setup.atoms,
+        &setup.properties,
+        &setup.topology,
+        &setup.parameters,
+        setup.seed,
+        setup.gpu,
+    );
+
+    let gpu = hash_grid.coulomb_energy(device, queue).await?;
+    let cpu = ewald.energy(
+        &setup.atoms,
+        &setup.properties.charges,
+        setup.parameters.grid_side_length,
+    );
+    let difference = (gpu - cpu).abs() / cpu.abs().max(f64::MIN_POSITIVE);
+    println!(
+        "{} atoms, {} wave vectors: coulomb energy {gpu:.8e} on the gpu, {cpu:.8e} on the cpu, \
+         relative difference {difference:.2e}",
+        setup.atoms.len(),
+        ewald
+            .wave_vectors(
+                setup.parameters.grid_side_length,
+                ewald.reference_k_max(setup.parameters.grid_side_length),
+            )
+            .len(),
+    );
+    let tolerance = match ewald.reciprocal {
+        Reciprocal::Direct { .. } => ENERGY_TOLERANCE,
+        Reciprocal::Mesh { .. } => MESH_ENERGY_TOLERANCE,
+    };
+    if difference > tolerance {
+        bail!("the coulomb energies differ by more than {tolerance:e}");
+    }
+    Ok(())
+}